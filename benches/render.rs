@@ -0,0 +1,45 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use ratatui::{backend::TestBackend, Terminal};
+use todo_rs::{app::App, config::Config, memory_store::MemoryStore, todo::Todo, ui};
+
+const TODO_COUNT: i64 = 100_000;
+
+fn app_with_todos(count: i64) -> App {
+    let mut app = App::from_store(Box::new(MemoryStore::new()), Config::default());
+    for id in 1..=count {
+        let mut todo = Todo::new(id, format!("Todo number {}", id));
+        todo.completed = id % 3 == 0;
+        app.todos.insert(id, todo);
+    }
+    app.refresh_todos();
+    app.selected_todo.select(Some(count as usize / 2));
+    app
+}
+
+fn render(c: &mut Criterion) {
+    let mut app = app_with_todos(TODO_COUNT);
+    let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+
+    c.bench_function("render frame 100k todos", |b| {
+        b.iter(|| {
+            terminal
+                .draw(|frame| ui::render(black_box(&mut app), frame))
+                .unwrap();
+        })
+    });
+}
+
+fn selection(c: &mut Criterion) {
+    let mut app = app_with_todos(TODO_COUNT);
+
+    c.bench_function("selected todo id 100k todos", |b| {
+        b.iter(|| black_box(app.get_selected_todo_id()))
+    });
+
+    c.bench_function("select next todo 100k todos", |b| {
+        b.iter(|| app.select_next_todo())
+    });
+}
+
+criterion_group!(benches, render, selection);
+criterion_main!(benches);
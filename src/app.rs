@@ -1,8 +1,32 @@
-use std::{collections::HashMap, process};
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs, io,
+    path::PathBuf,
+    process,
+    time::Instant,
+};
 
-use ratatui::widgets::{ListState, ScrollbarState};
+use ratatui::{
+    layout::Rect,
+    widgets::{ListState, ScrollbarState},
+};
 
-use crate::{db::TodoDb, todo::Todo};
+use crate::{
+    backup, clipboard,
+    command::{self, Command},
+    config::Config,
+    document, export,
+    fuzzy::{self, FuzzyMatch},
+    history::HistoryEntry,
+    keys::KeyPress,
+    query::Query,
+    search::SearchHit,
+    sort::{Sort, SortMode},
+    store::{self, StoreError, TodoStore},
+    time::{self, SECONDS_PER_DAY},
+    todo::Todo,
+    view::SavedView,
+};
 
 pub enum CurrentScreen {
     Main,
@@ -11,68 +35,1208 @@ pub enum CurrentScreen {
     EditTodo,
     Selection,
     Search,
+    Finder,
+    Help,
+    Command,
+    ConfirmPaste,
+    Trash,
+    // Asks before deleting the selected todo in the trash for good
+    ConfirmPurge,
+    Archive,
+    Details,
+    History,
+    DueDate,
+    EditNotes,
+    Filter,
+    Views,
+    ViewInput,
 }
 
+// What the text typed on `CurrentScreen::ViewInput` is used for
+pub enum ViewInputMode {
+    New,
+    EditQuery,
+    Rename,
+}
+
+// Most results the fuzzy finder lists at once
+const FINDER_LIMIT: usize = 200;
+
+// The sort last picked in the UI, or the configured default
+fn saved_sort(store: &dyn TodoStore, default: Sort) -> Sort {
+    Sort {
+        mode: store
+            .get_setting("sort_mode")
+            .and_then(|mode| SortMode::from_name(&mode))
+            .unwrap_or(default.mode),
+        descending: store
+            .get_setting("sort_descending")
+            .map_or(default.descending, |descending| descending == "1"),
+    }
+}
+
+// Where things were drawn in the last frame, for hit-testing mouse events
+#[derive(Debug, Default)]
+pub struct HitAreas {
+    // Rows of the list that takes clicks on this screen, with the index of
+    // the item drawn on each
+    pub rows: Vec<(Rect, usize)>,
+    // Column range of the checkboxes in the todo list
+    pub checkbox: Option<(u16, u16)>,
+    pub popup: Option<Rect>,
+    // Tabs of the view bar, None being "All"
+    pub view_tabs: Vec<(Rect, Option<usize>)>,
+}
+
+// Tab completion in progress on the command line
+pub struct Completion {
+    // Byte offset in `command_input` of the word being completed
+    pub start: usize,
+    pub candidates: Vec<String>,
+    pub index: usize,
+}
+
+// A change to the list that `.` repeats
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Toggle,
+    Delete,
+    CyclePriority,
+    Add(String),
+    Edit(String),
+    DueDate(String),
+    // Paste before the selection when true
+    Paste(bool),
+}
+
+// Largest count that can be typed before a key, to keep `99999j` cheap
+pub const MAX_COUNT: usize = 9999;
+
+// What is being edited in $EDITOR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExternalEdit {
+    Todo(i64),
+    // The visible todos, one per line
+    List,
+}
+
+// Smallest gap left between pasted todos before the manual order is renumbered
+const MIN_POSITION_GAP: f64 = 1e-6;
+
+// Most commands kept in the command line history
+const COMMAND_HISTORY_LIMIT: usize = 100;
+
 pub struct App {
     pub todos: HashMap<i64, Todo>,
-    pub todos_db: TodoDb,
+    // Ids of the todos shown in the list, in display order
+    pub visible_todos: Vec<i64>,
+    // Ids of archived todos, oldest archive first
+    pub archived_todos: Vec<i64>,
+    pub hide_completed: bool,
+    pub sort: Sort,
+    pub filter: Option<Query>,
+    pub filter_input: String,
+    pub views: Vec<SavedView>,
+    // Number of todos matching each of `views`, and on the tab showing all of
+    // them, counted again only when the todos or views change
+    pub view_counts: Vec<usize>,
+    pub all_count: usize,
+    pub active_view: Option<i64>,
+    pub selected_view: ListState,
+    pub view_input_mode: ViewInputMode,
+    pub store: Box<dyn TodoStore>,
+    pub config: Config,
+    // Keys typed so far of a multi-key binding such as "gg"
+    pub pending_keys: Vec<KeyPress>,
     pub should_quit: bool,
+    pub should_redraw: bool,
+    // Set by Ctrl-Z, the main loop then suspends the process
+    pub should_suspend: bool,
     pub current_screen: CurrentScreen,
     pub todo_input: String,
     pub selected_todo: ListState,
     pub scroll_state: ScrollbarState,
-    // TODO
+    pub selected_archived_todo: ListState,
+    // Todos in the trash, most recently deleted first. Loaded when the trash
+    // is opened, since they aren't in `todos`.
+    pub trash: Vec<Todo>,
+    pub selected_trashed_todo: ListState,
+    // One-off message shown in the footer until the next key press
+    pub notice: Option<String>,
     pub search_query: String,
+    pub search_results: Vec<SearchHit>,
+    pub selected_search_result: ListState,
+    pub finder_query: String,
+    pub finder_results: Vec<FuzzyMatch>,
+    pub selected_finder_result: ListState,
+    // First line of the help overlay that is shown, clamped when rendering
+    pub help_scroll: usize,
+    // Changes to the todo in the history popup, newest first
+    pub history: Vec<HistoryEntry>,
+    pub history_scroll: usize,
+    pub hit_areas: HitAreas,
+    // Time and list index of the last click, to detect double clicks
+    pub last_click: Option<(Instant, usize)>,
+    pub command_input: String,
+    // Previous commands, oldest first, and the one being shown while browsing
+    pub command_history: Vec<String>,
+    pub history_index: Option<usize>,
+    pub completion: Option<Completion>,
+    // Count typed before a key, like the 5 in `5j`
+    pub count: Option<usize>,
+    // Last change and its count, for `.`
+    pub last_change: Option<(Change, usize)>,
+    // Number of todos the delete confirmation is for
+    pub delete_count: usize,
+    // Todos last yanked, the text put on the clipboard for them and what the
+    // clipboard held right after, which differs when OSC 52 isn't supported
+    pub register: Vec<Todo>,
+    pub yanked_text: Option<String>,
+    pub clipboard_at_yank: Option<String>,
+    // Todos from a multi-line paste waiting for confirmation
+    pub pending_paste: Vec<Todo>,
+    pub paste_after_selection: bool,
+    // Set when an edit in $EDITOR is asked for, the main loop then runs it
+    pub external_edit: Option<ExternalEdit>,
+    // Text of an external edit that didn't validate, offered again next time
+    pub rejected_edit: Option<(ExternalEdit, String)>,
+    // Last seen `PRAGMA data_version`, to notice writes by other processes
+    pub data_version: i64,
+    // When the next scheduled backup is due, None when they are turned off
+    pub next_backup_at: Option<i64>,
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self::new(Config::default())
+    }
 }
 
 impl App {
-    pub fn new() -> Self {
-        if let Ok(todos_db) = TodoDb::new("todos.db") {
-            let todos = todos_db.get_all_todos().unwrap_or_default();
-            let todos_len = todos.len();
-            Self {
-                todos,
-                todos_db,
-                should_quit: false,
-                current_screen: CurrentScreen::Main,
-                todo_input: String::new(),
-                selected_todo: ListState::default(),
-                scroll_state: ScrollbarState::new(todos_len).position(0),
-                search_query: String::new(),
+    pub fn new(config: Config) -> Self {
+        match store::open(config.storage_backend, &config.storage_path) {
+            Ok(store) => Self::from_store(store, config),
+            Err(err) => {
+                eprintln!(
+                    "Error: unable to open {}: {}",
+                    config.storage_path.display(),
+                    err
+                );
+                process::exit(1);
             }
-        } else {
-            process::exit(1);
         }
     }
 
-    pub fn add_todo(&mut self) {
-        if let Some(inserted_id) = self.todos_db.add_todo(&self.todo_input) {
-            self.todos.insert(
-                inserted_id,
-                Todo {
-                    id: inserted_id,
-                    label: self.todo_input.clone(),
-                    completed: false,
+    pub fn from_store(mut store: Box<dyn TodoStore>, config: Config) -> Self {
+        if let Some(days) = store
+            .get_setting("auto_archive_days")
+            .and_then(|days| days.parse::<i64>().ok())
+        {
+            let _ = store.archive_completed(time::now() - days * SECONDS_PER_DAY);
+        }
+        if let Some(days) = store.trash_retention_days() {
+            let _ = store.empty_trash(time::now() - days * SECONDS_PER_DAY);
+        }
+        let hide_completed = store.get_setting("hide_completed").as_deref() == Some("1");
+        let sort = saved_sort(store.as_ref(), config.default_sort);
+        let views = store.get_views().unwrap_or_default();
+        let command_history = store
+            .get_setting("command_history")
+            .map(|history| history.lines().map(String::from).collect())
+            .unwrap_or_default();
+
+        let todos = store.get_all_todos().unwrap_or_default();
+        let todos_len = todos.len();
+        let mut app = Self {
+            todos,
+            visible_todos: Vec::with_capacity(todos_len),
+            archived_todos: Vec::new(),
+            hide_completed,
+            sort,
+            filter: None,
+            filter_input: String::new(),
+            views,
+            view_counts: vec![],
+            all_count: 0,
+            active_view: None,
+            selected_view: ListState::default(),
+            view_input_mode: ViewInputMode::New,
+            store,
+            config,
+            pending_keys: vec![],
+            should_quit: false,
+            should_redraw: true,
+            should_suspend: false,
+            current_screen: CurrentScreen::Main,
+            todo_input: String::new(),
+            selected_todo: ListState::default(),
+            scroll_state: ScrollbarState::new(todos_len).position(0),
+            selected_archived_todo: ListState::default(),
+            trash: vec![],
+            selected_trashed_todo: ListState::default(),
+            notice: None,
+            search_query: String::new(),
+            search_results: vec![],
+            selected_search_result: ListState::default(),
+            finder_query: String::new(),
+            finder_results: vec![],
+            selected_finder_result: ListState::default(),
+            help_scroll: 0,
+            history: vec![],
+            history_scroll: 0,
+            hit_areas: HitAreas::default(),
+            last_click: None,
+            command_input: String::new(),
+            command_history,
+            history_index: None,
+            completion: None,
+            count: None,
+            last_change: None,
+            delete_count: 1,
+            register: vec![],
+            yanked_text: None,
+            clipboard_at_yank: None,
+            pending_paste: vec![],
+            paste_after_selection: false,
+            external_edit: None,
+            rejected_edit: None,
+            data_version: 0,
+            next_backup_at: None,
+        };
+        app.data_version = app.store.data_version().unwrap_or_default();
+        app.next_backup_at = match (app.config.backup_interval, app.store.backup_dir()) {
+            (Some(interval), Some(dir)) => {
+                Some(backup::last_backup_at(dir).map_or(0, |at| at + interval))
+            }
+            _ => None,
+        };
+        app.backup_if_due();
+        app.refresh_todos();
+        app
+    }
+
+    /// Recomputes everything derived from `todos` and `views`: the view
+    /// counts, the archive and the display order. Needed after every change to
+    /// the todos or views; a new sort or filter only needs
+    /// `refresh_visible_todos`.
+    pub fn refresh_todos(&mut self) {
+        self.refresh_view_counts();
+        self.archived_todos.clear();
+        self.archived_todos.extend(
+            self.todos
+                .values()
+                .filter(|todo| todo.is_archived())
+                .map(|todo| todo.id),
+        );
+        self.archived_todos
+            .sort_unstable_by_key(|id| (self.todos[id].archived_at, *id));
+        if let Some(selected) = self.selected_archived_todo.selected() {
+            self.selected_archived_todo
+                .select(if self.archived_todos.is_empty() {
+                    None
+                } else {
+                    Some(selected.min(self.archived_todos.len() - 1))
+                });
+        }
+        self.refresh_visible_todos();
+    }
+
+    fn refresh_view_counts(&mut self) {
+        let now = time::now();
+        self.all_count = self
+            .todos
+            .values()
+            .filter(|todo| !todo.is_archived())
+            .count();
+        self.view_counts = self
+            .views
+            .iter()
+            .map(|view| match view.parse_query(now) {
+                // Counted like the view shows them, so a view of the archive
+                // counts archived todos
+                Ok(query) => self
+                    .todos
+                    .values()
+                    .filter(|todo| query.includes_archived() || !todo.is_archived())
+                    .filter(|todo| query.matches(todo, now))
+                    .count(),
+                Err(_) => 0,
+            })
+            .collect();
+    }
+
+    /// Rebuilds the display order from `todos`. Only needed when the todos,
+    /// the filter or the sort change, not on every frame. The selection
+    /// follows the selected todo rather than staying at the same index.
+    pub fn refresh_visible_todos(&mut self) {
+        let selected_id = self.get_selected_todo_id();
+        let (hide_completed, filter, now) = (self.hide_completed, &self.filter, time::now());
+        self.visible_todos.clear();
+        self.visible_todos.extend(
+            self.todos
+                .values()
+                .filter(|todo| !(todo.is_archived() || (hide_completed && todo.completed)))
+                .filter(|todo| {
+                    filter
+                        .as_ref()
+                        .is_none_or(|filter| filter.matches(todo, now))
+                })
+                .map(|todo| todo.id),
+        );
+        let (todos, sort) = (&self.todos, self.sort);
+        self.visible_todos
+            .sort_unstable_by(|a, b| sort.compare(&todos[a], &todos[b]));
+        if !self.select_todo_by_id(selected_id) {
+            self.clamp_selection();
+        }
+    }
+
+    // Reloads todos and views when another process, like the CLI or a second
+    // window, changed the store. Returns whether anything was reloaded.
+    pub fn reload_external_changes(&mut self) -> bool {
+        let version = match self.store.data_version() {
+            Ok(version) if version != self.data_version => version,
+            _ => return false,
+        };
+        self.data_version = version;
+        let selected_id = self.get_selected_todo_id();
+        let (added, changed, removed) = match self.reload_todos() {
+            Some(counts) => counts,
+            None => return false,
+        };
+
+        // Popups for a todo that is gone would act on whatever is selected now
+        let selection_lost = selected_id.is_some() && selected_id != self.get_selected_todo_id();
+        if selection_lost
+            && matches!(
+                self.current_screen,
+                CurrentScreen::EditTodo
+                    | CurrentScreen::DeleteTodo
+                    | CurrentScreen::Details
+                    | CurrentScreen::DueDate
+                    | CurrentScreen::EditNotes
+            )
+        {
+            self.todo_input.clear();
+            self.current_screen = CurrentScreen::Main;
+            self.notice = Some(String::from("The todo was removed elsewhere"));
+            return true;
+        }
+        if added + changed + removed > 0 {
+            self.notice = Some(format!(
+                "Reloaded changes from elsewhere: {} added, {} changed, {} removed",
+                added, changed, removed
+            ));
+        }
+        true
+    }
+
+    // Takes a scheduled backup when one is due and drops the oldest ones
+    // beyond the limit. Returns whether it failed and set a notice.
+    pub fn backup_if_due(&mut self) -> bool {
+        let (due_at, interval) = match (self.next_backup_at, self.config.backup_interval) {
+            (Some(due_at), Some(interval)) => (due_at, interval),
+            _ => return false,
+        };
+        let now = time::now();
+        if now < due_at {
+            return false;
+        }
+        self.next_backup_at = Some(now + interval);
+        let result = self
+            .store
+            .backup(None)
+            .map_err(|err| err.to_string())
+            .and_then(|_| match self.store.backup_dir() {
+                Some(dir) => {
+                    backup::rotate(dir, self.config.backup_keep).map_err(|err| err.to_string())
+                }
+                None => Ok(()),
+            });
+        match result {
+            Ok(()) => false,
+            Err(err) => {
+                self.notice = Some(format!("Backup failed: {}", err));
+                true
+            }
+        }
+    }
+
+    // Replaces the todos and views with the ones in the store, keeping the
+    // selection. Returns how many todos were added, changed and removed.
+    fn reload_todos(&mut self) -> Option<(usize, usize, usize)> {
+        let todos = self.store.get_all_todos().ok()?;
+        let (mut added, mut changed) = (0, 0);
+        for (id, todo) in &todos {
+            match self.todos.get(id) {
+                None => added += 1,
+                Some(old) if old.version != todo.version => changed += 1,
+                Some(_) => {}
+            }
+        }
+        let removed = self
+            .todos
+            .keys()
+            .filter(|id| !todos.contains_key(id))
+            .count();
+
+        self.todos = todos;
+        if let Ok(views) = self.store.get_views() {
+            if !views.iter().any(|view| Some(view.id) == self.active_view) {
+                self.active_view = None;
+            }
+            self.views = views;
+        }
+        self.refresh_todos();
+        if matches!(
+            self.current_screen,
+            CurrentScreen::Trash | CurrentScreen::ConfirmPurge
+        ) {
+            self.load_trash();
+        }
+        Some((added, changed, removed))
+    }
+
+    // Runs the writes in `write` as one transaction. When one fails, none of
+    // them are kept: the todos are reloaded from the store and a notice
+    // says what happened.
+    fn write<T>(
+        &mut self,
+        write: impl FnOnce(&mut Self) -> Result<T, StoreError>,
+    ) -> Result<T, StoreError> {
+        let result = self
+            .store
+            .begin()
+            .and_then(|()| write(self))
+            .and_then(|value| {
+                self.store.commit()?;
+                Ok(value)
+            });
+        if let Err(err) = &result {
+            self.store.rollback();
+            self.notice = Some(match err {
+                StoreError::Conflict(id) => format!(
+                    "Todo #{} was changed elsewhere, reloaded it instead of saving",
+                    id
+                ),
+                err => format!("Unable to save: {}", err),
+            });
+            if let Ok(version) = self.store.data_version() {
+                self.data_version = version;
+            }
+            self.reload_todos();
+        }
+        result
+    }
+
+    pub fn toggle_hide_completed(&mut self) {
+        self.hide_completed = !self.hide_completed;
+        let value = if self.hide_completed { "1" } else { "0" };
+        let _ = self.write(|app| app.store.set_setting("hide_completed", value));
+        self.refresh_visible_todos();
+    }
+
+    // Parses `todo_input` as the filter for the main list. Returns false and
+    // sets a notice when the query is invalid.
+    pub fn apply_filter(&mut self) -> bool {
+        match Query::parse(&self.todo_input, time::now()) {
+            Ok(query) => {
+                self.filter = if query.is_empty() { None } else { Some(query) };
+                self.filter_input = self.todo_input.trim().to_string();
+                self.active_view = None;
+                self.todo_input.clear();
+                self.refresh_visible_todos();
+                true
+            }
+            Err(err) => {
+                self.notice = Some(format!("Invalid filter: {}", err));
+                false
+            }
+        }
+    }
+
+    // Switches the main list to the filter and sort of `views[index]`
+    pub fn apply_view(&mut self, index: usize) {
+        let view = match self.views.get(index) {
+            Some(view) => view,
+            None => return,
+        };
+        match view.parse_query(time::now()) {
+            Ok(query) => {
+                self.filter = if query.is_empty() { None } else { Some(query) };
+                self.filter_input = view.query.clone();
+                self.sort = view.sort;
+                self.active_view = Some(view.id);
+                self.refresh_visible_todos();
+            }
+            Err(err) => {
+                self.notice = Some(format!("Invalid query in view {}: {}", view.name, err));
+            }
+        }
+    }
+
+    // Goes back to the unfiltered list with the saved sort
+    pub fn clear_view(&mut self) {
+        self.filter = None;
+        self.filter_input.clear();
+        self.sort = saved_sort(self.store.as_ref(), self.config.default_sort);
+        self.active_view = None;
+        self.refresh_visible_todos();
+    }
+
+    pub fn select_next_view(&mut self) {
+        if self.views.is_empty() {
+            return;
+        }
+        let i = match self.selected_view.selected() {
+            Some(i) if i + 1 < self.views.len() => i + 1,
+            _ => 0,
+        };
+        self.selected_view.select(Some(i));
+    }
+
+    pub fn select_prev_view(&mut self) {
+        if self.views.is_empty() {
+            return;
+        }
+        let i = match self.selected_view.selected() {
+            Some(0) | None => self.views.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.selected_view.select(Some(i));
+    }
+
+    // Uses `todo_input` to create, edit or rename a view depending on
+    // `view_input_mode`. Returns false and sets a notice when it is invalid.
+    pub fn submit_view_input(&mut self) -> bool {
+        let input = self.todo_input.trim().to_string();
+        let selected = self.selected_view.selected();
+        let result = match self.view_input_mode {
+            ViewInputMode::New => {
+                if input.is_empty() {
+                    return false;
+                }
+                self.store
+                    .add_view(&input, &self.filter_input, self.sort)
+                    .map(|view| {
+                        self.views.push(view);
+                        self.selected_view.select(Some(self.views.len() - 1));
+                    })
+                    .map_err(|err| err.to_string())
+            }
+            ViewInputMode::EditQuery => match Query::parse(&input, time::now()) {
+                Ok(_) => match selected.and_then(|index| self.views.get_mut(index)) {
+                    Some(view) => {
+                        view.query = input;
+                        view.sort = self.sort;
+                        self.store.update_view(view).map_err(|err| err.to_string())
+                    }
+                    None => Ok(()),
                 },
-            );
+                Err(err) => Err(err.to_string()),
+            },
+            ViewInputMode::Rename => match selected.and_then(|index| self.views.get_mut(index)) {
+                Some(view) if !input.is_empty() => {
+                    view.name = input;
+                    self.store.update_view(view).map_err(|err| err.to_string())
+                }
+                _ => return false,
+            },
+        };
+
+        match result {
+            Ok(()) => {
+                self.todo_input.clear();
+                self.refresh_todos();
+                true
+            }
+            Err(err) => {
+                self.notice = Some(format!("Unable to save view: {}", err));
+                false
+            }
+        }
+    }
+
+    pub fn delete_selected_view(&mut self) {
+        if let Some(index) = self.selected_view.selected() {
+            if index < self.views.len() {
+                let id = self.views[index].id;
+                if self.write(|app| app.store.delete_view(id)).is_err() {
+                    return;
+                }
+                let view = self.views.remove(index);
+                if self.active_view == Some(view.id) {
+                    self.clear_view();
+                }
+                self.selected_view.select(if self.views.is_empty() {
+                    None
+                } else {
+                    Some(index.min(self.views.len() - 1))
+                });
+                self.refresh_todos();
+            }
+        }
+    }
+
+    pub fn update_search_results(&mut self) {
+        self.search_results = self
+            .store
+            .search(&self.search_query, 200)
+            .unwrap_or_default();
+        self.selected_search_result
+            .select(if self.search_results.is_empty() {
+                None
+            } else {
+                Some(0)
+            });
+    }
+
+    pub fn select_next_search_result(&mut self) {
+        if self.search_results.is_empty() {
+            return;
+        }
+        let i = match self.selected_search_result.selected() {
+            Some(i) if i + 1 < self.search_results.len() => i + 1,
+            _ => 0,
+        };
+        self.selected_search_result.select(Some(i));
+    }
+
+    pub fn select_prev_search_result(&mut self) {
+        if self.search_results.is_empty() {
+            return;
+        }
+        let i = match self.selected_search_result.selected() {
+            Some(0) | None => self.search_results.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.selected_search_result.select(Some(i));
+    }
+
+    // Selects the todo behind the chosen search result, clearing whatever
+    // hides it from the main list. Archived todos are shown in the archive.
+    pub fn jump_to_search_result(&mut self) {
+        let id = match self
+            .selected_search_result
+            .selected()
+            .and_then(|index| self.search_results.get(index))
+        {
+            Some(hit) => hit.id,
+            None => return,
+        };
+        self.jump_to_todo(id);
+    }
+
+    // Scores every todo label, archived ones included, against `finder_query`.
+    // With an empty query the most recently updated todos are listed.
+    pub fn update_finder_results(&mut self) {
+        let mut results: Vec<FuzzyMatch> = self
+            .todos
+            .values()
+            .filter_map(|todo| {
+                fuzzy::score(&self.finder_query, &todo.label).map(|(score, positions)| FuzzyMatch {
+                    id: todo.id,
+                    score,
+                    positions,
+                })
+            })
+            .collect();
+        let todos = &self.todos;
+        results.sort_unstable_by_key(|result| {
+            let todo = &todos[&result.id];
+            (
+                std::cmp::Reverse(result.score),
+                todo.label.len(),
+                std::cmp::Reverse(todo.updated_at),
+                todo.id,
+            )
+        });
+        results.truncate(FINDER_LIMIT);
+        self.finder_results = results;
+        self.selected_finder_result
+            .select(if self.finder_results.is_empty() {
+                None
+            } else {
+                Some(0)
+            });
+    }
+
+    pub fn select_next_finder_result(&mut self) {
+        if self.finder_results.is_empty() {
+            return;
+        }
+        let i = match self.selected_finder_result.selected() {
+            Some(i) if i + 1 < self.finder_results.len() => i + 1,
+            _ => 0,
+        };
+        self.selected_finder_result.select(Some(i));
+    }
+
+    pub fn select_prev_finder_result(&mut self) {
+        if self.finder_results.is_empty() {
+            return;
+        }
+        let i = match self.selected_finder_result.selected() {
+            Some(0) | None => self.finder_results.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.selected_finder_result.select(Some(i));
+    }
+
+    pub fn jump_to_finder_result(&mut self) {
+        let id = match self
+            .selected_finder_result
+            .selected()
+            .and_then(|index| self.finder_results.get(index))
+        {
+            Some(result) => result.id,
+            None => return,
+        };
+        self.jump_to_todo(id);
+    }
+
+    pub fn jump_to_todo(&mut self, id: i64) {
+        let todo = match self.todos.get(&id) {
+            Some(todo) => todo,
+            None => return,
+        };
+        if todo.is_archived() {
+            if let Some(index) = self
+                .archived_todos
+                .iter()
+                .position(|archived| *archived == id)
+            {
+                self.selected_archived_todo.select(Some(index));
+            }
+            self.current_screen = CurrentScreen::Archive;
+            return;
+        }
+
+        if !self.visible_todos.contains(&id) {
+            if self.hide_completed && todo.completed {
+                self.hide_completed = false;
+            }
+            if self.filter.is_some() {
+                self.clear_view();
+            } else {
+                self.refresh_todos();
+            }
+        }
+        self.select_todo_by_id(Some(id));
+        self.current_screen = CurrentScreen::Main;
+    }
+
+    pub fn update_selected_notes(&mut self) {
+        if let Some(id) = self.get_selected_todo_id() {
+            let notes = self.todo_input.trim().to_string();
+            self.todo_input.clear();
+            let _ = self.write(|app| match app.todos.get_mut(&id) {
+                Some(todo) => {
+                    todo.notes = notes;
+                    app.store.update_todo(todo)
+                }
+                None => Ok(()),
+            });
+            self.refresh_todos();
+        };
+    }
+
+    pub fn cycle_sort_mode(&mut self) {
+        self.set_sort(Sort {
+            mode: self.sort.mode.next(),
+            ..self.sort
+        });
+    }
+
+    pub fn toggle_sort_direction(&mut self) {
+        self.set_sort(Sort {
+            descending: !self.sort.descending,
+            ..self.sort
+        });
+    }
+
+    // Switches to `sort` and remembers it for the next start
+    pub fn set_sort(&mut self, sort: Sort) {
+        self.sort = sort;
+        let _ = self.write(|app| {
+            app.store.set_setting("sort_mode", sort.mode.name())?;
+            app.store
+                .set_setting("sort_descending", if sort.descending { "1" } else { "0" })
+        });
+        self.refresh_visible_todos();
+    }
+
+    pub fn open_command_line(&mut self) {
+        self.command_input.clear();
+        self.history_index = None;
+        self.completion = None;
+        self.current_screen = CurrentScreen::Command;
+    }
+
+    // Completes the word before the cursor. Pressing tab again cycles through
+    // the candidates when there is more than one.
+    pub fn complete_command(&mut self) {
+        if let Some(completion) = &mut self.completion {
+            completion.index = (completion.index + 1) % completion.candidates.len();
+            self.command_input.truncate(completion.start);
+            self.command_input
+                .push_str(&completion.candidates[completion.index]);
+            return;
+        }
+
+        let view_names: Vec<String> = self.views.iter().map(|view| view.name.clone()).collect();
+        let (start, candidates) = command::completions(&self.command_input, &view_names);
+        match candidates.len() {
+            0 => {}
+            1 => {
+                self.command_input.truncate(start);
+                self.command_input.push_str(&candidates[0]);
+                if !candidates[0].ends_with([':', '<']) {
+                    self.command_input.push(' ');
+                }
+            }
+            _ => {
+                self.notice = Some(candidates.join("  "));
+                self.command_input.truncate(start);
+                self.command_input.push_str(&candidates[0]);
+                self.completion = Some(Completion {
+                    start,
+                    candidates,
+                    index: 0,
+                });
+            }
+        }
+    }
+
+    // Steps back through the command history, or forward when `older` is false
+    pub fn browse_command_history(&mut self, older: bool) {
+        if self.command_history.is_empty() {
+            return;
+        }
+        let last = self.command_history.len() - 1;
+        self.history_index = match (self.history_index, older) {
+            (None, true) => Some(last),
+            (None, false) => None,
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) if index < last => Some(index + 1),
+            (Some(_), false) => None,
+        };
+        self.command_input = self
+            .history_index
+            .map(|index| self.command_history[index].clone())
+            .unwrap_or_default();
+        self.completion = None;
+    }
+
+    // Runs `command_input`, remembering it in the history. Errors and results
+    // are reported through the notice.
+    pub fn run_command(&mut self) {
+        let input = self.command_input.trim().to_string();
+        self.command_input.clear();
+        self.completion = None;
+        self.current_screen = CurrentScreen::Main;
+        if input.is_empty() {
+            return;
+        }
+        if self.command_history.last() != Some(&input) {
+            self.command_history.push(input.clone());
+            let skip = self
+                .command_history
+                .len()
+                .saturating_sub(COMMAND_HISTORY_LIMIT);
+            self.command_history.drain(..skip);
+            let history = self.command_history.join("\n");
+            let _ = self.write(|app| app.store.set_setting("command_history", &history));
+        }
+
+        let command = match Command::parse(&input) {
+            Ok(command) => command,
+            Err(err) => {
+                self.notice = Some(err);
+                return;
+            }
+        };
+        match command {
+            Command::Add(label) => {
+                self.todo_input = label;
+                self.add_todo();
+            }
+            Command::Sort(mode, descending) => self.set_sort(Sort {
+                mode,
+                descending: descending.unwrap_or(self.sort.descending),
+            }),
+            Command::Filter(query) => {
+                self.todo_input = query;
+                if !self.apply_filter() {
+                    self.todo_input.clear();
+                }
+            }
+            Command::View(name) => {
+                match self
+                    .views
+                    .iter()
+                    .position(|view| view.name.eq_ignore_ascii_case(&name))
+                {
+                    Some(index) => self.apply_view(index),
+                    None => self.notice = Some(format!("No view named '{}'", name)),
+                }
+            }
+            Command::Export(format, path) => {
+                let path = match (path.strip_prefix("~"), env::var_os("HOME")) {
+                    (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+                    _ => path,
+                };
+                let todos = self.visible_todos.iter().map(|id| &self.todos[id]);
+                self.notice = Some(match fs::write(&path, format.export(todos)) {
+                    Ok(()) => format!(
+                        "Exported {} todo(s) to {}",
+                        self.visible_todos.len(),
+                        path.display()
+                    ),
+                    Err(err) => format!("Unable to export to {}: {}", path.display(), err),
+                });
+            }
+            Command::Purge(days) => {
+                let before = time::now().saturating_sub(days.saturating_mul(SECONDS_PER_DAY));
+                if let Ok(purged) = self.write(|app| app.store.purge_archived(before)) {
+                    self.todos = self.store.get_all_todos().unwrap_or_default();
+                    self.refresh_todos();
+                    self.notice = Some(format!("Purged {} archived todo(s)", purged));
+                }
+            }
+            Command::Goto(id) => {
+                if self.todos.contains_key(&id) {
+                    self.jump_to_todo(id);
+                } else {
+                    self.notice = Some(format!("No todo with id {}", id));
+                }
+            }
+            Command::Help => {
+                self.help_scroll = 0;
+                self.current_screen = CurrentScreen::Help;
+            }
+            Command::Quit => self.should_quit = true,
+        }
+    }
+
+    fn select_todo_by_id(&mut self, id: Option<i64>) -> bool {
+        match id.and_then(|id| self.visible_todos.iter().position(|visible| *visible == id)) {
+            Some(index) => {
+                self.select(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn archive_completed_todos(&mut self) {
+        if self
+            .write(|app| app.store.archive_completed(time::now()))
+            .is_ok()
+        {
+            self.todos = self.store.get_all_todos().unwrap_or_default();
+            self.refresh_todos();
+        }
+    }
+
+    pub fn open_history(&mut self) {
+        let id = match self.get_selected_todo_id() {
+            Some(id) => id,
+            None => return,
+        };
+        match self.store.get_history(Some(id)) {
+            Ok(history) => {
+                self.history = history;
+                self.history_scroll = 0;
+                self.current_screen = CurrentScreen::History;
+            }
+            Err(err) => self.notice = Some(format!("Unable to load the history: {}", err)),
+        }
+    }
+
+    pub fn get_selected_archived_todo(&self) -> Option<&Todo> {
+        let selected_index = self.selected_archived_todo.selected()?;
+        self.archived_todos
+            .get(selected_index)
+            .and_then(|id| self.todos.get(id))
+    }
+
+    pub fn restore_selected_archived_todo(&mut self) {
+        let id = match self.get_selected_archived_todo() {
+            Some(todo) => todo.id,
+            None => return,
+        };
+        let _ = self.write(|app| match app.todos.get_mut(&id) {
+            Some(todo) => app.store.set_archived(todo, false),
+            None => Ok(()),
+        });
+        self.refresh_todos();
+    }
+
+    pub fn open_trash(&mut self) {
+        self.load_trash();
+        if self.selected_trashed_todo.selected().is_none() && !self.trash.is_empty() {
+            self.selected_trashed_todo.select(Some(0));
+        }
+        self.current_screen = CurrentScreen::Trash;
+    }
+
+    fn load_trash(&mut self) {
+        self.trash = self.store.get_trash().unwrap_or_default();
+        if let Some(selected) = self.selected_trashed_todo.selected() {
+            self.selected_trashed_todo.select(if self.trash.is_empty() {
+                None
+            } else {
+                Some(selected.min(self.trash.len() - 1))
+            });
+        }
+    }
+
+    pub fn get_selected_trashed_todo(&self) -> Option<&Todo> {
+        self.trash.get(self.selected_trashed_todo.selected()?)
+    }
+
+    // Takes the selected todo out of the trash, back to where it was
+    pub fn restore_selected_trashed_todo(&mut self) {
+        let mut todo = match self.get_selected_trashed_todo() {
+            Some(todo) => todo.clone(),
+            None => return,
+        };
+        if self
+            .write(|app| app.store.set_deleted(&mut todo, false))
+            .is_ok()
+        {
+            self.notice = Some(format!("Restored \"{}\"", todo.label));
+            self.todos.insert(todo.id, todo);
+            self.refresh_todos();
+            self.load_trash();
+        }
+    }
+
+    pub fn delete_selected_trashed_todo(&mut self) {
+        let todo = match self.get_selected_trashed_todo() {
+            Some(todo) => todo.clone(),
+            None => return,
+        };
+        if self.write(|app| app.store.delete_todo(&todo)).is_ok() {
+            self.notice = Some(format!("Deleted \"{}\" for good", todo.label));
+            self.load_trash();
+        }
+    }
+
+    pub fn select_next_trashed_todo(&mut self) {
+        if self.trash.is_empty() {
+            return;
+        }
+        let i = match self.selected_trashed_todo.selected() {
+            Some(i) if i + 1 < self.trash.len() => i + 1,
+            _ => 0,
+        };
+        self.selected_trashed_todo.select(Some(i));
+    }
+
+    pub fn select_prev_trashed_todo(&mut self) {
+        if self.trash.is_empty() {
+            return;
+        }
+        let i = match self.selected_trashed_todo.selected() {
+            Some(0) | None => self.trash.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.selected_trashed_todo.select(Some(i));
+    }
+
+    pub fn select_next_archived_todo(&mut self) {
+        if self.archived_todos.is_empty() {
+            return;
+        }
+        let i = match self.selected_archived_todo.selected() {
+            Some(i) if i + 1 < self.archived_todos.len() => i + 1,
+            _ => 0,
+        };
+        self.selected_archived_todo.select(Some(i));
+    }
+
+    pub fn select_prev_archived_todo(&mut self) {
+        if self.archived_todos.is_empty() {
+            return;
+        }
+        let i = match self.selected_archived_todo.selected() {
+            Some(0) | None => self.archived_todos.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.selected_archived_todo.select(Some(i));
+    }
+
+    fn clamp_selection(&mut self) {
+        if let Some(selected) = self.selected_todo.selected() {
+            if self.visible_todos.is_empty() {
+                self.selected_todo.select(None);
+            } else if selected >= self.visible_todos.len() {
+                self.selected_todo
+                    .select(Some(self.visible_todos.len() - 1));
+            }
+        }
+    }
+
+    pub fn add_todo(&mut self) {
+        let label = self.todo_input.clone();
+        if let Ok(todo) = self.write(|app| app.store.add_todo(&label)) {
+            self.todos.insert(todo.id, todo);
+            self.refresh_todos();
             self.todo_input.clear();
         }
     }
 
+    fn select(&mut self, index: usize) {
+        self.selected_todo.select(Some(index));
+        self.scroll_state = self.scroll_state.position(index);
+    }
+
+    pub fn select_todo_at(&mut self, index: usize) {
+        if index < self.visible_todos.len() {
+            self.select(index);
+        }
+    }
+
     pub fn go_to_top(&mut self) {
-        self.selected_todo.select(Some(0));
-        self.scroll_state.first();
+        if self.visible_todos.is_empty() {
+            return;
+        }
+        self.select(0);
     }
 
     pub fn go_to_bottom(&mut self) {
-        self.selected_todo.select(Some(self.todos.len() - 1));
-        self.scroll_state.last();
+        if self.visible_todos.is_empty() {
+            return;
+        }
+        self.select(self.visible_todos.len() - 1);
+    }
+
+    // Moves the selection by `offset` rows, stopping at either end
+    pub fn move_selection(&mut self, offset: isize) {
+        if self.visible_todos.is_empty() {
+            return;
+        }
+        let selected = self.selected_todo.selected().unwrap_or(0);
+        let last = self.visible_todos.len() - 1;
+        self.select(selected.saturating_add_signed(offset).min(last));
+    }
+
+    // First and last rows drawn in the list, as of the last render
+    pub fn rows_on_screen(&self) -> Option<(usize, usize)> {
+        let first = self.hit_areas.rows.first()?.1;
+        let last = self.hit_areas.rows.last()?.1;
+        Some((first, last))
     }
 
     pub fn select_next_todo(&mut self) {
+        if self.visible_todos.is_empty() {
+            return;
+        }
         let i = match self.selected_todo.selected() {
             Some(i) => {
-                if i >= self.todos.len() - 1 {
+                if i >= self.visible_todos.len() - 1 {
                     0
                 } else {
                     i + 1
@@ -80,81 +1244,440 @@ impl App {
             }
             None => 0,
         };
-        self.selected_todo.select(Some(i));
-        if i == 0 {
-            self.scroll_state.first();
-            return;
-        }
-        self.scroll_state.next();
+        self.select(i);
     }
 
     pub fn select_prev_todo(&mut self) {
+        if self.visible_todos.is_empty() {
+            return;
+        }
         let i = match self.selected_todo.selected() {
             Some(i) => {
                 if i == 0 {
-                    self.todos.len() - 1
+                    self.visible_todos.len() - 1
                 } else {
                     i - 1
                 }
             }
             None => 0,
         };
-        self.selected_todo.select(Some(i));
-        if i == self.todos.len() - 1 {
-            self.scroll_state.last();
-            return;
+        self.select(i);
+    }
+
+    // The text to open in $EDITOR for `edit`. An edit that was rejected is
+    // picked up where it was left.
+    pub fn external_document(&mut self, edit: ExternalEdit) -> Option<String> {
+        if let Some((rejected, text)) = self.rejected_edit.take() {
+            if rejected == edit {
+                return Some(text);
+            }
+        }
+        match edit {
+            ExternalEdit::Todo(id) => self
+                .todos
+                .get(&id)
+                .map(|todo| document::todo_document(todo, &self.config.datetime_format)),
+            ExternalEdit::List => Some(document::list_document(
+                self.visible_todos.iter().map(|id| &self.todos[id]),
+            )),
         }
-        self.scroll_state.prev();
     }
 
-    pub fn toggle_selected_todo(&mut self) {
-        if let Some(id) = self.get_selected_todo_id() {
-            if let Some(todo) = self.todos.get_mut(&id) {
-                todo.toggle();
-                self.todos_db.toggle_todo(todo.id, todo.completed);
+    // Applies the text saved in $EDITOR over `original`. Invalid text is kept
+    // for the next edit and the problem is shown as a notice.
+    pub fn apply_external_edit(
+        &mut self,
+        edit: ExternalEdit,
+        original: &str,
+        text: io::Result<String>,
+    ) {
+        let text = match text {
+            Ok(text) if text == original => {
+                self.notice = Some(String::from("No changes"));
+                return;
             }
+            Ok(text) => text,
+            Err(err) => {
+                self.notice = Some(format!("Editor failed: {}", err));
+                return;
+            }
+        };
+        let result = match edit {
+            ExternalEdit::Todo(id) => self.apply_todo_document(id, &text),
+            ExternalEdit::List => self.apply_list_document(&text),
         };
+        self.notice = Some(match result {
+            Ok(summary) => summary,
+            Err(err) => {
+                self.rejected_edit = Some((edit, text));
+                format!("Not saved, {} (edit again to fix it)", err)
+            }
+        });
     }
 
-    pub fn get_selected_todo(&self) -> Option<&Todo> {
-        if let Some(id) = self.get_selected_todo_id() {
-            return Some(self.todos.get(&id).unwrap());
+    fn apply_todo_document(&mut self, id: i64, text: &str) -> Result<String, String> {
+        let fields = document::parse_todo_document(text, id, time::now())?;
+        if !self.todos.contains_key(&id) {
+            return Err(String::from("the todo no longer exists"));
+        }
+        self.write(|app| {
+            let todo = match app.todos.get_mut(&id) {
+                Some(todo) => todo,
+                None => return Ok(()),
+            };
+            if todo.completed != fields.completed {
+                todo.toggle();
+                app.store.toggle_todo(todo)?;
+            }
+            todo.label = fields.label;
+            todo.priority = fields.priority;
+            todo.due_at = fields.due_at;
+            todo.notes = fields.notes;
+            app.store.update_todo(todo)
+        })
+        .map_err(|err| err.to_string())?;
+        self.refresh_todos();
+        Ok(String::from("Todo saved"))
+    }
+
+    // Adds, changes and deletes todos so the visible ones match the list
+    fn apply_list_document(&mut self, text: &str) -> Result<String, String> {
+        let lines = document::parse_list_document(text)?;
+        if let Some(id) = lines
+            .iter()
+            .filter_map(|line| line.id)
+            .find(|id| !self.visible_todos.contains(id))
+        {
+            return Err(format!("todo {} isn't in the list", id));
+        }
+
+        let kept: HashSet<i64> = lines.iter().filter_map(|line| line.id).collect();
+        let removed: Vec<i64> = self
+            .visible_todos
+            .iter()
+            .copied()
+            .filter(|id| !kept.contains(id))
+            .collect();
+        let (added, changed) = self
+            .write(|app| {
+                for id in &removed {
+                    if let Some(mut todo) = app.todos.remove(id) {
+                        app.store.set_deleted(&mut todo, true)?;
+                    }
+                }
+
+                let (mut added, mut changed) = (0, 0);
+                for line in lines {
+                    let todo = match line.id {
+                        Some(id) => match app.todos.get_mut(&id) {
+                            Some(todo) => todo,
+                            None => continue,
+                        },
+                        None => {
+                            let mut new = Todo::new(0, line.label.clone());
+                            new.completed = line.completed;
+                            let todo = app.store.add_todo_with(&new, None)?;
+                            added += 1;
+                            app.todos.entry(todo.id).or_insert(todo)
+                        }
+                    };
+                    if todo.label == line.label && todo.completed == line.completed {
+                        continue;
+                    }
+                    if line.id.is_some() {
+                        changed += 1;
+                    }
+                    if todo.completed != line.completed {
+                        todo.toggle();
+                        app.store.toggle_todo(todo)?;
+                    }
+                    if todo.label != line.label {
+                        todo.label = line.label;
+                        app.store.update_todo(todo)?;
+                    }
+                }
+                Ok((added, changed))
+            })
+            .map_err(|err| err.to_string())?;
+        self.refresh_todos();
+        Ok(format!(
+            "{} added, {} changed, {} deleted",
+            added,
+            changed,
+            removed.len()
+        ))
+    }
+
+    // Ids of the selected todo and the ones below it, `count` in all
+    fn selected_todo_ids(&self, count: usize) -> Vec<i64> {
+        match self.selected_todo.selected() {
+            Some(selected) => self
+                .visible_todos
+                .iter()
+                .skip(selected)
+                .take(count)
+                .copied()
+                .collect(),
+            None => vec![],
         }
-        None
+    }
+
+    pub fn toggle_selected_todos(&mut self, count: usize) {
+        let ids = self.selected_todo_ids(count);
+        let _ = self.write(|app| {
+            for id in ids {
+                if let Some(todo) = app.todos.get_mut(&id) {
+                    todo.toggle();
+                    app.store.toggle_todo(todo)?;
+                }
+            }
+            Ok(())
+        });
+        self.refresh_todos();
+    }
+
+    pub fn get_selected_todo(&self) -> Option<&Todo> {
+        self.get_selected_todo_id()
+            .and_then(|id| self.todos.get(&id))
     }
 
     pub fn get_selected_todo_id(&self) -> Option<i64> {
-        let selected_index = match self.selected_todo.selected() {
-            Some(index) => index,
-            None => return None,
+        let selected_index = self.selected_todo.selected()?;
+        self.visible_todos.get(selected_index).copied()
+    }
+
+    pub fn delete_selected_todos(&mut self, count: usize) {
+        let ids = self.selected_todo_ids(count);
+        let deleted = self.write(|app| {
+            for id in &ids {
+                if let Some(todo) = app.todos.get_mut(id) {
+                    app.store.set_deleted(todo, true)?;
+                }
+            }
+            Ok(())
+        });
+        if deleted.is_ok() {
+            for id in &ids {
+                self.todos.remove(id);
+                self.visible_todos.retain(|visible_id| visible_id != id);
+            }
+            self.refresh_view_counts();
+            self.clamp_selection();
+            self.notice = Some(format!("Moved {} todo(s) to the trash", ids.len()));
+        }
+    }
+
+    pub fn update_selected_todo(&mut self) {
+        if let Some(id) = self.get_selected_todo_id() {
+            let label = std::mem::take(&mut self.todo_input);
+            let _ = self.write(|app| match app.todos.get_mut(&id) {
+                Some(todo) => {
+                    todo.label = label;
+                    app.store.update_todo(todo)
+                }
+                None => Ok(()),
+            });
+            self.refresh_todos();
         };
+    }
 
-        let id = match self.todos.keys().nth(selected_index) {
-            Some(key) => key.clone(),
-            None => {
-                eprintln!("Invalid index: {}", selected_index);
-                return None;
+    pub fn cycle_selected_priority(&mut self, count: usize) {
+        let ids = self.selected_todo_ids(count);
+        let _ = self.write(|app| {
+            for id in ids {
+                if let Some(todo) = app.todos.get_mut(&id) {
+                    todo.priority = todo.priority.next();
+                    app.store.update_todo(todo)?;
+                }
+            }
+            Ok(())
+        });
+        self.refresh_todos();
+    }
+
+    // Applies `change` again to the selection. Deletes are left to the caller
+    // so they can be confirmed.
+    pub fn repeat_change(&mut self, change: &Change, count: usize) {
+        match change {
+            Change::Toggle => self.toggle_selected_todos(count),
+            Change::Delete => self.delete_selected_todos(count),
+            Change::CyclePriority => self.cycle_selected_priority(count),
+            Change::Add(label) => {
+                for _ in 0..count {
+                    self.todo_input = label.clone();
+                    self.add_todo();
+                }
+            }
+            Change::Edit(label) => {
+                self.todo_input = label.clone();
+                self.update_selected_todo();
             }
+            Change::DueDate(input) => {
+                self.todo_input = input.clone();
+                self.set_selected_due_date();
+            }
+            Change::Paste(before) => self.paste(count, *before),
+        }
+    }
+
+    // Copies `count` todos from the selection into the register and onto the
+    // system clipboard, as plain labels or as a Markdown checklist
+    pub fn yank_selected_todos(&mut self, count: usize, markdown: bool) {
+        let todos: Vec<Todo> = self
+            .selected_todo_ids(count)
+            .iter()
+            .map(|id| self.todos[id].clone())
+            .collect();
+        if todos.is_empty() {
+            return;
+        }
+        let text = if markdown {
+            export::markdown_checklist(&todos)
+        } else {
+            let labels: Vec<&str> = todos.iter().map(|todo| todo.label.as_str()).collect();
+            labels.join("\n")
         };
+        self.notice = Some(match clipboard::copy(&text) {
+            Ok(()) => format!("Yanked {} todo(s)", todos.len()),
+            Err(err) => format!("Yanked {} todo(s), clipboard failed: {}", todos.len(), err),
+        });
+        self.register = todos;
+        self.yanked_text = Some(text);
+        self.clipboard_at_yank = clipboard::paste();
+    }
 
-        return Some(id);
+    // Pastes the clipboard as todos, one per line, or the register when the
+    // clipboard hasn't changed since the last yank or can't be read
+    pub fn paste(&mut self, count: usize, before: bool) {
+        let clipboard = clipboard::paste().filter(|text| {
+            !text.trim().is_empty()
+                && [&self.yanked_text, &self.clipboard_at_yank]
+                    .iter()
+                    .all(|seen| seen.as_deref().map(str::trim_end) != Some(text.trim_end()))
+        });
+        let todos = match clipboard {
+            Some(text) => export::parse_checklist(&text),
+            None => self.register.clone(),
+        };
+        if todos.is_empty() {
+            self.notice = Some(String::from("Nothing to paste"));
+            return;
+        }
+        let todos: Vec<Todo> = (0..count).flat_map(|_| todos.iter().cloned()).collect();
+        let positions = self.positions_around_selection(todos.len(), before);
+        self.insert_todos(&todos, positions);
     }
 
-    pub fn delete_selected_todo(&mut self) {
-        if let Some(id) = self.get_selected_todo_id() {
-            if let Some(todo) = self.todos.remove(&id) {
-                self.todos_db.delete_todo(todo.id);
-            };
+    // Asks before adding a todo for every line of pasted `text`. They go after
+    // the selection when pasted into the list, or at the end from the add popup.
+    pub fn confirm_paste(&mut self, text: &str, after_selection: bool) {
+        self.pending_paste = export::parse_checklist(text);
+        self.paste_after_selection = after_selection;
+        if !self.pending_paste.is_empty() {
+            self.current_screen = CurrentScreen::ConfirmPaste;
         }
     }
 
-    pub fn update_selected_todo(&mut self) {
-        if let Some(id) = self.get_selected_todo_id() {
-            if let Some(todo) = self.todos.get_mut(&id) {
-                todo.label = self.todo_input.clone();
-                self.todo_input.clear();
-                self.todos_db.update_todo(id, &todo.label.as_str());
+    pub fn add_pending_paste(&mut self) {
+        let todos = std::mem::take(&mut self.pending_paste);
+        let positions = if self.paste_after_selection {
+            self.positions_around_selection(todos.len(), false)
+        } else {
+            None
+        };
+        self.insert_todos(&todos, positions);
+    }
+
+    // Adds copies of `todos` at `positions` in the manual order, or at the end,
+    // and selects the first one
+    fn insert_todos(&mut self, todos: &[Todo], positions: Option<Vec<f64>>) {
+        let inserted = self.write(|app| {
+            let mut first = None;
+            for (index, pasted) in todos.iter().enumerate() {
+                let position = positions.as_ref().map(|positions| positions[index]);
+                let todo = app.store.add_todo_with(pasted, position)?;
+                first.get_or_insert(todo.id);
+                app.todos.insert(todo.id, todo);
             }
+            Ok(first)
+        });
+        self.refresh_todos();
+        if let Ok(Some(id)) = inserted {
+            self.notice = Some(format!("Pasted {} todo(s)", todos.len()));
+            if let Some(index) = self.visible_todos.iter().position(|visible| *visible == id) {
+                self.select(index);
+            }
+        }
+    }
+
+    // Evenly spaced positions for `count` todos next to the selected one, or
+    // None to add them at the end
+    fn positions_around_selection(&mut self, count: usize, before: bool) -> Option<Vec<f64>> {
+        let id = self.get_selected_todo_id()?;
+        let (mut low, mut high) = self.neighbour_positions(id, count, before);
+        // Repeated pastes in one spot eventually run out of precision
+        if (high - low) / (count as f64 + 1.0) < MIN_POSITION_GAP {
+            self.renumber_positions();
+            (low, high) = self.neighbour_positions(id, count, before);
+        }
+        let step = (high - low) / (count as f64 + 1.0);
+        Some((1..=count).map(|n| low + step * n as f64).collect())
+    }
+
+    // Positions between which todos go after, or before, todo `id`
+    fn neighbour_positions(&self, id: i64, count: usize, before: bool) -> (f64, f64) {
+        let position = self.todos[&id].position;
+        let others = self.todos.values().map(|todo| todo.position);
+        if before {
+            let low = others
+                .filter(|other| *other < position)
+                .reduce(f64::max)
+                .unwrap_or(position - count as f64 - 1.0);
+            (low, position)
+        } else {
+            let high = others
+                .filter(|other| *other > position)
+                .reduce(f64::min)
+                .unwrap_or(position + count as f64 + 1.0);
+            (position, high)
+        }
+    }
+
+    // Spreads the manual order back out to whole numbers
+    fn renumber_positions(&mut self) {
+        let mut ids: Vec<i64> = self.todos.keys().copied().collect();
+        ids.sort_by(|a, b| SortMode::Manual.compare(&self.todos[a], &self.todos[b]));
+        let _ = self.write(|app| {
+            for (index, id) in ids.into_iter().enumerate() {
+                if let Some(todo) = app.todos.get_mut(&id) {
+                    app.store.set_position(todo, index as f64 + 1.0)?;
+                }
+            }
+            Ok(())
+        });
+    }
+
+    // Parses `todo_input` as a due date for the selected todo. Returns false and
+    // sets a notice when the input is not a valid date.
+    pub fn set_selected_due_date(&mut self) -> bool {
+        let due_at = match time::parse_due(&self.todo_input, time::now()) {
+            Ok(due_at) => due_at,
+            Err(err) => {
+                self.notice = Some(err);
+                return false;
+            }
+        };
+        if let Some(id) = self.get_selected_todo_id() {
+            self.todo_input.clear();
+            let _ = self.write(|app| match app.todos.get_mut(&id) {
+                Some(todo) => {
+                    todo.due_at = due_at;
+                    app.store.update_todo(todo)
+                }
+                None => Ok(()),
+            });
+            self.refresh_todos();
         };
+        true
     }
 }
@@ -0,0 +1,177 @@
+use std::{
+    fs::{self, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use chrono::Local;
+use rusqlite::{backup::Backup, Connection, OpenFlags, Result};
+
+// Directory next to the database that backups go to
+pub const BACKUP_DIR: &str = "backups";
+
+const PREFIX: &str = "todos-";
+// A database backup, and a copy of a JSON store
+const EXTENSION: &str = "db";
+const EXTENSIONS: &[&str] = &[EXTENSION, "json"];
+
+// Pages copied per step, with a pause in between so other connections can
+// keep writing while a backup runs
+const PAGES_PER_STEP: i32 = 100;
+const STEP_PAUSE: Duration = Duration::from_millis(10);
+
+// Creates the empty file a new backup goes into, named after the current
+// time so the names sort oldest first, like `todos-20240502-093000-250.db`.
+// `reason` is appended when the backup isn't a scheduled one. An existing
+// file is never reused: a backup made in the same millisecond as another
+// waits for the next one.
+fn create_file(dir: &Path, reason: Option<&str>, extension: &str) -> io::Result<PathBuf> {
+    loop {
+        let mut name = format!("{}{}", PREFIX, Local::now().format("%Y%m%d-%H%M%S-%3f"));
+        if let Some(reason) = reason {
+            name.push('-');
+            name.push_str(reason);
+        }
+        let path = dir.join(format!("{}.{}", name, extension));
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => return Ok(path),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                thread::sleep(Duration::from_millis(1))
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+// Copies the database behind `conn` into a new file in `dir` with the online
+// backup API, so it is consistent even while other processes write
+pub fn create(conn: &Connection, dir: &Path, reason: Option<&str>) -> Result<PathBuf> {
+    let path = fs::create_dir_all(dir)
+        .and_then(|()| create_file(dir, reason, EXTENSION))
+        .map_err(|_| rusqlite::Error::InvalidPath(dir.to_path_buf()))?;
+    let copied = Connection::open(&path).and_then(|mut destination| {
+        Backup::new(conn, &mut destination)?.run_to_completion(PAGES_PER_STEP, STEP_PAUSE, None)?;
+        // The copy takes on WAL mode from the database. A plain journal keeps
+        // the backup in a single file.
+        destination.pragma_update(None, "journal_mode", "DELETE")
+    });
+    if let Err(err) = copied {
+        let _ = fs::remove_file(&path);
+        return Err(err);
+    }
+    Ok(path)
+}
+
+// Copies the store file at `file` into a new file in `dir`, keeping its
+// extension
+pub fn copy(file: &Path, dir: &Path, reason: Option<&str>) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let extension = file.extension().and_then(|extension| extension.to_str());
+    let path = create_file(dir, reason, extension.unwrap_or_default())?;
+    if let Err(err) = fs::copy(file, &path) {
+        let _ = fs::remove_file(&path);
+        return Err(err);
+    }
+    Ok(path)
+}
+
+// Backups in `dir`, oldest first
+pub fn list(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut backups = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_backup = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| EXTENSIONS.contains(&extension))
+            && path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(PREFIX));
+        if is_backup {
+            backups.push(path);
+        }
+    }
+    backups.sort();
+    Ok(backups)
+}
+
+// Deletes the oldest backups in `dir` until at most `keep` are left
+pub fn rotate(dir: &Path, keep: usize) -> io::Result<()> {
+    let backups = list(dir)?;
+    let excess = backups.len().saturating_sub(keep);
+    for path in &backups[..excess] {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+// When the newest backup in `dir` was made, if there is one
+pub fn last_backup_at(dir: &Path) -> Option<i64> {
+    let newest = list(dir).ok()?.pop()?;
+    let modified = fs::metadata(newest).ok()?.modified().ok()?;
+    modified
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|duration| duration.as_secs() as i64)
+}
+
+// Checks that `path` is an intact todo database before it is restored
+pub fn verify(path: &Path) -> std::result::Result<(), String> {
+    if !path.is_file() {
+        return Err(format!("{} doesn't exist", path.display()));
+    }
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|err| format!("unable to open {}: {}", path.display(), err))?;
+    let problems = conn
+        .prepare("PRAGMA integrity_check")
+        .and_then(|mut stmt| {
+            stmt.query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<String>>>()
+        })
+        .map_err(|err| format!("{} is not a database: {}", path.display(), err))?;
+    if problems != ["ok"] {
+        return Err(format!(
+            "{} failed the integrity check: {}",
+            path.display(),
+            problems.join("; ")
+        ));
+    }
+    let has_todos: bool = conn
+        .query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'todos')",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|err| err.to_string())?;
+    if !has_todos {
+        return Err(format!("{} has no todos in it", path.display()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDir;
+
+    #[test]
+    fn backups_in_the_same_instant_get_their_own_files() {
+        let dir = TempDir::new("backup-names");
+        let file = dir.join("todos.json");
+        fs::write(&file, "{}").unwrap();
+        let backups = dir.join(BACKUP_DIR);
+        let paths: Vec<PathBuf> = (0..5)
+            .map(|_| copy(&file, &backups, Some("manual")).unwrap())
+            .collect();
+        let mut unique = paths.clone();
+        unique.dedup();
+        assert_eq!(unique.len(), 5);
+        assert_eq!(list(&backups).unwrap(), paths);
+
+        rotate(&backups, 2).unwrap();
+        assert_eq!(list(&backups).unwrap(), paths[3..]);
+    }
+}
@@ -0,0 +1,222 @@
+use std::path::PathBuf;
+
+use crate::{
+    backup,
+    config::Config,
+    query::Query,
+    store::{self, StoreResult, TodoStore},
+    time::{self, SECONDS_PER_DAY},
+    todo::{Priority, Todo},
+};
+
+const USAGE: &str = "Usage:
+    todo-rs                          Start the interactive UI
+    todo-rs list [query]             Print todos matching a filter query, e.g.
+                                     status:open tag:work due<1w prio>=high text
+    todo-rs log [id]                 Print the history of todo <id>, or of all todos
+    todo-rs purge <days>             Delete archived todos older than <days>
+    todo-rs auto-archive <days|off>  Archive completed todos after <days>
+    todo-rs trash                    Print the todos in the trash
+    todo-rs untrash <id>             Restore todo <id> from the trash
+    todo-rs destroy <id>             Delete todo <id> in the trash for good
+    todo-rs empty-trash [days]       Delete todos in the trash for good, or only
+                                     those deleted more than <days> ago
+    todo-rs trash-retention <days|off>
+                                     Empty the trash of todos deleted more than
+                                     <days> ago on start, 30 by default
+    todo-rs backup                   Copy the todos into the backups directory
+    todo-rs restore <file>           Replace the todos with a backup";
+
+pub enum Command {
+    List(Query),
+    Log(Option<i64>),
+    Purge(i64),
+    AutoArchive(Option<i64>),
+    Trash,
+    Untrash(i64),
+    Destroy(i64),
+    EmptyTrash(Option<i64>),
+    TrashRetention(Option<i64>),
+    Backup,
+    Restore(PathBuf),
+}
+
+fn parse_days(arg: Option<&String>) -> Result<i64, String> {
+    let arg = arg.ok_or_else(|| String::from("missing number of days"))?;
+    match arg.parse::<i64>() {
+        Ok(days) if days >= 0 => Ok(days),
+        _ => Err(format!("invalid number of days: {}", arg)),
+    }
+}
+
+fn parse_id(arg: Option<&String>) -> Result<i64, String> {
+    let arg = arg.ok_or_else(|| String::from("missing todo id"))?;
+    arg.trim_start_matches('#')
+        .parse()
+        .map_err(|_| format!("invalid todo id: {}", arg))
+}
+
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    match args.first().map(String::as_str) {
+        Some("list") => Query::parse(&args[1..].join(" "), time::now())
+            .map(Command::List)
+            .map_err(|err| format!("invalid query: {}", err)),
+        Some("log") => match args.get(1) {
+            Some(_) => Ok(Command::Log(Some(parse_id(args.get(1))?))),
+            None => Ok(Command::Log(None)),
+        },
+        Some("purge") => Ok(Command::Purge(parse_days(args.get(1))?)),
+        Some("auto-archive") => match args.get(1).map(String::as_str) {
+            Some("off") => Ok(Command::AutoArchive(None)),
+            _ => Ok(Command::AutoArchive(Some(parse_days(args.get(1))?))),
+        },
+        Some("backup") => Ok(Command::Backup),
+        Some("restore") => match args.get(1) {
+            Some(path) => Ok(Command::Restore(PathBuf::from(path))),
+            None => Err(String::from("missing backup file")),
+        },
+        Some("trash") => Ok(Command::Trash),
+        Some("untrash") => Ok(Command::Untrash(parse_id(args.get(1))?)),
+        Some("destroy") => Ok(Command::Destroy(parse_id(args.get(1))?)),
+        Some("empty-trash") => match args.get(1) {
+            Some(_) => Ok(Command::EmptyTrash(Some(parse_days(args.get(1))?))),
+            None => Ok(Command::EmptyTrash(None)),
+        },
+        Some("trash-retention") => match args.get(1).map(String::as_str) {
+            Some("off") => Ok(Command::TrashRetention(None)),
+            _ => Ok(Command::TrashRetention(Some(parse_days(args.get(1))?))),
+        },
+        Some(command) => Err(format!("unknown command: {}", command)),
+        None => Err(String::from("missing command")),
+    }
+}
+
+fn format_todo(todo: &Todo, date_format: &str) -> String {
+    let mut line = format!(
+        "[{}] {:>4} {}{}",
+        if todo.completed { "x" } else { " " },
+        todo.id,
+        match todo.priority {
+            Priority::None => "",
+            Priority::Low => "! ",
+            Priority::Medium => "!! ",
+            Priority::High => "!!! ",
+        },
+        todo.label
+    );
+    if let Some(due_at) = todo.due_at {
+        line.push_str(&format!("  (due {})", time::format(due_at, date_format)));
+    }
+    line
+}
+
+// Todo `id` from the trash, exiting with an error when it isn't there
+fn trashed_todo(store: &dyn TodoStore, id: i64) -> StoreResult<Todo> {
+    match store.get_trash()?.into_iter().find(|todo| todo.id == id) {
+        Some(todo) => Ok(todo),
+        None => {
+            eprintln!("Error: todo {} isn't in the trash", id);
+            std::process::exit(1);
+        }
+    }
+}
+
+pub fn run(args: &[String], config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let command = match parse(args) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("Error: {}\n\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let mut store = store::open(config.storage_backend, &config.storage_path)?;
+    match command {
+        Command::List(query) => {
+            for todo in store.find_todos(&query, time::now())? {
+                println!("{}", format_todo(&todo, &config.date_format));
+            }
+        }
+        Command::Log(id) => {
+            for entry in store.get_history(id)? {
+                println!(
+                    "{}  {:>4}  {}",
+                    time::format(entry.changed_at, &config.datetime_format),
+                    entry.todo_id,
+                    entry.describe(&config.date_format)
+                );
+            }
+        }
+        Command::Purge(days) => {
+            let purged = store.purge_archived(time::now() - days * SECONDS_PER_DAY)?;
+            println!("Purged {} archived todo(s)", purged);
+        }
+        Command::AutoArchive(Some(days)) => {
+            store.set_setting("auto_archive_days", &days.to_string())?;
+            println!("Completed todos will be archived after {} day(s)", days);
+        }
+        Command::AutoArchive(None) => {
+            store.delete_setting("auto_archive_days")?;
+            println!("Automatic archiving disabled");
+        }
+        Command::Trash => {
+            for todo in store.get_trash()? {
+                println!("{}", format_todo(&todo, &config.date_format));
+            }
+        }
+        Command::Untrash(id) => {
+            let mut todo = trashed_todo(store.as_ref(), id)?;
+            store.set_deleted(&mut todo, false)?;
+            println!("Restored todo {}", id);
+        }
+        Command::Destroy(id) => {
+            let todo = trashed_todo(store.as_ref(), id)?;
+            store.delete_todo(&todo)?;
+            println!("Deleted todo {} for good", id);
+        }
+        Command::EmptyTrash(days) => {
+            let before = match days {
+                Some(days) => time::now() - days * SECONDS_PER_DAY,
+                None => i64::MAX,
+            };
+            let deleted = store.empty_trash(before)?;
+            println!("Deleted {} todo(s) in the trash for good", deleted);
+        }
+        Command::TrashRetention(Some(days)) => {
+            store.set_setting("trash_retention_days", &days.to_string())?;
+            println!(
+                "Todos will be deleted for good {} day(s) after going to the trash",
+                days
+            );
+        }
+        Command::TrashRetention(None) => {
+            store.set_setting("trash_retention_days", "off")?;
+            println!("The trash will be kept until emptied");
+        }
+        Command::Backup => {
+            let path = store.backup(None)?;
+            if let Err(err) = store.verify_backup(&path) {
+                eprintln!("Error: the backup is unusable, {}", err);
+                std::process::exit(1);
+            }
+            if let Some(dir) = store.backup_dir() {
+                backup::rotate(dir, config.backup_keep)?;
+            }
+            println!("Backed up to {}", path.display());
+        }
+        Command::Restore(path) => {
+            if let Err(err) = store.verify_backup(&path) {
+                eprintln!("Error: not restoring, {}", err);
+                std::process::exit(1);
+            }
+            let previous = store.backup(Some("before-restore"))?;
+            store.restore(&path)?;
+            println!(
+                "Restored {}, the replaced todos are in {}",
+                path.display(),
+                previous.display()
+            );
+        }
+    }
+    Ok(())
+}
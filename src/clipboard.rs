@@ -0,0 +1,107 @@
+use std::{
+    env,
+    io::{self, Read, Write},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+// Commands that print the system clipboard in order of preference, with the
+// environment variable they need to reach it
+const PASTE_COMMANDS: &[(&[&str], Option<&str>)] = &[
+    (&["pbpaste"], None),
+    (&["wl-paste", "--no-newline"], Some("WAYLAND_DISPLAY")),
+    (&["xclip", "-selection", "clipboard", "-o"], Some("DISPLAY")),
+    (&["xsel", "--clipboard", "--output"], Some("DISPLAY")),
+];
+
+// How long a paste command may take before the clipboard is given up on
+const PASTE_TIMEOUT: Duration = Duration::from_millis(500);
+const PASTE_POLL: Duration = Duration::from_millis(5);
+
+// The paste command for this session, looked up on first use
+static PASTE_COMMAND: OnceLock<Option<&[&str]>> = OnceLock::new();
+// Set once a paste command hung, so it only ever holds up one paste
+static PASTE_HUNG: AtomicBool = AtomicBool::new(false);
+
+// Puts `text` on the system clipboard with an OSC 52 escape sequence. The
+// terminal does the copying, so this also works over SSH.
+pub fn copy(text: &str) -> io::Result<()> {
+    let mut stderr = io::stderr();
+    write!(stderr, "\x1b]52;c;{}\x07", STANDARD.encode(text))?;
+    stderr.flush()
+}
+
+fn on_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}
+
+fn paste_command() -> Option<&'static [&'static str]> {
+    *PASTE_COMMAND.get_or_init(|| {
+        PASTE_COMMANDS
+            .iter()
+            .find(|(command, needs)| {
+                needs.is_none_or(|name| env::var_os(name).is_some()) && on_path(command[0])
+            })
+            .map(|(command, _)| *command)
+    })
+}
+
+fn wait_until(child: &mut Child, deadline: Instant) -> Option<ExitStatus> {
+    loop {
+        if let Some(status) = child.try_wait().ok()? {
+            return Some(status);
+        }
+        if Instant::now() >= deadline {
+            return None;
+        }
+        thread::sleep(PASTE_POLL);
+    }
+}
+
+// Reads the system clipboard, or None when there is no way to, so callers
+// fall back to their own register. Terminals rarely allow reading it back
+// over OSC 52, so this runs a paste command, which can hang on a broken
+// display connection and is given `PASTE_TIMEOUT` to answer.
+pub fn paste() -> Option<String> {
+    if PASTE_HUNG.load(Ordering::Relaxed) {
+        return None;
+    }
+    let command = paste_command()?;
+    let deadline = Instant::now() + PASTE_TIMEOUT;
+    let mut child = Command::new(command[0])
+        .args(&command[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    // Read on another thread so a command that never closes its output
+    // can't block past the deadline
+    let mut stdout = child.stdout.take()?;
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = vec![];
+        let _ = sender.send(stdout.read_to_end(&mut output).map(|_| output));
+    });
+    let output = receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()));
+    let status = wait_until(&mut child, deadline);
+    if status.is_none() {
+        PASTE_HUNG.store(true, Ordering::Relaxed);
+        let _ = child.kill();
+        let _ = child.wait();
+        return None;
+    }
+    match (output, status) {
+        (Ok(Ok(output)), Some(status)) if status.success() => String::from_utf8(output).ok(),
+        _ => None,
+    }
+}
@@ -0,0 +1,294 @@
+use std::path::PathBuf;
+
+use crate::{export::ExportFormat, sort::SortMode};
+
+// A command typed on the `:` command line
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Add(String),
+    // Sort mode and, when given, whether it is descending
+    Sort(SortMode, Option<bool>),
+    // An empty query clears the filter
+    Filter(String),
+    View(String),
+    Export(ExportFormat, PathBuf),
+    // Purges archived todos older than this many days. There is no default,
+    // so emptying the archive takes an explicit 0.
+    Purge(i64),
+    Goto(i64),
+    Help,
+    Quit,
+}
+
+// Command names with a short usage, in the order they are completed
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("add", "add <label>"),
+    ("export", "export <md|csv> <file>"),
+    ("filter", "filter [query]"),
+    ("goto", "goto <id>"),
+    ("help", "help"),
+    ("purge", "purge <days>"),
+    ("quit", "quit"),
+    ("sort", "sort <mode> [asc|desc]"),
+    ("view", "view <name>"),
+];
+
+const FILTER_FIELDS: &[&str] = &["status:", "tag:", "due:", "prio:", "created<", "updated<"];
+
+fn usage(name: &str) -> String {
+    let usage = COMMANDS
+        .iter()
+        .find(|(command, _)| *command == name)
+        .map_or(name, |(_, usage)| usage);
+    format!("usage: :{}", usage)
+}
+
+impl Command {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let input = input.trim();
+        let (name, rest) = input.split_once(' ').unwrap_or((input, ""));
+        let rest = rest.trim();
+        let args: Vec<&str> = rest.split_whitespace().collect();
+        // Commands can be shortened to any unique prefix, like "q" for quit
+        let matching: Vec<&str> = COMMANDS
+            .iter()
+            .map(|(command, _)| *command)
+            .filter(|command| command.starts_with(name))
+            .collect();
+        let name = match matching.as_slice() {
+            _ if name.is_empty() => return Err(String::from("empty command")),
+            [command] => *command,
+            _ if matching.contains(&name) => name,
+            [] => return Err(format!("unknown command '{}'", name)),
+            _ => {
+                return Err(format!(
+                    "ambiguous command '{}': {}",
+                    name,
+                    matching.join(", ")
+                ))
+            }
+        };
+
+        match name {
+            "add" if !rest.is_empty() => Ok(Command::Add(rest.to_string())),
+            "sort" => match args.as_slice() {
+                [mode] | [mode, _] => {
+                    let mode = SortMode::from_name(mode)
+                        .ok_or_else(|| format!("unknown sort mode '{}'", mode))?;
+                    let descending = match args.get(1) {
+                        None => None,
+                        Some(&"asc") => Some(false),
+                        Some(&"desc") => Some(true),
+                        Some(direction) => {
+                            return Err(format!("unknown direction '{}'", direction))
+                        }
+                    };
+                    Ok(Command::Sort(mode, descending))
+                }
+                _ => Err(usage(name)),
+            },
+            "filter" => Ok(Command::Filter(rest.to_string())),
+            "view" if !rest.is_empty() => Ok(Command::View(rest.to_string())),
+            "export" => match args.as_slice() {
+                [format, _, ..] => {
+                    let format = ExportFormat::from_name(format)
+                        .ok_or_else(|| format!("unknown export format '{}'", format))?;
+                    // The path is everything after the format, spaces included
+                    let path = rest[args[0].len()..].trim();
+                    Ok(Command::Export(format, PathBuf::from(path)))
+                }
+                _ => Err(usage(name)),
+            },
+            "purge" => match args.as_slice() {
+                [days] => match days.parse::<i64>() {
+                    Ok(days) if days >= 0 => Ok(Command::Purge(days)),
+                    _ => Err(format!("invalid number of days '{}'", days)),
+                },
+                _ => Err(usage(name)),
+            },
+            "goto" => match args.as_slice() {
+                [id] => id
+                    .trim_start_matches('#')
+                    .parse()
+                    .map(Command::Goto)
+                    .map_err(|_| format!("invalid todo id '{}'", id)),
+                _ => Err(usage(name)),
+            },
+            "help" => Ok(Command::Help),
+            "quit" => Ok(Command::Quit),
+            _ => Err(usage(name)),
+        }
+    }
+}
+
+// Candidates for completing the last word of `input`, as the byte offset the
+// word starts at and the full replacements for it
+pub fn completions(input: &str, view_names: &[String]) -> (usize, Vec<String>) {
+    let start = input
+        .rfind(char::is_whitespace)
+        .map_or(0, |index| index + 1);
+    let word = &input[start..];
+    let words: Vec<&str> = input[..start].split_whitespace().collect();
+
+    let candidates: Vec<String> = match words.as_slice() {
+        [] => COMMANDS
+            .iter()
+            .map(|(command, _)| command.to_string())
+            .collect(),
+        ["sort"] => SortMode::ALL
+            .iter()
+            .map(|mode| mode.name().to_string())
+            .collect(),
+        ["sort", _] => vec![String::from("asc"), String::from("desc")],
+        ["export"] => ExportFormat::ALL
+            .iter()
+            .map(|format| format.name().to_string())
+            .collect(),
+        ["filter", ..] => FILTER_FIELDS
+            .iter()
+            .map(|field| field.to_string())
+            .collect(),
+        // View names can have spaces, so the whole argument is completed
+        ["view", ..] => {
+            let typed = input["view".len()..].trim_start();
+            let start = input.len() - typed.len();
+            let names = view_names
+                .iter()
+                .filter(|name| name.to_lowercase().starts_with(&typed.to_lowercase()))
+                .cloned()
+                .collect();
+            return (start, names);
+        }
+        _ => vec![],
+    };
+    let candidates = candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .collect();
+    (start, candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            Command::parse(" add  Buy milk "),
+            Ok(Command::Add(String::from("Buy milk")))
+        );
+        assert_eq!(
+            Command::parse("sort due"),
+            Ok(Command::Sort(SortMode::Due, None))
+        );
+        assert_eq!(
+            Command::parse("sort alpha desc"),
+            Ok(Command::Sort(SortMode::Alphabetical, Some(true)))
+        );
+        assert_eq!(Command::parse("filter"), Ok(Command::Filter(String::new())));
+        assert_eq!(
+            Command::parse("view Waiting on others"),
+            Ok(Command::View(String::from("Waiting on others")))
+        );
+        assert_eq!(
+            Command::parse("export md my todos.md"),
+            Ok(Command::Export(
+                ExportFormat::Markdown,
+                PathBuf::from("my todos.md")
+            ))
+        );
+        assert_eq!(Command::parse("purge 30"), Ok(Command::Purge(30)));
+        assert_eq!(Command::parse("goto #12"), Ok(Command::Goto(12)));
+        assert_eq!(Command::parse("help"), Ok(Command::Help));
+    }
+
+    #[test]
+    fn commands_can_be_shortened() {
+        assert_eq!(Command::parse("q"), Ok(Command::Quit));
+        assert_eq!(
+            Command::parse("so created asc"),
+            Ok(Command::Sort(SortMode::Created, Some(false)))
+        );
+        assert_eq!(Command::parse("g 3"), Ok(Command::Goto(3)));
+        assert_eq!(
+            Command::parse("x"),
+            Err(String::from("unknown command 'x'"))
+        );
+        assert!(Command::parse("").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert_eq!(
+            Command::parse("add"),
+            Err(String::from("usage: :add <label>"))
+        );
+        assert_eq!(
+            Command::parse("sort size"),
+            Err(String::from("unknown sort mode 'size'"))
+        );
+        assert_eq!(
+            Command::parse("sort due up"),
+            Err(String::from("unknown direction 'up'"))
+        );
+        assert_eq!(
+            Command::parse("export pdf out.pdf"),
+            Err(String::from("unknown export format 'pdf'"))
+        );
+        assert_eq!(
+            Command::parse("export md"),
+            Err(String::from("usage: :export <md|csv> <file>"))
+        );
+        assert_eq!(
+            Command::parse("goto twelve"),
+            Err(String::from("invalid todo id 'twelve'"))
+        );
+        // Purging everything has to be asked for
+        assert_eq!(
+            Command::parse("purge"),
+            Err(String::from("usage: :purge <days>"))
+        );
+        assert_eq!(
+            Command::parse("purge -1"),
+            Err(String::from("invalid number of days '-1'"))
+        );
+    }
+
+    #[test]
+    fn completes_the_last_word() {
+        assert_eq!(
+            completions("", &[]),
+            (
+                0,
+                COMMANDS.iter().map(|(name, _)| name.to_string()).collect()
+            )
+        );
+        assert_eq!(completions("g", &[]), (0, vec![String::from("goto")]));
+        assert_eq!(completions("sort d", &[]), (5, vec![String::from("due")]));
+        assert_eq!(
+            completions("sort due ", &[]),
+            (9, vec![String::from("asc"), String::from("desc")])
+        );
+        assert_eq!(completions("export c", &[]), (7, vec![String::from("csv")]));
+        assert_eq!(
+            completions("filter status:open t", &[]),
+            (19, vec![String::from("tag:")])
+        );
+        assert_eq!(completions("goto 1", &[]), (5, vec![]));
+    }
+
+    #[test]
+    fn completes_whole_view_names() {
+        let views = [String::from("Waiting on others"), String::from("Today")];
+        assert_eq!(
+            completions("view wait", &views),
+            (5, vec![views[0].clone()])
+        );
+        assert_eq!(
+            completions("view Waiting o", &views),
+            (5, vec![views[0].clone()])
+        );
+        assert_eq!(completions("view ", &views), (5, views.to_vec()));
+    }
+}
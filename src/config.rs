@@ -0,0 +1,563 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fmt, fs, io,
+    path::PathBuf,
+};
+
+use chrono::format::{Item, StrftimeItems};
+use serde::Deserialize;
+
+use crate::{
+    keys::{KeyContext, Keymap},
+    sort::{Sort, SortMode},
+    store::Backend,
+    theme::{self, ColorMode, Theme, BUILTIN_THEMES},
+    time::{self, SECONDS_PER_DAY},
+};
+
+// Settings read from `$XDG_CONFIG_HOME/todo-rs/config.toml`, for example:
+//
+//     tick_rate = 250
+//     confirm_delete = false
+//     date_format = "%d/%m/%Y"
+//     datetime_format = "%d/%m/%Y %H:%M"
+//     theme = "mine"
+//     color_mode = "256"
+//
+//     [sort]
+//     mode = "due"
+//     descending = false
+//
+//     [backup]
+//     interval = "12h"
+//     keep = 20
+//
+//     [storage]
+//     backend = "json"
+//     path = "/home/me/notes/todos.json"
+//
+//     [keys.main]
+//     top = "gg"
+//     delete = ["dd", "<Delete>"]
+//
+//     [themes.mine]
+//     base = "light"
+//     priority_high = "bold #d70000"
+//
+// Every setting is optional. Binding an action replaces its default keys,
+// and an empty list unbinds it.
+// `color_mode` is detected from NO_COLOR, COLORTERM and TERM unless set.
+// Backups are taken daily and the last 10 kept by default, and `interval =
+// "off"` stops the scheduled ones.
+// Todos are kept in `todos.db` in the working directory by default, or in
+// `todos.json` with the json backend.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub keymap: Keymap,
+    // Used until a sort is picked in the UI, which is then remembered
+    pub default_sort: Sort,
+    pub tick_rate: u64,
+    pub confirm_delete: bool,
+    pub date_format: String,
+    pub datetime_format: String,
+    pub theme: Theme,
+    // Seconds between scheduled backups, or None for no scheduled backups
+    pub backup_interval: Option<i64>,
+    // How many backups to keep before deleting the oldest
+    pub backup_keep: usize,
+    pub storage_backend: Backend,
+    pub storage_path: PathBuf,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            keymap: Keymap::default(),
+            default_sort: Sort::default(),
+            tick_rate: 250,
+            confirm_delete: true,
+            date_format: String::from("%Y-%m-%d"),
+            datetime_format: String::from("%Y-%m-%d %H:%M"),
+            theme: Theme::default(),
+            backup_interval: Some(SECONDS_PER_DAY),
+            backup_keep: 10,
+            storage_backend: Backend::default(),
+            storage_path: Backend::default().default_path(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct RawConfig {
+    tick_rate: Option<u64>,
+    confirm_delete: Option<bool>,
+    date_format: Option<String>,
+    datetime_format: Option<String>,
+    sort: Option<RawSort>,
+    backup: Option<RawBackup>,
+    storage: Option<RawStorage>,
+    keys: BTreeMap<String, BTreeMap<String, RawKeys>>,
+    theme: Option<String>,
+    color_mode: Option<String>,
+    themes: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSort {
+    mode: String,
+    #[serde(default)]
+    descending: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct RawBackup {
+    interval: Option<String>,
+    keep: Option<usize>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct RawStorage {
+    backend: Option<String>,
+    path: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawKeys {
+    One(String),
+    Many(Vec<String>),
+}
+
+fn validate_format(name: &str, format: String) -> Result<String, String> {
+    if format.is_empty() || StrftimeItems::new(&format).any(|item| item == Item::Error) {
+        return Err(format!("{}: invalid date format '{}'", name, format));
+    }
+    Ok(format)
+}
+
+// Resolves the theme called `name`, either built in or from `[themes.<name>]`
+fn resolve_theme(
+    name: &str,
+    themes: &BTreeMap<String, BTreeMap<String, String>>,
+) -> Result<Theme, String> {
+    if let Some(theme) = Theme::builtin(name) {
+        return Ok(theme);
+    }
+    let slots = themes.get(name).ok_or_else(|| {
+        let mut names: Vec<&str> = BUILTIN_THEMES.to_vec();
+        names.extend(themes.keys().map(String::as_str));
+        format!(
+            "theme: unknown theme '{}', expected one of {}",
+            name,
+            names.join(", ")
+        )
+    })?;
+    let base = slots.get("base").map_or("dark", String::as_str);
+    let mut theme = Theme::builtin(base).ok_or_else(|| {
+        format!(
+            "themes.{}.base: '{}' is not a built-in theme, expected one of {}",
+            name,
+            base,
+            BUILTIN_THEMES.join(", ")
+        )
+    })?;
+    for (slot, value) in slots.iter().filter(|(slot, _)| *slot != "base") {
+        let style = theme::parse_style(value)
+            .map_err(|err| format!("themes.{}.{}: {}", name, slot, err))?;
+        *theme.slot_mut(slot).ok_or_else(|| {
+            format!(
+                "themes.{}: unknown slot '{}', expected base or one of {}",
+                name,
+                slot,
+                theme::SLOTS.join(", ")
+            )
+        })? = style;
+    }
+    Ok(theme)
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_home.join("todo-rs").join("config.toml"))
+    }
+
+    // Reads the config file, falling back to the defaults when there is none
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => {
+                return Self::parse("").map_err(|message| ConfigError {
+                    path: PathBuf::new(),
+                    message,
+                })
+            }
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(ConfigError {
+                    path,
+                    message: err.to_string(),
+                })
+            }
+        };
+        Self::parse(&contents).map_err(|message| ConfigError { path, message })
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let raw: RawConfig = toml::from_str(contents).map_err(|err| err.to_string())?;
+        let defaults = Self::default();
+
+        let tick_rate = match raw.tick_rate {
+            Some(tick_rate) if !(10..=60_000).contains(&tick_rate) => {
+                return Err(format!(
+                    "tick_rate: {} is out of range, use 10 to 60000 milliseconds",
+                    tick_rate
+                ))
+            }
+            Some(tick_rate) => tick_rate,
+            None => defaults.tick_rate,
+        };
+
+        let default_sort = match raw.sort {
+            Some(sort) => Sort {
+                mode: SortMode::from_name(&sort.mode).ok_or_else(|| {
+                    let names: Vec<&str> = SortMode::ALL.iter().map(|mode| mode.name()).collect();
+                    format!(
+                        "sort.mode: unknown sort '{}', expected one of {}",
+                        sort.mode,
+                        names.join(", ")
+                    )
+                })?,
+                descending: sort.descending,
+            },
+            None => defaults.default_sort,
+        };
+
+        let backup = raw.backup.unwrap_or_default();
+        let backup_interval = match backup.interval.as_deref() {
+            None => defaults.backup_interval,
+            Some("off") => None,
+            Some(interval) => Some(
+                time::parse_duration(interval)
+                    .filter(|seconds| *seconds > 0)
+                    .ok_or_else(|| {
+                        format!(
+                            "backup.interval: invalid interval '{}', expected off or a span like 12h, 1d or 1w",
+                            interval
+                        )
+                    })?,
+            ),
+        };
+        let backup_keep = match backup.keep {
+            Some(0) => return Err(String::from("backup.keep: must be at least 1")),
+            Some(keep) => keep,
+            None => defaults.backup_keep,
+        };
+
+        let storage = raw.storage.unwrap_or_default();
+        let storage_backend = match storage.backend.as_deref() {
+            Some(name) => Backend::from_name(name).ok_or_else(|| {
+                let names: Vec<&str> = Backend::ALL.iter().map(|backend| backend.name()).collect();
+                format!(
+                    "storage.backend: unknown backend '{}', expected one of {}",
+                    name,
+                    names.join(", ")
+                )
+            })?,
+            None => defaults.storage_backend,
+        };
+        let storage_path = match storage.path {
+            Some(path) if path.as_os_str().is_empty() => {
+                return Err(String::from("storage.path: can't be empty"))
+            }
+            Some(path) => path,
+            None => storage_backend.default_path(),
+        };
+
+        if let Some(name) = raw
+            .themes
+            .keys()
+            .find(|name| Theme::builtin(name).is_some())
+        {
+            return Err(format!(
+                "themes.{}: the name is taken by a built-in theme",
+                name
+            ));
+        }
+        let color_mode = match raw.color_mode.as_deref() {
+            None | Some("auto") => ColorMode::detect(),
+            Some(name) => ColorMode::from_name(name).ok_or_else(|| {
+                format!(
+                    "color_mode: unknown mode '{}', expected auto, truecolor, 256, 16 or mono",
+                    name
+                )
+            })?,
+        };
+        // Check every theme so mistakes show up before switching to it
+        for name in raw.themes.keys() {
+            resolve_theme(name, &raw.themes)?;
+        }
+        let theme =
+            resolve_theme(raw.theme.as_deref().unwrap_or("dark"), &raw.themes)?.degrade(color_mode);
+
+        let mut overrides = HashMap::new();
+        for (context_name, actions) in raw.keys {
+            let context = KeyContext::from_name(&context_name).ok_or_else(|| {
+                let names: Vec<&str> = KeyContext::ALL
+                    .iter()
+                    .map(|context| context.name())
+                    .collect();
+                format!(
+                    "keys.{}: unknown screen, expected one of {}",
+                    context_name,
+                    names.join(", ")
+                )
+            })?;
+            let actions: Vec<(String, Vec<String>)> = actions
+                .into_iter()
+                .map(|(action, keys)| match keys {
+                    RawKeys::One(key) => (action, vec![key]),
+                    RawKeys::Many(keys) => (action, keys),
+                })
+                .collect();
+            overrides.insert(context, actions);
+        }
+
+        Ok(Self {
+            keymap: Keymap::new(&overrides)?,
+            default_sort,
+            tick_rate,
+            confirm_delete: raw.confirm_delete.unwrap_or(defaults.confirm_delete),
+            date_format: match raw.date_format {
+                Some(format) => validate_format("date_format", format)?,
+                None => defaults.date_format,
+            },
+            datetime_format: match raw.datetime_format {
+                Some(format) => validate_format("datetime_format", format)?,
+                None => defaults.datetime_format,
+            },
+            theme,
+            backup_interval,
+            backup_keep,
+            storage_backend,
+            storage_path,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::Action;
+
+    fn error(contents: &str) -> String {
+        Config::parse(contents).unwrap_err()
+    }
+
+    #[test]
+    fn parses_a_full_file() {
+        let config = Config::parse(
+            r##"
+            tick_rate = 100
+            confirm_delete = false
+            date_format = "%d/%m/%Y"
+            datetime_format = "%d/%m/%Y %H:%M"
+            theme = "mine"
+            color_mode = "truecolor"
+
+            [sort]
+            mode = "due"
+            descending = true
+
+            [backup]
+            interval = "12h"
+            keep = 20
+
+            [storage]
+            backend = "json"
+            path = "/tmp/todos.json"
+
+            [keys.main]
+            top = "gg"
+            delete = ["dd", "<Delete>"]
+
+            [themes.mine]
+            base = "light"
+            priority_high = "bold #d70000"
+            "##,
+        )
+        .unwrap();
+        assert_eq!(config.tick_rate, 100);
+        assert!(!config.confirm_delete);
+        assert_eq!(config.date_format, "%d/%m/%Y");
+        assert_eq!(config.datetime_format, "%d/%m/%Y %H:%M");
+        assert_eq!(
+            config.default_sort,
+            Sort {
+                mode: SortMode::Due,
+                descending: true
+            }
+        );
+        assert_eq!(config.backup_interval, Some(12 * 60 * 60));
+        assert_eq!(config.backup_keep, 20);
+        assert_eq!(config.storage_backend, Backend::Json);
+        assert_eq!(config.storage_path, PathBuf::from("/tmp/todos.json"));
+        assert_eq!(
+            config
+                .keymap
+                .keys_for(KeyContext::Main, Action::Delete)
+                .len(),
+            2
+        );
+        let mut theme = Theme::light();
+        theme.priority_high = theme::parse_style("bold #d70000").unwrap();
+        assert_eq!(config.theme, theme);
+    }
+
+    #[test]
+    fn an_empty_file_gives_the_defaults() {
+        let config = Config::parse("color_mode = \"truecolor\"").unwrap();
+        let defaults = Config::default();
+        assert_eq!(config.tick_rate, defaults.tick_rate);
+        assert_eq!(config.default_sort, defaults.default_sort);
+        assert_eq!(config.backup_interval, defaults.backup_interval);
+        assert_eq!(config.storage_path, defaults.storage_path);
+        assert_eq!(config.theme, defaults.theme);
+    }
+
+    #[test]
+    fn rejects_unknown_settings() {
+        assert!(error("colour_mode = \"mono\"").contains("unknown field `colour_mode`"));
+        assert!(error("[backup]\nevery = \"1d\"").contains("unknown field `every`"));
+        assert!(error("[sort]\nmode = \"due\"\norder = \"asc\"").contains("unknown field `order`"));
+        assert!(error("tick_rate = \"fast\"").contains("invalid type"));
+    }
+
+    #[test]
+    fn rejects_out_of_range_tick_rates() {
+        assert_eq!(
+            error("tick_rate = 5"),
+            "tick_rate: 5 is out of range, use 10 to 60000 milliseconds"
+        );
+        assert_eq!(
+            error("tick_rate = 60001"),
+            "tick_rate: 60001 is out of range, use 10 to 60000 milliseconds"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_sorts_and_date_formats() {
+        assert_eq!(
+            error("[sort]\nmode = \"size\""),
+            "sort.mode: unknown sort 'size', expected one of manual, created, updated, due, priority, alpha, status"
+        );
+        assert_eq!(
+            error("date_format = \"%Y-%Q\""),
+            "date_format: invalid date format '%Y-%Q'"
+        );
+        assert_eq!(
+            error("datetime_format = \"\""),
+            "datetime_format: invalid date format ''"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_keys() {
+        assert_eq!(
+            error("[keys.main]\ntop = \"<Hyper-x>\""),
+            "keys.main.top: unknown modifier 'Hyper' in <Hyper-x>"
+        );
+        assert_eq!(
+            error("[keys.main]\ntop = \"g g\""),
+            "keys.main.top: whitespace in 'g g', use <Space> instead"
+        );
+        assert_eq!(
+            error("[keys.main]\nbottom = \"g\"\ntop = \"gg\""),
+            "keys.main.top: 'gg' clashes with the keys for 'bottom'"
+        );
+        assert!(error("[keys.main]\nfly = \"f\"")
+            .starts_with("keys.main: unknown action 'fly', expected one of "));
+        assert!(error("[keys.nowhere]\ntop = \"g\"")
+            .starts_with("keys.nowhere: unknown screen, expected one of "));
+    }
+
+    #[test]
+    fn rejects_bad_themes() {
+        assert_eq!(
+            error("theme = \"solarized\""),
+            "theme: unknown theme 'solarized', expected one of dark, light, high-contrast, monochrome"
+        );
+        assert_eq!(
+            error("[themes.dark]\nmuted = \"gray\""),
+            "themes.dark: the name is taken by a built-in theme"
+        );
+        assert_eq!(
+            error("[themes.mine]\nbase = \"mine\""),
+            "themes.mine.base: 'mine' is not a built-in theme, expected one of dark, light, high-contrast, monochrome"
+        );
+        assert_eq!(
+            error("[themes.mine]\nmuted = \"greyish\""),
+            "themes.mine.muted: unknown colour 'greyish'"
+        );
+        assert!(error("[themes.mine]\nshadow = \"gray\"")
+            .starts_with("themes.mine: unknown slot 'shadow', expected base or one of muted, "));
+        assert_eq!(
+            error("color_mode = \"8\""),
+            "color_mode: unknown mode '8', expected auto, truecolor, 256, 16 or mono"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_backup_and_storage_options() {
+        assert_eq!(
+            error("[backup]\ninterval = \"soon\""),
+            "backup.interval: invalid interval 'soon', expected off or a span like 12h, 1d or 1w"
+        );
+        assert_eq!(
+            error("[backup]\ninterval = \"0d\""),
+            "backup.interval: invalid interval '0d', expected off or a span like 12h, 1d or 1w"
+        );
+        assert_eq!(
+            error("[backup]\nkeep = 0"),
+            "backup.keep: must be at least 1"
+        );
+        assert_eq!(
+            error("[storage]\nbackend = \"csv\""),
+            "storage.backend: unknown backend 'csv', expected one of sqlite, json"
+        );
+        assert_eq!(
+            error("[storage]\npath = \"\""),
+            "storage.path: can't be empty"
+        );
+        assert_eq!(
+            Config::parse("[backup]\ninterval = \"off\"")
+                .unwrap()
+                .backup_interval,
+            None
+        );
+    }
+}
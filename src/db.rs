@@ -1,16 +1,263 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use rusqlite::{Connection, Result};
+use rusqlite::{
+    backup::Progress, params, params_from_iter, types::Value, Connection, DatabaseName,
+    OptionalExtension, Result, Row,
+};
 
-use crate::todo::Todo;
+use crate::{
+    backup::{self, BACKUP_DIR},
+    history::HistoryEntry,
+    query::{AgeField, Query, Status, Term},
+    search::{self, SearchHit},
+    sort::{Sort, SortMode},
+    store::{StoreError, StoreResult, TodoStore},
+    time::now,
+    todo::{Priority, Todo},
+    view::SavedView,
+};
+
+// Each entry upgrades the schema by one version, tracked in `PRAGMA user_version`
+const MIGRATIONS: &[&str] = &[
+    // 1: completion/archive timestamps and key-value settings
+    "
+    ALTER TABLE todos ADD COLUMN completed_at INTEGER;
+    ALTER TABLE todos ADD COLUMN archived_at INTEGER;
+    UPDATE todos SET completed_at = strftime('%s', 'now') WHERE completed = 1;
+    CREATE TABLE IF NOT EXISTS settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    ",
+    // 2: creation/update timestamps, backfilled for existing rows
+    "
+    ALTER TABLE todos ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE todos ADD COLUMN updated_at INTEGER NOT NULL DEFAULT 0;
+    UPDATE todos
+    SET
+        created_at = COALESCE(completed_at, strftime('%s', 'now')),
+        updated_at = COALESCE(completed_at, strftime('%s', 'now'));
+    ",
+    // 3: due dates and priorities
+    "
+    ALTER TABLE todos ADD COLUMN due_at INTEGER;
+    ALTER TABLE todos ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+    ",
+    // 4: saved views, seeded with a few useful ones
+    "
+    CREATE TABLE IF NOT EXISTS views (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL UNIQUE,
+        query TEXT NOT NULL,
+        sort_mode TEXT NOT NULL,
+        sort_descending BOOLEAN NOT NULL CHECK (sort_descending IN (0, 1))
+    );
+    INSERT OR IGNORE INTO views (name, query, sort_mode, sort_descending) VALUES
+        ('Today', 'status:open due<=today', 'priority', 0),
+        ('Overdue', 'status:open due:overdue', 'due', 0),
+        ('Waiting on others', 'status:open tag:waiting', 'updated', 0);
+    ",
+    // 5: notes and a full-text index over labels and notes kept in sync by triggers
+    "
+    ALTER TABLE todos ADD COLUMN notes TEXT NOT NULL DEFAULT '';
+    CREATE VIRTUAL TABLE IF NOT EXISTS todos_fts USING fts5(
+        label,
+        notes,
+        content = 'todos',
+        content_rowid = 'id',
+        tokenize = 'unicode61 remove_diacritics 2'
+    );
+    CREATE TRIGGER IF NOT EXISTS todos_fts_insert AFTER INSERT ON todos BEGIN
+        INSERT INTO todos_fts (rowid, label, notes) VALUES (new.id, new.label, new.notes);
+    END;
+    CREATE TRIGGER IF NOT EXISTS todos_fts_delete AFTER DELETE ON todos BEGIN
+        INSERT INTO todos_fts (todos_fts, rowid, label, notes)
+        VALUES ('delete', old.id, old.label, old.notes);
+    END;
+    CREATE TRIGGER IF NOT EXISTS todos_fts_update AFTER UPDATE OF label, notes ON todos BEGIN
+        INSERT INTO todos_fts (todos_fts, rowid, label, notes)
+        VALUES ('delete', old.id, old.label, old.notes);
+        INSERT INTO todos_fts (rowid, label, notes) VALUES (new.id, new.label, new.notes);
+    END;
+    INSERT INTO todos_fts (todos_fts) VALUES ('rebuild');
+    ",
+    // 6: manual order, starting out as the creation order
+    "
+    ALTER TABLE todos ADD COLUMN position REAL NOT NULL DEFAULT 0;
+    UPDATE todos SET position = id;
+    ",
+    // 7: row versions, bumped on every write to catch edits made elsewhere
+    "
+    ALTER TABLE todos ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
+    ",
+    // 8: history of changes to each todo, one row per changed field
+    "
+    CREATE TABLE IF NOT EXISTS history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        todo_id INTEGER NOT NULL,
+        changed_at INTEGER NOT NULL,
+        field TEXT NOT NULL,
+        old_value TEXT,
+        new_value TEXT
+    );
+    CREATE INDEX IF NOT EXISTS history_todo_id ON history (todo_id);
+    CREATE TRIGGER IF NOT EXISTS history_insert AFTER INSERT ON todos BEGIN
+        INSERT INTO history (todo_id, changed_at, field, new_value)
+        VALUES (new.id, new.created_at, 'created', new.label);
+    END;
+    CREATE TRIGGER IF NOT EXISTS history_delete AFTER DELETE ON todos BEGIN
+        INSERT INTO history (todo_id, changed_at, field, old_value)
+        VALUES (old.id, strftime('%s', 'now'), 'deleted', old.label);
+    END;
+    CREATE TRIGGER IF NOT EXISTS history_update AFTER UPDATE ON todos BEGIN
+        INSERT INTO history (todo_id, changed_at, field, old_value, new_value)
+        SELECT new.id, strftime('%s', 'now'), 'label', old.label, new.label
+        WHERE old.label IS NOT new.label;
+        INSERT INTO history (todo_id, changed_at, field, old_value, new_value)
+        SELECT new.id, strftime('%s', 'now'), 'completed', old.completed, new.completed
+        WHERE old.completed IS NOT new.completed;
+        INSERT INTO history (todo_id, changed_at, field, old_value, new_value)
+        SELECT new.id, strftime('%s', 'now'), 'priority', old.priority, new.priority
+        WHERE old.priority IS NOT new.priority;
+        INSERT INTO history (todo_id, changed_at, field, old_value, new_value)
+        SELECT new.id, strftime('%s', 'now'), 'due_at', old.due_at, new.due_at
+        WHERE old.due_at IS NOT new.due_at;
+        INSERT INTO history (todo_id, changed_at, field, old_value, new_value)
+        SELECT new.id, strftime('%s', 'now'), 'notes', old.notes, new.notes
+        WHERE old.notes IS NOT new.notes;
+        INSERT INTO history (todo_id, changed_at, field, old_value, new_value)
+        SELECT new.id, strftime('%s', 'now'), 'archived_at', old.archived_at, new.archived_at
+        WHERE old.archived_at IS NOT new.archived_at;
+    END;
+    ",
+    // 9: a trash that deleted todos go to before they are deleted for good
+    "
+    ALTER TABLE todos ADD COLUMN deleted_at INTEGER;
+    CREATE TRIGGER IF NOT EXISTS history_trash AFTER UPDATE OF deleted_at ON todos BEGIN
+        INSERT INTO history (todo_id, changed_at, field, old_value, new_value)
+        SELECT new.id, strftime('%s', 'now'), 'deleted_at', old.deleted_at, new.deleted_at
+        WHERE old.deleted_at IS NOT new.deleted_at;
+    END;
+    ",
+];
+
+// How long a write waits for another process to finish writing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Bumps the version of `todo` after a write that matched `written` rows. No
+// rows means the version in the database moved on.
+fn check_written(written: usize, todo: &mut Todo) -> StoreResult<()> {
+    if written == 0 {
+        return Err(StoreError::Conflict(todo.id));
+    }
+    todo.version += 1;
+    Ok(())
+}
+
+const TODO_COLUMNS: &str = "id, label, completed, completed_at, archived_at, created_at, \
+    updated_at, due_at, priority, notes, position, version, deleted_at";
+
+fn todo_from_row(row: &Row) -> Result<Todo> {
+    Ok(Todo {
+        id: row.get(0)?,
+        label: row.get(1)?,
+        completed: row.get(2)?,
+        completed_at: row.get(3)?,
+        archived_at: row.get(4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+        due_at: row.get(7)?,
+        priority: Priority::from_i64(row.get(8)?),
+        notes: row.get(9)?,
+        position: row.get(10)?,
+        version: row.get(11)?,
+        deleted_at: row.get(12)?,
+    })
+}
+
+fn like_pattern(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+// Translates a query term into a SQL condition, pushing its parameters onto
+// `values`. Returns false alongside conditions that only narrow the rows down
+// and need the term checked again in memory.
+fn term_to_sql(term: &Term, now: i64, values: &mut Vec<Value>) -> (String, bool) {
+    match term {
+        Term::Status(Status::Open) => (String::from("completed = 0"), true),
+        Term::Status(Status::Done) => (String::from("completed = 1"), true),
+        Term::Status(Status::Archived) => (String::from("archived_at IS NOT NULL"), true),
+        // LIKE only ignores ASCII case, so anything else is left to the in-memory check
+        Term::Tag(text) | Term::Text(text) if !text.is_ascii() => (String::from("1"), false),
+        Term::Tag(tag) => {
+            values.push(Value::Text(like_pattern(&format!("#{}", tag))));
+            (String::from("label LIKE ? ESCAPE '\\'"), false)
+        }
+        Term::Text(text) => {
+            values.push(Value::Text(like_pattern(text)));
+            (String::from("label LIKE ? ESCAPE '\\'"), true)
+        }
+        Term::Due(comparison, timestamp) => {
+            values.push(Value::Integer(*timestamp));
+            (
+                format!("(due_at IS NOT NULL AND due_at {} ?)", comparison.sql()),
+                true,
+            )
+        }
+        Term::HasDue(true) => (String::from("due_at IS NOT NULL"), true),
+        Term::HasDue(false) => (String::from("due_at IS NULL"), true),
+        Term::Priority(comparison, priority) => {
+            values.push(Value::Integer(priority.as_i64()));
+            (format!("priority {} ?", comparison.sql()), true)
+        }
+        Term::Age(field, comparison, seconds) => {
+            let column = match field {
+                AgeField::Created => "created_at",
+                AgeField::Updated => "updated_at",
+                AgeField::Completed => "completed_at",
+            };
+            values.push(Value::Integer(now));
+            values.push(Value::Integer(*seconds));
+            (
+                format!(
+                    "({column} IS NOT NULL AND (? - {column}) {} ?)",
+                    comparison.sql()
+                ),
+                true,
+            )
+        }
+        Term::Not(term) => {
+            let mut inner_values = vec![];
+            match term_to_sql(term, now, &mut inner_values) {
+                (condition, true) => {
+                    values.append(&mut inner_values);
+                    (format!("NOT ({})", condition), true)
+                }
+                // The negation of a loose condition would drop matching rows
+                (_, false) => (String::from("1"), false),
+            }
+        }
+    }
+}
 
 pub struct TodoDb {
-    pub conn: Connection,
+    conn: Connection,
+    backup_dir: PathBuf,
 }
 
 impl TodoDb {
     pub fn new(url: &str) -> Result<Self> {
-        let conn = Connection::open(url)?;
+        let existed = Path::new(url).exists();
+        let mut conn = Connection::open(url)?;
+        let backup_dir = Path::new(url).with_file_name(BACKUP_DIR);
 
         let query = "
             CREATE TABLE IF NOT EXISTS todos (
@@ -19,86 +266,479 @@ impl TodoDb {
                 completed BOOLEAN NOT NULL CHECK (completed IN (0, 1))
             )";
 
-        match conn.execute(&query, ()) {
-            Ok(_) => println!("Successfully created todos table"),
-            Err(err) => eprintln!("Error: unable to create todos table {}", err),
-        };
+        // Only failures are reported, on stderr, so CLI output stays clean
+        if let Err(err) = conn.execute(query, ()) {
+            eprintln!("Error: unable to create todos table {}", err);
+        }
 
-        Ok(Self { conn })
+        // WAL lets other processes read while one writes, and the timeout
+        // makes writers wait for each other instead of failing right away
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        // A failed migration can't be undone by hand, so keep a copy first
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if existed && version < MIGRATIONS.len() {
+            let reason = format!("before-v{}", MIGRATIONS.len());
+            backup::create(&conn, &backup_dir, Some(&reason))?;
+        }
+        Self::migrate(&mut conn)?;
+
+        Ok(Self { conn, backup_dir })
     }
 
-    pub fn get_all_todos(&self) -> Result<HashMap<i64, Todo>> {
-        let query = "SELECT * FROM todos";
-        let mut stmt = self.conn.prepare(&query)?;
-        let todos = stmt.query_map([], |row| {
-            Ok(Todo {
+    // Runs `write` in a transaction that takes the write lock up front, unless
+    // it is part of one that is already open
+    fn immediate<T>(&mut self, write: impl FnOnce(&Connection) -> Result<T>) -> StoreResult<T> {
+        if !self.conn.is_autocommit() {
+            return Ok(write(&self.conn)?);
+        }
+        self.conn.execute_batch("BEGIN IMMEDIATE")?;
+        match write(&self.conn) {
+            Ok(value) => {
+                self.conn.execute_batch("COMMIT")?;
+                Ok(value)
+            }
+            Err(err) => {
+                let _ = self.conn.execute_batch("ROLLBACK");
+                Err(err.into())
+            }
+        }
+    }
+
+    fn migrate(conn: &mut Connection) -> Result<()> {
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", index + 1)?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+}
+
+impl TodoStore for TodoDb {
+    // Changes whenever another connection commits to the database
+    fn data_version(&mut self) -> StoreResult<i64> {
+        Ok(self
+            .conn
+            .query_row("PRAGMA data_version", [], |row| row.get(0))?)
+    }
+
+    fn get_setting(&self, key: &str) -> Option<String> {
+        let query = "SELECT value FROM settings WHERE key = (?1)";
+        self.conn
+            .query_row(query, [key], |row| row.get(0))
+            .optional()
+            .unwrap_or_default()
+    }
+
+    fn set_setting(&mut self, key: &str, value: &str) -> StoreResult<()> {
+        let query = "
+            INSERT INTO settings (key, value) VALUES (?1, ?2)
+            ON CONFLICT(key) DO UPDATE SET value = excluded.value
+        ";
+        self.conn.execute(query, [key, value])?;
+        Ok(())
+    }
+
+    fn delete_setting(&mut self, key: &str) -> StoreResult<()> {
+        let query = "DELETE FROM settings WHERE key = (?1)";
+        self.conn.execute(query, [key])?;
+        Ok(())
+    }
+
+    fn get_views(&self) -> StoreResult<Vec<SavedView>> {
+        let query = "SELECT id, name, query, sort_mode, sort_descending FROM views ORDER BY id";
+        let mut stmt = self.conn.prepare(query)?;
+        let views = stmt.query_map([], |row| {
+            Ok(SavedView {
                 id: row.get(0)?,
-                label: row.get(1)?,
-                completed: row.get(2)?,
+                name: row.get(1)?,
+                query: row.get(2)?,
+                sort: Sort {
+                    mode: SortMode::from_name(&row.get::<_, String>(3)?).unwrap_or_default(),
+                    descending: row.get(4)?,
+                },
             })
         })?;
+        Ok(views.collect::<Result<_>>()?)
+    }
 
-        let todos: HashMap<i64, Todo> = todos
-            .map(|todo| {
-                let todo = todo.unwrap();
-                (todo.id, todo)
+    fn add_view(&mut self, name: &str, query: &str, sort: Sort) -> StoreResult<SavedView> {
+        let sql = "
+            INSERT INTO views (name, query, sort_mode, sort_descending)
+            VALUES(?1, ?2, ?3, ?4)
+        ";
+        self.conn
+            .execute(sql, params![name, query, sort.mode.name(), sort.descending])?;
+        Ok(SavedView {
+            id: self.conn.last_insert_rowid(),
+            name: name.to_string(),
+            query: query.to_string(),
+            sort,
+        })
+    }
+
+    fn update_view(&mut self, view: &SavedView) -> StoreResult<()> {
+        let sql = "
+            UPDATE views
+            SET name = (?1), query = (?2), sort_mode = (?3), sort_descending = (?4)
+            WHERE
+                id = (?5)
+        ";
+        self.conn.execute(
+            sql,
+            params![
+                view.name,
+                view.query,
+                view.sort.mode.name(),
+                view.sort.descending,
+                view.id
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn delete_view(&mut self, id: i64) -> StoreResult<()> {
+        let query = "DELETE FROM views WHERE id = (?1)";
+        self.conn.execute(query, [id])?;
+        Ok(())
+    }
+
+    fn get_history(&self, id: Option<i64>) -> StoreResult<Vec<HistoryEntry>> {
+        let query = "
+            SELECT todo_id, changed_at, field, old_value, new_value
+            FROM history
+            WHERE (?1) IS NULL OR todo_id = (?1)
+            ORDER BY id DESC
+        ";
+        let mut stmt = self.conn.prepare(query)?;
+        let entries = stmt.query_map([id], |row| {
+            Ok(HistoryEntry {
+                todo_id: row.get(0)?,
+                changed_at: row.get(1)?,
+                field: row.get(2)?,
+                old_value: row.get(3)?,
+                new_value: row.get(4)?,
             })
-            .collect();
+        })?;
+        Ok(entries.collect::<Result<_>>()?)
+    }
 
-        Ok(todos)
+    fn get_all_todos(&self) -> StoreResult<HashMap<i64, Todo>> {
+        let query = format!(
+            "SELECT {} FROM todos WHERE deleted_at IS NULL",
+            TODO_COLUMNS
+        );
+        let mut stmt = self.conn.prepare(&query)?;
+        let todos = stmt.query_map([], todo_from_row)?;
+
+        let mut by_id = HashMap::new();
+        for todo in todos {
+            let todo = todo?;
+            by_id.insert(todo.id, todo);
+        }
+        Ok(by_id)
+    }
+
+    fn get_trash(&self) -> StoreResult<Vec<Todo>> {
+        let query = format!(
+            "SELECT {} FROM todos WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC, id DESC",
+            TODO_COLUMNS
+        );
+        let mut stmt = self.conn.prepare(&query)?;
+        let todos = stmt.query_map([], todo_from_row)?;
+        Ok(todos.collect::<Result<_>>()?)
     }
 
-    pub fn add_todo(&self, todo: &str) -> Option<i64> {
-        let query = "INSERT INTO todos (label, completed) VALUES(?1, ?2)";
-        if let Ok(mut stmt) = self.conn.prepare(query) {
-            let res = stmt.execute([todo, "0"]);
-            if res.is_ok() {
-                return Some(self.conn.last_insert_rowid());
+    // Filters in SQL as far as possible and checks the rest in memory
+    fn find_todos(&self, query: &Query, now: i64) -> StoreResult<Vec<Todo>> {
+        let mut conditions = vec![String::from("deleted_at IS NULL")];
+        let mut values = vec![];
+        let mut exact = true;
+        for term in &query.terms {
+            let (condition, term_exact) = term_to_sql(term, now, &mut values);
+            conditions.push(condition);
+            exact &= term_exact;
+        }
+        if !query.includes_archived() {
+            conditions.push(String::from("archived_at IS NULL"));
+        }
+
+        let sql = format!(
+            "SELECT {} FROM todos WHERE {} ORDER BY id",
+            TODO_COLUMNS,
+            conditions.join(" AND ")
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let todos = stmt.query_map(params_from_iter(values), todo_from_row)?;
+
+        let mut matching = vec![];
+        for todo in todos {
+            let todo = todo?;
+            if exact || query.matches(&todo, now) {
+                matching.push(todo);
             }
+        }
+        Ok(matching)
+    }
+
+    fn search(&self, input: &str, limit: usize) -> StoreResult<Vec<SearchHit>> {
+        let fts_query = search::fts_query(input);
+        if fts_query.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let query = "
+            SELECT
+                rowid,
+                snippet(todos_fts, 0, char(1), char(2), '...', 12),
+                snippet(todos_fts, 1, char(1), char(2), '...', 12)
+            FROM todos_fts
+            WHERE
+                todos_fts MATCH (?1)
+                AND rowid IN (SELECT id FROM todos WHERE deleted_at IS NULL)
+            ORDER BY rank
+            LIMIT (?2)
+        ";
+        let mut stmt = self.conn.prepare(query)?;
+        let hits = stmt.query_map(params![fts_query, limit as i64], |row| {
+            Ok(SearchHit {
+                id: row.get(0)?,
+                label: row.get(1)?,
+                notes: row.get(2)?,
+            })
+        })?;
+        Ok(hits.collect::<Result<_>>()?)
+    }
+
+    fn add_todo_with(&mut self, todo: &Todo, position: Option<f64>) -> StoreResult<Todo> {
+        let query = "
+            INSERT INTO todos (
+                label, completed, completed_at, created_at, updated_at, due_at, priority,
+                notes, position
+            )
+            VALUES(
+                ?1, ?2, ?3, ?4, ?4, ?5, ?6, ?7,
+                COALESCE(?8, (SELECT COALESCE(MAX(position), 0) + 1 FROM todos))
+            )
+            RETURNING position
+        ";
+        let created_at = now();
+        let completed_at = todo.completed.then_some(created_at);
+        let position = self.conn.query_row(
+            query,
+            params![
+                todo.label,
+                todo.completed,
+                completed_at,
+                created_at,
+                todo.due_at,
+                todo.priority.as_i64(),
+                todo.notes,
+                position
+            ],
+            |row| row.get(0),
+        )?;
+        let mut added = Todo::new(self.conn.last_insert_rowid(), todo.label.clone());
+        added.completed = todo.completed;
+        added.completed_at = completed_at;
+        added.created_at = created_at;
+        added.updated_at = created_at;
+        added.due_at = todo.due_at;
+        added.priority = todo.priority;
+        added.notes = todo.notes.clone();
+        added.position = position;
+        Ok(added)
+    }
+
+    fn set_position(&mut self, todo: &mut Todo, position: f64) -> StoreResult<()> {
+        let query = "
+            UPDATE todos
+            SET position = (?1), version = version + 1
+            WHERE
+                id = (?2) AND version = (?3)
+        ";
+        let written = self
+            .conn
+            .execute(query, params![position, todo.id, todo.version])?;
+        check_written(written, todo)?;
+        todo.position = position;
+        Ok(())
+    }
+
+    fn toggle_todo(&mut self, todo: &mut Todo) -> StoreResult<()> {
+        let query = "
+            UPDATE todos
+            SET
+                completed = (?1), completed_at = (?2), updated_at = (?3),
+                version = version + 1
+            WHERE
+                id = (?4) AND version = (?5)
+        ";
+        let updated_at = now();
+        let completed_at = if todo.completed {
+            Some(updated_at)
+        } else {
+            None
         };
-        None
+        let written = self.conn.execute(
+            query,
+            params![
+                todo.completed,
+                completed_at,
+                updated_at,
+                todo.id,
+                todo.version
+            ],
+        )?;
+        check_written(written, todo)?;
+        todo.completed_at = completed_at;
+        todo.updated_at = updated_at;
+        Ok(())
     }
 
-    pub fn toggle_todo(&self, id: i64, completed: bool) {
+    fn set_archived(&mut self, todo: &mut Todo, archived: bool) -> StoreResult<()> {
         let query = "
             UPDATE todos
-            SET completed = (?1)
+            SET archived_at = (?1), version = version + 1
             WHERE
-                id = (?2)
+                id = (?2) AND version = (?3)
         ";
-        if let Ok(mut stmt) = self.conn.prepare(query) {
-            let _ = stmt.execute([
-                {
-                    if completed {
-                        1
-                    } else {
-                        0
-                    }
-                },
-                id,
-            ]);
-        }
+        let archived_at = if archived { Some(now()) } else { None };
+        let written = self
+            .conn
+            .execute(query, params![archived_at, todo.id, todo.version])?;
+        check_written(written, todo)?;
+        todo.archived_at = archived_at;
+        Ok(())
+    }
+
+    fn archive_completed(&mut self, completed_before: i64) -> StoreResult<usize> {
+        let query = "
+            UPDATE todos
+            SET archived_at = (?1), version = version + 1
+            WHERE
+                completed = 1
+                AND archived_at IS NULL
+                AND deleted_at IS NULL
+                AND completed_at <= (?2)
+        ";
+        self.immediate(|conn| conn.execute(query, params![now(), completed_before]))
     }
 
-    pub fn delete_todo(&self, id: i64) {
+    fn purge_archived(&mut self, archived_before: i64) -> StoreResult<usize> {
         let query = "
             DELETE FROM todos
-            WHERE id = (?1)
+            WHERE
+                archived_at IS NOT NULL
+                AND archived_at < (?1)
         ";
-        if let Ok(mut stmt) = self.conn.prepare(query) {
-            let _ = stmt.execute([id]);
-        }
+        self.immediate(|conn| conn.execute(query, [archived_before]))
     }
 
-    pub fn update_todo(&self, id: i64, label: &str) {
+    fn set_deleted(&mut self, todo: &mut Todo, deleted: bool) -> StoreResult<()> {
         let query = "
             UPDATE todos
-            SET label = (?1)
+            SET deleted_at = (?1), version = version + 1
             WHERE
-                id = (?2)
+                id = (?2) AND version = (?3)
+        ";
+        let deleted_at = if deleted { Some(now()) } else { None };
+        let written = self
+            .conn
+            .execute(query, params![deleted_at, todo.id, todo.version])?;
+        check_written(written, todo)?;
+        todo.deleted_at = deleted_at;
+        Ok(())
+    }
+
+    fn delete_todo(&mut self, todo: &Todo) -> StoreResult<()> {
+        let query = "
+            DELETE FROM todos
+            WHERE id = (?1) AND version = (?2)
         ";
-        if let Ok(mut stmt) = self.conn.prepare(query) {
-            let _ = stmt.execute([label, &id.to_string()]);
+        let written = self.conn.execute(query, params![todo.id, todo.version])?;
+        if written == 0 {
+            return Err(StoreError::Conflict(todo.id));
         }
+        Ok(())
+    }
+
+    fn empty_trash(&mut self, deleted_before: i64) -> StoreResult<usize> {
+        let query = "
+            DELETE FROM todos
+            WHERE
+                deleted_at IS NOT NULL
+                AND deleted_at < (?1)
+        ";
+        self.immediate(|conn| conn.execute(query, [deleted_before]))
+    }
+
+    fn update_todo(&mut self, todo: &mut Todo) -> StoreResult<()> {
+        let query = "
+            UPDATE todos
+            SET
+                label = (?1), completed = (?2), completed_at = (?3), due_at = (?4),
+                priority = (?5), notes = (?6), updated_at = (?7), version = version + 1
+            WHERE
+                id = (?8) AND version = (?9)
+        ";
+        let updated_at = now();
+        let written = self.conn.execute(
+            query,
+            params![
+                todo.label,
+                todo.completed,
+                todo.completed_at,
+                todo.due_at,
+                todo.priority.as_i64(),
+                todo.notes,
+                updated_at,
+                todo.id,
+                todo.version
+            ],
+        )?;
+        check_written(written, todo)?;
+        todo.updated_at = updated_at;
+        Ok(())
+    }
+
+    // Waits for other writers up to the busy timeout
+    fn begin(&mut self) -> StoreResult<()> {
+        Ok(self.conn.execute_batch("BEGIN IMMEDIATE")?)
+    }
+
+    fn commit(&mut self) -> StoreResult<()> {
+        Ok(self.conn.execute_batch("COMMIT")?)
+    }
+
+    fn rollback(&mut self) {
+        let _ = self.conn.execute_batch("ROLLBACK");
+    }
+
+    fn backup_dir(&self) -> Option<&Path> {
+        Some(&self.backup_dir)
+    }
+
+    // Snapshots the database with the online backup API, so the copy is
+    // consistent even while other processes write
+    fn backup(&self, reason: Option<&str>) -> StoreResult<PathBuf> {
+        Ok(backup::create(&self.conn, &self.backup_dir, reason)?)
+    }
+
+    fn verify_backup(&self, path: &Path) -> std::result::Result<(), String> {
+        backup::verify(path)
+    }
+
+    // Other processes see the restore like any other write. Older backups are
+    // brought up to the current schema.
+    fn restore(&mut self, path: &Path) -> StoreResult<()> {
+        self.conn
+            .restore(DatabaseName::Main, path, None::<fn(Progress)>)?;
+        self.conn
+            .pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        Ok(Self::migrate(&mut self.conn)?)
     }
 }
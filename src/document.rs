@@ -0,0 +1,348 @@
+use std::collections::HashSet;
+
+use crate::{
+    time,
+    todo::{Priority, Todo},
+};
+
+// Fields of a todo as edited in a front-matter document
+#[derive(Debug, Clone, PartialEq)]
+pub struct TodoFields {
+    pub label: String,
+    pub completed: bool,
+    pub priority: Priority,
+    pub due_at: Option<i64>,
+    pub notes: String,
+}
+
+const FENCE: &str = "---";
+
+// Keys shown for reference that can't be changed
+const READ_ONLY_KEYS: &[&str] = &["id", "created", "updated", "completed"];
+
+// Writes `todo` as a document with its fields between `---` lines and the
+// notes below them, for example:
+//
+//     ---
+//     id: 12
+//     label: Renew passport
+//     status: open
+//     priority: high
+//     due: 2024-06-01
+//     created: 2024-05-02 09:30
+//     updated: 2024-05-02 09:30
+//     ---
+//     Photos are in the drawer.
+pub fn todo_document(todo: &Todo, datetime_format: &str) -> String {
+    let mut document = format!(
+        "{FENCE}\nid: {}\nlabel: {}\nstatus: {}\npriority: {}\ndue: {}\n",
+        todo.id,
+        todo.label,
+        if todo.completed { "done" } else { "open" },
+        todo.priority.label(),
+        todo.due_at.map(time::format_date).unwrap_or_default(),
+    );
+    document.push_str(&format!(
+        "created: {}\nupdated: {}\n",
+        time::format(todo.created_at, datetime_format),
+        time::format(todo.updated_at, datetime_format)
+    ));
+    if let Some(completed_at) = todo.completed_at {
+        document.push_str(&format!(
+            "completed: {}\n",
+            time::format(completed_at, datetime_format)
+        ));
+    }
+    document.push_str(FENCE);
+    document.push('\n');
+    if !todo.notes.is_empty() {
+        document.push_str(&todo.notes);
+        document.push('\n');
+    }
+    document
+}
+
+// Reads back a document written by `todo_document` for todo `id`. Errors
+// name the line they are about.
+pub fn parse_todo_document(document: &str, id: i64, now: i64) -> Result<TodoFields, String> {
+    let mut lines = document
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line));
+    match lines.next() {
+        Some((_, line)) if line.trim() == FENCE => {}
+        _ => return Err(format!("line 1: expected '{}' to start the fields", FENCE)),
+    }
+
+    let mut fields = TodoFields {
+        label: String::new(),
+        completed: false,
+        priority: Priority::None,
+        due_at: None,
+        notes: String::new(),
+    };
+    let mut seen = HashSet::new();
+    let mut closed = false;
+    for (number, line) in lines.by_ref() {
+        if line.trim() == FENCE {
+            closed = true;
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (key, value) = line
+            .split_once(':')
+            .ok_or_else(|| format!("line {}: expected 'key: value'", number))?;
+        let (key, value) = (key.trim(), value.trim());
+        if !seen.insert(key.to_string()) {
+            return Err(format!("line {}: '{}' is given twice", number, key));
+        }
+        match key {
+            "label" => fields.label = value.to_string(),
+            "status" => {
+                fields.completed = match value {
+                    "open" => false,
+                    "done" => true,
+                    _ => {
+                        return Err(format!(
+                            "line {}: unknown status '{}', expected open or done",
+                            number, value
+                        ))
+                    }
+                }
+            }
+            "priority" => {
+                fields.priority = Priority::from_label(value).ok_or_else(|| {
+                    format!(
+                        "line {}: unknown priority '{}', expected none, low, medium or high",
+                        number, value
+                    )
+                })?
+            }
+            "due" => {
+                fields.due_at = time::parse_due(value, now)
+                    .map_err(|err| format!("line {}: {}", number, err))?
+            }
+            "id" if value.trim_start_matches('#') != id.to_string() => {
+                return Err(format!("line {}: the id can't be changed", number))
+            }
+            key if READ_ONLY_KEYS.contains(&key) => {}
+            _ => return Err(format!("line {}: unknown field '{}'", number, key)),
+        }
+    }
+    if !closed {
+        return Err(format!("expected '{}' to end the fields", FENCE));
+    }
+    if fields.label.is_empty() {
+        return Err(String::from("the label can't be empty"));
+    }
+
+    let notes: Vec<&str> = lines.map(|(_, line)| line).collect();
+    fields.notes = notes.join("\n").trim().to_string();
+    Ok(fields)
+}
+
+// A line of the list document. New todos have no id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListLine {
+    pub id: Option<i64>,
+    pub completed: bool,
+    pub label: String,
+}
+
+const LIST_HEADER: &str = "\
+# One todo per line as '<id> [ ] label', with [x] for done ones.
+# Add lines without an id for new todos and remove lines to delete todos.
+# Lines starting with '# ' are ignored.
+";
+
+fn is_comment(line: &str) -> bool {
+    line == "#" || line.starts_with("# ")
+}
+
+// Writes `todos` one per line, like "12 [x] Renew passport"
+pub fn list_document<'a>(todos: impl IntoIterator<Item = &'a Todo>) -> String {
+    let mut document = String::from(LIST_HEADER);
+    for todo in todos {
+        document.push_str(&format!(
+            "{} [{}] {}\n",
+            todo.id,
+            if todo.completed { "x" } else { " " },
+            todo.label
+        ));
+    }
+    document
+}
+
+// Reads back a list document. Lines may leave out the id for new todos and
+// the checkbox for open ones.
+pub fn parse_list_document(document: &str) -> Result<Vec<ListLine>, String> {
+    let mut lines = vec![];
+    let mut ids = HashSet::new();
+    for (index, line) in document.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || is_comment(line) {
+            continue;
+        }
+        let (id, rest) = match line.split_once(' ') {
+            // Only a number in front of a checkbox is an id, so labels such
+            // as "3 apples" stay intact
+            Some((id, rest))
+                if !id.is_empty()
+                    && id.chars().all(|c| c.is_ascii_digit())
+                    && rest.trim_start().starts_with('[') =>
+            {
+                let id: i64 = id
+                    .parse()
+                    .map_err(|_| format!("line {}: invalid id '{}'", number, id))?;
+                if !ids.insert(id) {
+                    return Err(format!("line {}: todo {} is listed twice", number, id));
+                }
+                (Some(id), rest.trim_start())
+            }
+            _ => (None, line),
+        };
+        let (completed, label) = match rest.get(..3) {
+            Some("[ ]") => (false, &rest[3..]),
+            Some("[x]" | "[X]") => (true, &rest[3..]),
+            _ => (false, rest),
+        };
+        let label = label.trim();
+        if label.is_empty() {
+            return Err(format!("line {}: the label can't be empty", number));
+        }
+        lines.push(ListLine {
+            id,
+            completed,
+            label: label.to_string(),
+        });
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn todo() -> Todo {
+        let mut todo = Todo::new(12, String::from("Renew passport"));
+        todo.priority = Priority::High;
+        todo.due_at = time::parse_due("2024-06-01", NOW).unwrap();
+        todo.notes = String::from("Photos are in the drawer.\n\n- old passport");
+        todo.created_at = NOW;
+        todo.updated_at = NOW;
+        todo
+    }
+
+    #[test]
+    fn todo_documents_read_back_as_written() {
+        let todo = todo();
+        let document = todo_document(&todo, "%Y-%m-%d %H:%M");
+        assert_eq!(
+            parse_todo_document(&document, todo.id, NOW),
+            Ok(TodoFields {
+                label: todo.label.clone(),
+                completed: false,
+                priority: Priority::High,
+                due_at: todo.due_at,
+                notes: todo.notes.clone(),
+            })
+        );
+
+        let mut done = todo;
+        done.toggle();
+        done.completed_at = Some(NOW);
+        done.due_at = None;
+        done.notes.clear();
+        let fields = parse_todo_document(&todo_document(&done, "%c"), done.id, NOW).unwrap();
+        assert!(fields.completed);
+        assert_eq!(fields.due_at, None);
+        assert_eq!(fields.notes, "");
+    }
+
+    #[test]
+    fn todo_documents_edits_are_checked() {
+        let document = todo_document(&todo(), "%Y-%m-%d");
+        let edited =
+            |from: &str, to: &str| parse_todo_document(&document.replacen(from, to, 1), 12, NOW);
+        assert_eq!(
+            edited("label: Renew passport", "label: Renew ID")
+                .unwrap()
+                .label,
+            "Renew ID"
+        );
+        assert_eq!(
+            edited("status: open", "status: done").map(|fields| fields.completed),
+            Ok(true)
+        );
+        assert_eq!(edited("due: 2024-06-01", "due:").unwrap().due_at, None);
+        // The read-only fields are ignored
+        assert!(edited("created: ", "created: yesterday ").is_ok());
+
+        assert_eq!(
+            edited("id: 12", "id: 13"),
+            Err(String::from("line 2: the id can't be changed"))
+        );
+        assert!(edited("status: open", "status: maybe")
+            .unwrap_err()
+            .starts_with("line 4:"));
+        assert!(edited("priority: high", "priority: urgent")
+            .unwrap_err()
+            .starts_with("line 5:"));
+        assert!(edited("label: Renew passport", "label:").is_err());
+        assert!(edited("label: Renew passport", "colour: red").is_err());
+        assert!(edited("priority: high", "label: twice").is_err());
+        assert!(edited("---\n", "").is_err());
+        assert!(parse_todo_document("---\nlabel: unclosed\n", 12, NOW).is_err());
+    }
+
+    #[test]
+    fn list_documents_read_back_as_written() {
+        let mut done = Todo::new(3, String::from("3 apples"));
+        done.toggle();
+        let open = Todo::new(14, String::from("[draft] reply to Sam"));
+        let document = list_document([&done, &open]);
+        assert_eq!(
+            parse_list_document(&document),
+            Ok(vec![
+                ListLine {
+                    id: Some(3),
+                    completed: true,
+                    label: String::from("3 apples"),
+                },
+                ListLine {
+                    id: Some(14),
+                    completed: false,
+                    label: String::from("[draft] reply to Sam"),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn list_documents_take_new_lines() {
+        let lines =
+            parse_list_document("# comment\n\n3 apples\n[x] done already\n7 [X] kept\n").unwrap();
+        let lines: Vec<_> = lines
+            .iter()
+            .map(|line| (line.id, line.completed, line.label.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (None, false, "3 apples"),
+                (None, true, "done already"),
+                (Some(7), true, "kept"),
+            ]
+        );
+        assert_eq!(
+            parse_list_document("1 [ ] a\n1 [x] b\n"),
+            Err(String::from("line 2: todo 1 is listed twice"))
+        );
+        assert!(parse_list_document("2 [ ]  \n").is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use std::{
+    collections::hash_map::RandomState,
+    env,
+    fs::{self, File, OpenOptions},
+    hash::{BuildHasher, Hasher},
+    io::{self, Write},
+    path::PathBuf,
+    process::Command,
+};
+
+// The editor from $VISUAL or $EDITOR, falling back to vi
+pub fn command() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| String::from("vi"))
+}
+
+// Names tried before giving up on creating a temporary file
+const TEMP_ATTEMPTS: usize = 100;
+
+// A file in the temporary directory, removed when dropped
+struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    // Creates a file nobody else can read under a random name. It has to be
+    // new, so a file or link planted under that name is never written to.
+    fn create(extension: &str) -> io::Result<(Self, File)> {
+        for _ in 0..TEMP_ATTEMPTS {
+            let random = RandomState::new().build_hasher().finish();
+            let path = env::temp_dir().join(format!("todo-rs-{:016x}.{}", random, extension));
+            let mut options = OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            match options.open(&path) {
+                Ok(file) => return Ok((Self { path }, file)),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "no free name for a temporary file",
+        ))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Opens `text` in the editor and returns it as saved. The caller must give up
+// the terminal first. The file name ends in `extension` so editors pick a
+// fitting syntax.
+pub fn edit(text: &str, extension: &str) -> io::Result<String> {
+    let (temp, mut file) = TempFile::create(extension)?;
+    file.write_all(text.as_bytes())?;
+    drop(file);
+    let editor = command();
+    // Through the shell, so editors given with arguments like "code -w" work
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&temp.path)
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "{} exited with {}",
+            editor, status
+        )));
+    }
+    fs::read_to_string(&temp.path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temporary_files_are_private_and_removed() {
+        let (temp, _) = TempFile::create("md").unwrap();
+        let path = temp.path.clone();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        // Taken names are never opened again
+        let (other, _) = TempFile::create("md").unwrap();
+        assert_ne!(other.path, path);
+        drop(temp);
+        assert!(!path.exists());
+    }
+}
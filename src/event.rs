@@ -1,66 +1,199 @@
 use crossterm::event::{self, Event as CrosstermEvent, KeyEvent, MouseEvent};
+#[cfg(unix)]
+use signal_hook::{
+    consts::{SIGCONT, SIGHUP, SIGTERM, SIGTSTP},
+    iterator::{backend::Handle, Signals},
+};
 use std::{
-    sync::mpsc::{self, RecvError},
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvError},
+        Arc,
+    },
     thread,
     time::{Duration, Instant},
 };
 
-#[derive(Clone, Copy, Debug)]
+// How often a paused input thread checks whether it may read again
+const PAUSE_POLL: Duration = Duration::from_millis(10);
+
+#[derive(Clone, Debug)]
 pub enum Event {
     Tick,
     Key(KeyEvent),
     Mouse(MouseEvent),
     Resize(u16, u16),
+    // Text pasted into the terminal, delivered in one piece by bracketed paste
+    Paste(String),
+    // SIGTSTP from outside, like `kill -TSTP`. Ctrl-Z arrives as a key in raw mode.
+    Suspend,
+    // SIGCONT after the process was stopped from outside, like `kill -STOP`.
+    // A suspend from here takes the terminal back by itself.
+    Resume,
+    // SIGTERM or SIGHUP
+    Terminate,
+    // Reading the terminal failed. No more input events follow.
+    Error(String),
+}
+
+// Flags shared with the input thread
+#[derive(Debug, Default)]
+struct InputState {
+    stopped: AtomicBool,
+    paused: AtomicBool,
+    // Set while the thread may be inside `event::poll` or `event::read`
+    reading: AtomicBool,
 }
 
 #[derive(Debug)]
-pub struct EventHandler(
-    mpsc::Sender<Event>,
-    mpsc::Receiver<Event>,
-    thread::JoinHandle<()>,
-);
+pub struct EventHandler {
+    _sender: mpsc::Sender<Event>,
+    receiver: mpsc::Receiver<Event>,
+    state: Arc<InputState>,
+    #[cfg(unix)]
+    signals: Handle,
+    input_thread: Option<thread::JoinHandle<()>>,
+    signal_thread: Option<thread::JoinHandle<()>>,
+}
+
+fn read_input(sender: &mpsc::Sender<Event>, state: &InputState, tick_rate: Duration) {
+    let mut last_tick = Instant::now();
+    while !state.stopped.load(Ordering::SeqCst) {
+        // Announce the read before checking for a pause, so `pause` never
+        // returns while a read is about to start
+        state.reading.store(true, Ordering::SeqCst);
+        if state.paused.load(Ordering::SeqCst) {
+            state.reading.store(false, Ordering::SeqCst);
+            thread::sleep(PAUSE_POLL);
+            last_tick = Instant::now();
+            continue;
+        }
+
+        let timeout = tick_rate
+            .checked_sub(last_tick.elapsed())
+            .unwrap_or(tick_rate);
+        let event = match event::poll(timeout) {
+            Ok(true) => event::read().map(|event| match event {
+                CrosstermEvent::Key(e) if e.kind == event::KeyEventKind::Press => {
+                    Some(Event::Key(e))
+                }
+                CrosstermEvent::Mouse(e) => Some(Event::Mouse(e)),
+                CrosstermEvent::Resize(w, h) => Some(Event::Resize(w, h)),
+                CrosstermEvent::Paste(text) => Some(Event::Paste(text)),
+                _ => None,
+            }),
+            Ok(false) => Ok(None),
+            Err(err) => Err(err),
+        };
+        state.reading.store(false, Ordering::SeqCst);
+
+        let sent = match event {
+            Ok(Some(event)) => sender.send(event),
+            Ok(None) => Ok(()),
+            Err(err) => {
+                let _ = sender.send(Event::Error(err.to_string()));
+                return;
+            }
+        };
+        // The receiver is gone, so nobody is listening any more
+        if sent.is_err() {
+            return;
+        }
+
+        if last_tick.elapsed() >= tick_rate {
+            if sender.send(Event::Tick).is_err() {
+                return;
+            }
+            last_tick = Instant::now();
+        }
+    }
+}
+
+// Turns job control and termination signals into events
+#[cfg(unix)]
+fn watch_signals(
+    sender: mpsc::Sender<Event>,
+) -> io::Result<(Handle, Option<thread::JoinHandle<()>>)> {
+    let mut signals = Signals::new([SIGTSTP, SIGCONT, SIGTERM, SIGHUP])?;
+    let handle = signals.handle();
+    let thread = thread::spawn(move || {
+        for signal in signals.forever() {
+            let event = match signal {
+                SIGTSTP => Event::Suspend,
+                SIGCONT => Event::Resume,
+                _ => Event::Terminate,
+            };
+            if sender.send(event).is_err() {
+                break;
+            }
+        }
+    });
+    Ok((handle, Some(thread)))
+}
 
 impl EventHandler {
-    pub fn new(tick_rate: u64) -> Self {
+    pub fn new(tick_rate: u64) -> io::Result<Self> {
         let tick_rate = Duration::from_millis(tick_rate);
         let (sender, receiver) = mpsc::channel();
-        let handler = {
+        let state = Arc::new(InputState::default());
+
+        let input_thread = {
             let sender = sender.clone();
-            thread::spawn(move || {
-                let mut last_tick = Instant::now();
-                loop {
-                    let timeout = tick_rate
-                        .checked_sub(last_tick.elapsed())
-                        .unwrap_or(tick_rate);
-
-                    if event::poll(timeout).expect("Unable to poll for event") {
-                        match event::read().expect("Unable to read event") {
-                            CrosstermEvent::Key(e) => {
-                                if e.kind == event::KeyEventKind::Press {
-                                    sender.send(Event::Key(e))
-                                } else {
-                                    Ok(())
-                                }
-                            }
-                            CrosstermEvent::Mouse(e) => sender.send(Event::Mouse(e)),
-                            CrosstermEvent::Resize(w, h) => sender.send(Event::Resize(w, h)),
-                            _ => Ok(()),
-                        }
-                        .expect("Failed to send terminal events")
-                    }
-
-                    if last_tick.elapsed() >= tick_rate {
-                        sender.send(Event::Tick).expect("Failed to send tick event");
-                        last_tick = Instant::now();
-                    }
-                }
-            })
+            let state = Arc::clone(&state);
+            thread::spawn(move || read_input(&sender, &state, tick_rate))
         };
 
-        Self(sender, receiver, handler)
+        #[cfg(unix)]
+        let (signals, signal_thread) = watch_signals(sender.clone())?;
+        #[cfg(not(unix))]
+        let signal_thread = None;
+
+        Ok(Self {
+            _sender: sender,
+            receiver,
+            state,
+            #[cfg(unix)]
+            signals,
+            input_thread: Some(input_thread),
+            signal_thread,
+        })
     }
 
     pub fn next(&self) -> Result<Event, RecvError> {
-        Ok(self.1.recv()?)
+        self.receiver.recv()
+    }
+
+    // Stops reading the terminal until `resume`, for when another process
+    // such as $EDITOR owns it. Returns once no read is in progress.
+    pub fn pause(&self) {
+        self.state.paused.store(true, Ordering::SeqCst);
+        while self.state.reading.load(Ordering::SeqCst) {
+            thread::sleep(PAUSE_POLL);
+        }
+    }
+
+    pub fn resume(&self) {
+        self.state.paused.store(false, Ordering::SeqCst);
+    }
+
+    // Stops both threads and waits for them. The input thread finishes its
+    // current poll first, which takes at most one tick.
+    pub fn stop(&mut self) {
+        self.state.stopped.store(true, Ordering::SeqCst);
+        #[cfg(unix)]
+        self.signals.close();
+        for thread in [self.input_thread.take(), self.signal_thread.take()]
+            .into_iter()
+            .flatten()
+        {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for EventHandler {
+    fn drop(&mut self) {
+        self.stop();
     }
 }
@@ -0,0 +1,110 @@
+use crate::{time, todo::Todo};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Csv,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 2] = [ExportFormat::Markdown, ExportFormat::Csv];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Csv => "csv",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "md" | "markdown" => Some(ExportFormat::Markdown),
+            "csv" => Some(ExportFormat::Csv),
+            _ => None,
+        }
+    }
+
+    pub fn export<'a>(self, todos: impl IntoIterator<Item = &'a Todo>) -> String {
+        match self {
+            ExportFormat::Markdown => markdown_checklist(todos),
+            ExportFormat::Csv => csv(todos),
+        }
+    }
+}
+
+// One "- [ ] label" line per todo, with notes indented below
+pub fn markdown_checklist<'a>(todos: impl IntoIterator<Item = &'a Todo>) -> String {
+    let mut output = String::new();
+    for todo in todos {
+        output.push_str(&format!(
+            "- [{}] {}\n",
+            if todo.completed { "x" } else { " " },
+            todo.label
+        ));
+        for line in todo.notes.lines() {
+            output.push_str(&format!("  {}\n", line));
+        }
+    }
+    output
+}
+
+// Reads todos back from text, one per line. Markdown list markers and
+// checkboxes are understood, and indented lines under an item become its notes.
+pub fn parse_checklist(text: &str) -> Vec<Todo> {
+    let mut todos: Vec<Todo> = vec![];
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some(todo) = todos.last_mut() {
+                if !todo.notes.is_empty() {
+                    todo.notes.push('\n');
+                }
+                todo.notes.push_str(line.trim());
+                continue;
+            }
+        }
+        let label = line.trim();
+        let label = ["- ", "* ", "+ "]
+            .iter()
+            .find_map(|marker| label.strip_prefix(marker))
+            .unwrap_or(label);
+        let (completed, label) = match label.get(..4) {
+            Some("[ ] ") => (false, &label[4..]),
+            Some("[x] " | "[X] ") => (true, &label[4..]),
+            _ => (false, label),
+        };
+        let mut todo = Todo::new(0, label.trim().to_string());
+        todo.completed = completed;
+        todos.push(todo);
+    }
+    todos
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv<'a>(todos: impl IntoIterator<Item = &'a Todo>) -> String {
+    let mut output = String::from("id,label,completed,priority,due,created,updated,notes\n");
+    for todo in todos {
+        let fields = [
+            todo.id.to_string(),
+            csv_field(&todo.label),
+            todo.completed.to_string(),
+            todo.priority.label().to_string(),
+            todo.due_at.map(time::format_date).unwrap_or_default(),
+            time::format(todo.created_at, "%Y-%m-%dT%H:%M:%S"),
+            time::format(todo.updated_at, "%Y-%m-%dT%H:%M:%S"),
+            csv_field(&todo.notes),
+        ];
+        output.push_str(&fields.join(","));
+        output.push('\n');
+    }
+    output
+}
@@ -0,0 +1,197 @@
+// Subsequence matching for the fuzzy finder, in the spirit of fzf. Every
+// character of the pattern has to appear in the text in order; the score
+// rewards matches at word starts and runs of consecutive characters and
+// penalises gaps, so "wrp" ranks "write report" above "overwrap". Like in
+// fzf, every character of a run keeps the bonus of the word start it began
+// at, so "milk" ranks "buy milk" above "make it look kind".
+
+const SCORE_MATCH: i64 = 16;
+const BONUS_WORD_START: i64 = 10;
+const BONUS_FIRST_CHAR: i64 = 4;
+const BONUS_CONSECUTIVE: i64 = 6;
+const PENALTY_GAP: i64 = 1;
+const MAX_LEADING_PENALTY: i64 = 8;
+
+#[derive(Debug, Clone)]
+pub struct FuzzyMatch {
+    pub id: i64,
+    pub score: i64,
+    // Char indices of the label that matched the pattern
+    pub positions: Vec<usize>,
+}
+
+fn is_word_start(chars: &[char], index: usize) -> bool {
+    match index.checked_sub(1).map(|prev| chars[prev]) {
+        None => true,
+        Some(prev) => {
+            !prev.is_alphanumeric() || (prev.is_lowercase() && chars[index].is_uppercase())
+        }
+    }
+}
+
+fn char_eq(pattern: char, text: char) -> bool {
+    pattern == text || text.to_lowercase().eq(pattern.to_lowercase())
+}
+
+// Returns the score and matched char positions, or None when `pattern` is not
+// a subsequence of `text`. Matching ignores case and whitespace in the pattern.
+pub fn score(pattern: &str, text: &str) -> Option<(i64, Vec<usize>)> {
+    let pattern: Vec<char> = pattern.chars().filter(|c| !c.is_whitespace()).collect();
+    if pattern.is_empty() {
+        return Some((0, vec![]));
+    }
+    let chars: Vec<char> = text.chars().collect();
+
+    // Cheap rejection before doing the full scoring
+    let mut remaining = pattern.iter().peekable();
+    for c in &chars {
+        if remaining.peek().is_some_and(|p| char_eq(**p, *c)) {
+            remaining.next();
+        }
+    }
+    if remaining.peek().is_some() {
+        return None;
+    }
+
+    // best[i][j] is the best score with pattern[i] matched at chars[j],
+    // from[i][j] the position pattern[i - 1] was matched at to get there and
+    // bonus[i][j] the word start bonus of the run it ends
+    let (rows, cols) = (pattern.len(), chars.len());
+    let mut best = vec![vec![None::<i64>; cols]; rows];
+    let mut from = vec![vec![0usize; cols]; rows];
+    let mut bonus = vec![vec![0i64; cols]; rows];
+    for (i, p) in pattern.iter().enumerate() {
+        // Best of best[i - 1][k] + k * PENALTY_GAP for k < j - 1
+        let mut gapped: Option<(i64, usize)> = None;
+        for j in 0..cols {
+            if i > 0 && j >= 2 {
+                if let Some(score) = best[i - 1][j - 2] {
+                    let candidate = score + (j - 2) as i64 * PENALTY_GAP;
+                    if gapped.is_none_or(|(best, _)| candidate > best) {
+                        gapped = Some((candidate, j - 2));
+                    }
+                }
+            }
+            if !char_eq(*p, chars[j]) {
+                continue;
+            }
+
+            let word_start = if is_word_start(&chars, j) {
+                BONUS_WORD_START
+            } else {
+                0
+            };
+            let first_char = if j == 0 { BONUS_FIRST_CHAR } else { 0 };
+            if i == 0 {
+                let leading = (j as i64 * PENALTY_GAP).min(MAX_LEADING_PENALTY);
+                best[i][j] = Some(SCORE_MATCH + word_start + first_char - leading);
+                bonus[i][j] = word_start;
+                continue;
+            }
+
+            let consecutive = j.checked_sub(1).and_then(|k| {
+                let run_bonus = word_start.max(bonus[i - 1][k]);
+                best[i - 1][k].map(|score| (score + BONUS_CONSECUTIVE + run_bonus, k, run_bonus))
+            });
+            let gapped = gapped.map(|(score, k)| {
+                (
+                    score - (j - 1) as i64 * PENALTY_GAP + word_start,
+                    k,
+                    word_start,
+                )
+            });
+            let previous = match (consecutive, gapped) {
+                (Some(a), Some(b)) => Some(if a.0 >= b.0 { a } else { b }),
+                (a, b) => a.or(b),
+            };
+            if let Some((score, k, run_bonus)) = previous {
+                best[i][j] = Some(score + SCORE_MATCH + first_char);
+                from[i][j] = k;
+                bonus[i][j] = run_bonus;
+            }
+        }
+    }
+
+    let (mut j, total) = best[rows - 1]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, score)| score.map(|score| (j, score)))
+        .max_by_key(|(j, score)| (*score, std::cmp::Reverse(*j)))?;
+    let mut positions = vec![0; rows];
+    for i in (0..rows).rev() {
+        positions[i] = j;
+        j = from[i][j];
+    }
+    Some((total, positions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rank<'a>(pattern: &str, texts: &[&'a str]) -> Vec<&'a str> {
+        let mut scored: Vec<(i64, &str)> = texts
+            .iter()
+            .filter_map(|text| score(pattern, text).map(|(score, _)| (score, *text)))
+            .collect();
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        scored.into_iter().map(|(_, text)| text).collect()
+    }
+
+    #[test]
+    fn matches_subsequences_ignoring_case() {
+        assert_eq!(
+            score("WRP", "write report").map(|(_, positions)| positions),
+            Some(vec![0, 1, 8])
+        );
+        assert_eq!(
+            score("w r", "overwrap").map(|(_, positions)| positions),
+            Some(vec![4, 5])
+        );
+        assert!(score("pw", "write report").is_none());
+        assert!(score("x", "").is_none());
+        assert_eq!(score(" ", "anything"), Some((0, vec![])));
+    }
+
+    #[test]
+    fn word_starts_rank_above_the_middle_of_words() {
+        assert_eq!(
+            rank("wrp", &["overwrap", "write report"]),
+            ["write report", "overwrap"]
+        );
+        assert_eq!(
+            rank("bm", &["submarine", "buy milk"]),
+            ["buy milk", "submarine"]
+        );
+        assert_eq!(
+            rank("cc", &["chaotic", "camelCase"]),
+            ["camelCase", "chaotic"]
+        );
+    }
+
+    #[test]
+    fn runs_rank_above_scattered_matches() {
+        assert_eq!(
+            rank("milk", &["make it look kind", "buy milk"]),
+            ["buy milk", "make it look kind"]
+        );
+        assert_eq!(
+            rank("tax", &["tidy attic box", "taxes"]),
+            ["taxes", "tidy attic box"]
+        );
+    }
+
+    #[test]
+    fn earlier_and_tighter_matches_rank_higher() {
+        assert_eq!(
+            rank("call", &["remember to call", "call mom"]),
+            ["call mom", "remember to call"]
+        );
+        assert_eq!(rank("ab", &["a......b", "a..b"]), ["a..b", "a......b"]);
+        // The best placement is picked, not the first one found
+        assert_eq!(
+            score("ab", "xaxb ab").map(|(_, positions)| positions),
+            Some(vec![5, 6])
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{time, todo::Priority};
+
+// A change to one field of a todo, recorded by the store on every write.
+// Values are kept as the database columns store them, like "1" for done.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub todo_id: i64,
+    pub changed_at: i64,
+    // A column of the todos table, or "created" and "deleted" with the label
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+// Shows `value` on one line, quoted, or "nothing" when it is empty
+fn quote(value: Option<&str>) -> String {
+    match value.map(str::trim).filter(|value| !value.is_empty()) {
+        Some(value) => format!("\"{}\"", value.lines().collect::<Vec<_>>().join(" / ")),
+        None => String::from("nothing"),
+    }
+}
+
+fn priority(value: Option<&str>) -> &'static str {
+    Priority::from_i64(value.and_then(|value| value.parse().ok()).unwrap_or(0)).label()
+}
+
+fn due(value: Option<&str>, date_format: &str) -> String {
+    match value.and_then(|value| value.parse().ok()) {
+        Some(due_at) => time::format(due_at, date_format),
+        None => String::from("none"),
+    }
+}
+
+impl HistoryEntry {
+    // What happened, like `label changed from "a" to "b"`
+    pub fn describe(&self, date_format: &str) -> String {
+        let (old, new) = (self.old_value.as_deref(), self.new_value.as_deref());
+        match self.field.as_str() {
+            "created" => format!("created as {}", quote(new)),
+            "deleted" => format!("deleted for good, it said {}", quote(old)),
+            "deleted_at" if new.is_some() => String::from("moved to the trash"),
+            "deleted_at" => String::from("restored from the trash"),
+            "completed" if new == Some("1") => String::from("marked done"),
+            "completed" => String::from("marked open"),
+            "archived_at" if new.is_some() => String::from("archived"),
+            "archived_at" => String::from("restored from the archive"),
+            "label" => format!("label changed from {} to {}", quote(old), quote(new)),
+            "notes" => format!("notes changed from {} to {}", quote(old), quote(new)),
+            "priority" => format!(
+                "priority changed from {} to {}",
+                priority(old),
+                priority(new)
+            ),
+            "due_at" => format!(
+                "due date changed from {} to {}",
+                due(old, date_format),
+                due(new, date_format)
+            ),
+            field => format!("{} changed", field),
+        }
+    }
+}
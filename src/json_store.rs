@@ -0,0 +1,347 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    backup::{self, BACKUP_DIR},
+    history::HistoryEntry,
+    memory_store::{MemoryStore, StoreData},
+    query::Query,
+    search::SearchHit,
+    sort::Sort,
+    store::{StoreError, StoreResult, TodoStore},
+    todo::Todo,
+    view::SavedView,
+};
+
+// A store kept in a single pretty-printed JSON file, so todos can live in a
+// git repository and changes read well in diffs. The file is read into a
+// `MemoryStore`, reloaded when it changes on disk and written back after
+// every write or transaction. Writers hold a lock on a file next to it, so a
+// write never overwrites one made elsewhere.
+pub struct JsonStore {
+    path: PathBuf,
+    lock_path: PathBuf,
+    backup_dir: PathBuf,
+    memory: MemoryStore,
+    // The file as last read or written here, to notice writes made elsewhere
+    stamp: Option<Stamp>,
+    // Bumped whenever the file is reloaded after a write elsewhere
+    version: i64,
+    // The lock held while a transaction is open
+    lock: Option<File>,
+}
+
+// Tells versions of the file apart. The modification time alone can miss a
+// write made right after another, but on unix every save moves a new file
+// into place, so the inode changes too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    modified: SystemTime,
+    len: u64,
+    #[cfg(unix)]
+    inode: u64,
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some(Stamp {
+        modified: metadata.modified().ok()?,
+        len: metadata.len(),
+        #[cfg(unix)]
+        inode: std::os::unix::fs::MetadataExt::ino(&metadata),
+    })
+}
+
+// `path` with `suffix` added to its file name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn read(path: &Path) -> StoreResult<StoreData> {
+    let contents = fs::read_to_string(path)?;
+    serde_json::from_str(&contents).map_err(|err| {
+        StoreError::Invalid(format!("{} is not a todo file: {}", path.display(), err))
+    })
+}
+
+// Checks that `path` is a todo file before it is restored
+pub fn verify(path: &Path) -> Result<(), String> {
+    if !path.is_file() {
+        return Err(format!("{} doesn't exist", path.display()));
+    }
+    read(path).map(|_| ()).map_err(|err| err.to_string())
+}
+
+impl JsonStore {
+    // Opens the file at `path`, creating it with the default views when it
+    // doesn't exist
+    pub fn open(path: &Path) -> StoreResult<Self> {
+        let mut store = Self {
+            path: path.to_path_buf(),
+            lock_path: with_suffix(path, ".lock"),
+            backup_dir: path.with_file_name(BACKUP_DIR),
+            memory: MemoryStore::new(),
+            stamp: None,
+            version: 0,
+            lock: None,
+        };
+        let _lock = store.lock()?;
+        if path.exists() {
+            store.refresh()?;
+        } else {
+            store.save()?;
+        }
+        Ok(store)
+    }
+
+    // Waits until no other process is writing the file. The lock is released
+    // when the returned file is dropped.
+    fn lock(&self) -> StoreResult<File> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&self.lock_path)?;
+        file.lock()?;
+        Ok(file)
+    }
+
+    // Reloads the file when it was written elsewhere since it was last read
+    fn refresh(&mut self) -> StoreResult<()> {
+        let stamp = stamp(&self.path);
+        if stamp.is_some() && stamp == self.stamp {
+            return Ok(());
+        }
+        self.memory = MemoryStore::from_data(read(&self.path)?);
+        self.stamp = stamp;
+        self.version += 1;
+        Ok(())
+    }
+
+    // Writes a temporary file next to the store and moves it into place, so
+    // readers never see half a file
+    fn save(&mut self) -> StoreResult<()> {
+        let mut contents = serde_json::to_string_pretty(self.memory.data())
+            .map_err(|err| StoreError::Io(io::Error::other(err)))?;
+        contents.push('\n');
+        let temp_path = with_suffix(&self.path, ".tmp");
+        fs::write(&temp_path, contents)?;
+        fs::rename(&temp_path, &self.path)?;
+        self.stamp = stamp(&self.path);
+        Ok(())
+    }
+
+    // Runs `write` on the latest contents of the file and saves the result,
+    // unless a transaction is open and `commit` saves it. Nothing is kept in
+    // memory when `write` fails or the file can't be saved.
+    fn write<T>(
+        &mut self,
+        write: impl FnOnce(&mut MemoryStore) -> StoreResult<T>,
+    ) -> StoreResult<T> {
+        if self.lock.is_some() {
+            return write(&mut self.memory);
+        }
+        let _lock = self.lock()?;
+        self.refresh()?;
+        self.memory.begin()?;
+        match write(&mut self.memory).and_then(|value| self.save().map(|_| value)) {
+            Ok(value) => {
+                self.memory.commit()?;
+                Ok(value)
+            }
+            Err(err) => {
+                self.memory.rollback();
+                Err(err)
+            }
+        }
+    }
+}
+
+impl TodoStore for JsonStore {
+    fn data_version(&mut self) -> StoreResult<i64> {
+        if self.lock.is_none() {
+            self.refresh()?;
+        }
+        Ok(self.version)
+    }
+
+    fn get_setting(&self, key: &str) -> Option<String> {
+        self.memory.get_setting(key)
+    }
+
+    fn set_setting(&mut self, key: &str, value: &str) -> StoreResult<()> {
+        self.write(|memory| memory.set_setting(key, value))
+    }
+
+    fn delete_setting(&mut self, key: &str) -> StoreResult<()> {
+        self.write(|memory| memory.delete_setting(key))
+    }
+
+    fn get_views(&self) -> StoreResult<Vec<SavedView>> {
+        self.memory.get_views()
+    }
+
+    fn add_view(&mut self, name: &str, query: &str, sort: Sort) -> StoreResult<SavedView> {
+        self.write(|memory| memory.add_view(name, query, sort))
+    }
+
+    fn update_view(&mut self, view: &SavedView) -> StoreResult<()> {
+        self.write(|memory| memory.update_view(view))
+    }
+
+    fn delete_view(&mut self, id: i64) -> StoreResult<()> {
+        self.write(|memory| memory.delete_view(id))
+    }
+
+    fn get_history(&self, id: Option<i64>) -> StoreResult<Vec<HistoryEntry>> {
+        self.memory.get_history(id)
+    }
+
+    fn get_all_todos(&self) -> StoreResult<HashMap<i64, Todo>> {
+        self.memory.get_all_todos()
+    }
+
+    fn get_trash(&self) -> StoreResult<Vec<Todo>> {
+        self.memory.get_trash()
+    }
+
+    fn find_todos(&self, query: &Query, now: i64) -> StoreResult<Vec<Todo>> {
+        self.memory.find_todos(query, now)
+    }
+
+    fn search(&self, input: &str, limit: usize) -> StoreResult<Vec<SearchHit>> {
+        self.memory.search(input, limit)
+    }
+
+    fn add_todo_with(&mut self, todo: &Todo, position: Option<f64>) -> StoreResult<Todo> {
+        self.write(|memory| memory.add_todo_with(todo, position))
+    }
+
+    fn set_position(&mut self, todo: &mut Todo, position: f64) -> StoreResult<()> {
+        self.write(|memory| memory.set_position(todo, position))
+    }
+
+    fn toggle_todo(&mut self, todo: &mut Todo) -> StoreResult<()> {
+        self.write(|memory| memory.toggle_todo(todo))
+    }
+
+    fn set_archived(&mut self, todo: &mut Todo, archived: bool) -> StoreResult<()> {
+        self.write(|memory| memory.set_archived(todo, archived))
+    }
+
+    fn archive_completed(&mut self, completed_before: i64) -> StoreResult<usize> {
+        self.write(|memory| memory.archive_completed(completed_before))
+    }
+
+    fn purge_archived(&mut self, archived_before: i64) -> StoreResult<usize> {
+        self.write(|memory| memory.purge_archived(archived_before))
+    }
+
+    fn set_deleted(&mut self, todo: &mut Todo, deleted: bool) -> StoreResult<()> {
+        self.write(|memory| memory.set_deleted(todo, deleted))
+    }
+
+    fn delete_todo(&mut self, todo: &Todo) -> StoreResult<()> {
+        self.write(|memory| memory.delete_todo(todo))
+    }
+
+    fn empty_trash(&mut self, deleted_before: i64) -> StoreResult<usize> {
+        self.write(|memory| memory.empty_trash(deleted_before))
+    }
+
+    fn update_todo(&mut self, todo: &mut Todo) -> StoreResult<()> {
+        self.write(|memory| memory.update_todo(todo))
+    }
+
+    // Version checks inside the transaction run against the latest file,
+    // which nobody else writes until the transaction ends
+    fn begin(&mut self) -> StoreResult<()> {
+        let lock = self.lock()?;
+        self.refresh()?;
+        self.memory.begin()?;
+        self.lock = Some(lock);
+        Ok(())
+    }
+
+    // Nothing is kept in memory unless the file was written, so a failed
+    // save can still be rolled back
+    fn commit(&mut self) -> StoreResult<()> {
+        self.save()?;
+        self.memory.commit()?;
+        self.lock = None;
+        Ok(())
+    }
+
+    fn rollback(&mut self) {
+        self.memory.rollback();
+        self.lock = None;
+    }
+
+    fn backup_dir(&self) -> Option<&Path> {
+        Some(&self.backup_dir)
+    }
+
+    fn backup(&self, reason: Option<&str>) -> StoreResult<PathBuf> {
+        Ok(backup::copy(&self.path, &self.backup_dir, reason)?)
+    }
+
+    fn verify_backup(&self, path: &Path) -> Result<(), String> {
+        verify(path)
+    }
+
+    fn restore(&mut self, path: &Path) -> StoreResult<()> {
+        let data = read(path)?;
+        let _lock = self.lock()?;
+        self.memory = MemoryStore::from_data(data);
+        self.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDir;
+
+    #[test]
+    fn writes_made_elsewhere_are_kept() {
+        let dir = TempDir::new("json-writers");
+        let path = dir.join("todos.json");
+        let mut first = JsonStore::open(&path).unwrap();
+        let mut second = JsonStore::open(&path).unwrap();
+        for index in 0..10 {
+            first.add_todo(&format!("first {}", index)).unwrap();
+            second.add_todo(&format!("second {}", index)).unwrap();
+        }
+        let todos = JsonStore::open(&path).unwrap().get_all_todos().unwrap();
+        assert_eq!(todos.len(), 20);
+    }
+
+    #[test]
+    fn failed_writes_leave_nothing_behind() {
+        let dir = TempDir::new("json-failed-write");
+        let path = dir.join("todos.json");
+        let mut store = JsonStore::open(&path).unwrap();
+        store.add_todo("kept").unwrap();
+        let failed: StoreResult<()> = store.write(|memory| {
+            memory.add_todo("dropped")?;
+            Err(StoreError::Invalid(String::from("failed")))
+        });
+        assert!(failed.is_err());
+        assert_eq!(store.get_all_todos().unwrap().len(), 1);
+        assert_eq!(
+            JsonStore::open(&path)
+                .unwrap()
+                .get_all_todos()
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,763 @@
+use std::{collections::HashMap, fmt};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+// A single key press with the modifiers that matter for bindings. Shift is
+// folded into the character, so "G" is stored as 'G' without SHIFT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyPress {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyPress {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let modifiers = match code {
+            KeyCode::Char(_) | KeyCode::BackTab => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        Self {
+            code,
+            modifiers: modifiers
+                & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT),
+        }
+    }
+}
+
+impl From<&KeyEvent> for KeyPress {
+    fn from(key: &KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+}
+
+const NAMED_KEYS: &[(&str, KeyCode)] = &[
+    ("Enter", KeyCode::Enter),
+    ("Esc", KeyCode::Esc),
+    ("Space", KeyCode::Char(' ')),
+    ("Tab", KeyCode::Tab),
+    ("BackTab", KeyCode::BackTab),
+    ("Backspace", KeyCode::Backspace),
+    ("Delete", KeyCode::Delete),
+    ("Insert", KeyCode::Insert),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("lt", KeyCode::Char('<')),
+];
+
+fn parse_bracketed(name: &str) -> Result<KeyPress, String> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = name;
+    while let Some((prefix, tail)) = rest.split_once('-').filter(|(_, tail)| !tail.is_empty()) {
+        modifiers |= match prefix {
+            "C" | "c" => KeyModifiers::CONTROL,
+            "A" | "a" | "M" | "m" => KeyModifiers::ALT,
+            "S" | "s" => KeyModifiers::SHIFT,
+            _ => return Err(format!("unknown modifier '{}' in <{}>", prefix, name)),
+        };
+        rest = tail;
+    }
+
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(if modifiers.contains(KeyModifiers::SHIFT) {
+            c.to_ascii_uppercase()
+        } else {
+            c
+        }),
+        _ => {
+            if let Some((_, code)) = NAMED_KEYS
+                .iter()
+                .find(|(key_name, _)| key_name.eq_ignore_ascii_case(rest))
+            {
+                *code
+            } else if let Some(n) = rest
+                .strip_prefix(['F', 'f'])
+                .and_then(|n| n.parse::<u8>().ok())
+                .filter(|n| (1..=12).contains(n))
+            {
+                KeyCode::F(n)
+            } else {
+                return Err(format!("unknown key <{}>", name));
+            }
+        }
+    };
+    Ok(KeyPress::new(code, modifiers))
+}
+
+// Parses a key sequence such as "gg", "<C-d>" or "<Space>x". Plain characters
+// are single keys; special keys and modifiers go in angle brackets.
+pub fn parse_sequence(input: &str) -> Result<Vec<KeyPress>, String> {
+    let mut keys = vec![];
+    let mut rest = input;
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(end) = rest.find('>').filter(|end| *end > 1) {
+                keys.push(parse_bracketed(&rest[1..end])?);
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+        if c.is_whitespace() {
+            return Err(format!("whitespace in '{}', use <Space> instead", input));
+        }
+        keys.push(KeyPress::new(KeyCode::Char(c), KeyModifiers::NONE));
+        rest = &rest[c.len_utf8()..];
+    }
+    if keys.is_empty() {
+        return Err(String::from("empty key sequence"));
+    }
+    Ok(keys)
+}
+
+impl fmt::Display for KeyPress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut prefix = String::new();
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            prefix.push_str("C-");
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            prefix.push_str("A-");
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            prefix.push_str("S-");
+        }
+        let name = match self.code {
+            KeyCode::Char(c) if prefix.is_empty() && c != ' ' && c != '<' => {
+                return write!(f, "{}", c)
+            }
+            KeyCode::Char(c) if c != ' ' && c != '<' => c.to_string(),
+            KeyCode::F(n) => format!("F{}", n),
+            code => NAMED_KEYS
+                .iter()
+                .find(|(_, named)| *named == code)
+                .map(|(name, _)| name.to_string())
+                .unwrap_or_else(|| format!("{:?}", code)),
+        };
+        write!(f, "<{}{}>", prefix, name)
+    }
+}
+
+pub fn format_sequence(keys: &[KeyPress]) -> String {
+    keys.iter().map(KeyPress::to_string).collect()
+}
+
+// The screens whose keys can be remapped. Prompts that take text keep
+// <Enter> and <Esc>, and the finder shares the search bindings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyContext {
+    Main,
+    Archive,
+    Trash,
+    Views,
+    Delete,
+    Details,
+    History,
+    Search,
+    Help,
+}
+
+impl KeyContext {
+    pub const ALL: [KeyContext; 9] = [
+        KeyContext::Main,
+        KeyContext::Archive,
+        KeyContext::Trash,
+        KeyContext::Views,
+        KeyContext::Delete,
+        KeyContext::Details,
+        KeyContext::History,
+        KeyContext::Search,
+        KeyContext::Help,
+    ];
+
+    // Name of the `[keys.<name>]` table in the config file
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyContext::Main => "main",
+            KeyContext::Archive => "archive",
+            KeyContext::Trash => "trash",
+            KeyContext::Views => "views",
+            KeyContext::Delete => "delete",
+            KeyContext::Details => "details",
+            KeyContext::History => "history",
+            KeyContext::Search => "search",
+            KeyContext::Help => "help",
+        }
+    }
+
+    // Heading for the screen in the help overlay
+    pub fn title(self) -> &'static str {
+        match self {
+            KeyContext::Main => "Todo list",
+            KeyContext::Archive => "Archive",
+            KeyContext::Trash => "Trash",
+            KeyContext::Views => "Saved views",
+            KeyContext::Delete => "Confirmations",
+            KeyContext::Details => "Details",
+            KeyContext::History => "History",
+            KeyContext::Search => "Search and find",
+            KeyContext::Help => "Help",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|context| context.name() == name)
+    }
+
+    pub fn bindings(self) -> &'static [Binding] {
+        match self {
+            KeyContext::Main => MAIN_BINDINGS,
+            KeyContext::Archive => ARCHIVE_BINDINGS,
+            KeyContext::Trash => TRASH_BINDINGS,
+            KeyContext::Views => VIEWS_BINDINGS,
+            KeyContext::Delete => DELETE_BINDINGS,
+            KeyContext::Details => DETAILS_BINDINGS,
+            KeyContext::History => HISTORY_BINDINGS,
+            KeyContext::Search => SEARCH_BINDINGS,
+            KeyContext::Help => HELP_BINDINGS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    Back,
+    Add,
+    Delete,
+    Edit,
+    Details,
+    Notes,
+    CyclePriority,
+    DueDate,
+    Toggle,
+    GoToTop,
+    GoToBottom,
+    Select,
+    Next,
+    Prev,
+    Search,
+    Find,
+    Command,
+    Filter,
+    Views,
+    ClearView,
+    ApplyView(usize),
+    CycleSort,
+    ToggleSortDirection,
+    ToggleHideCompleted,
+    ArchiveCompleted,
+    Archive,
+    Restore,
+    Apply,
+    NewView,
+    EditView,
+    RenameView,
+    DeleteView,
+    Confirm,
+    Jump,
+    Help,
+    PageDown,
+    PageUp,
+    HalfPageDown,
+    HalfPageUp,
+    ScreenTop,
+    ScreenMiddle,
+    ScreenBottom,
+    Repeat,
+    Yank,
+    YankMarkdown,
+    PasteAfter,
+    PasteBefore,
+    EditExternal,
+    EditList,
+    History,
+    Trash,
+    Purge,
+}
+
+// An action as it appears in the config file, with its default keys
+pub struct Binding {
+    pub action: Action,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub keys: &'static [&'static str],
+}
+
+const fn bind(
+    action: Action,
+    name: &'static str,
+    description: &'static str,
+    keys: &'static [&'static str],
+) -> Binding {
+    Binding {
+        action,
+        name,
+        description,
+        keys,
+    }
+}
+
+const MAIN_BINDINGS: &[Binding] = &[
+    bind(Action::Next, "next", "Next todo", &["j", "<Down>"]),
+    bind(Action::Prev, "prev", "Previous todo", &["k", "<Up>"]),
+    bind(Action::GoToTop, "top", "First todo, or todo N", &["gg"]),
+    bind(Action::GoToBottom, "bottom", "Last todo, or todo N", &["G"]),
+    bind(
+        Action::HalfPageDown,
+        "half_page_down",
+        "Half a page down",
+        &["<C-d>"],
+    ),
+    bind(
+        Action::HalfPageUp,
+        "half_page_up",
+        "Half a page up",
+        &["<C-u>"],
+    ),
+    bind(Action::ScreenTop, "screen_top", "Top of the screen", &["H"]),
+    bind(
+        Action::ScreenMiddle,
+        "screen_middle",
+        "Middle of the screen",
+        &["M"],
+    ),
+    bind(
+        Action::ScreenBottom,
+        "screen_bottom",
+        "Bottom of the screen",
+        &["L"],
+    ),
+    bind(Action::Toggle, "toggle", "Toggle done", &["<Space>"]),
+    bind(Action::Details, "details", "Show details", &["<Enter>"]),
+    bind(
+        Action::History,
+        "history",
+        "Show the history of the todo",
+        &["gh"],
+    ),
+    bind(Action::Add, "add", "Add a todo", &["a"]),
+    bind(Action::Edit, "edit", "Edit the label", &["e"]),
+    bind(Action::Delete, "delete", "Delete the todo", &["dd"]),
+    bind(Action::Repeat, "repeat", "Repeat the last change", &["."]),
+    bind(Action::Notes, "notes", "Edit notes", &["n"]),
+    bind(
+        Action::EditExternal,
+        "edit_external",
+        "Edit the todo in $EDITOR",
+        &["E"],
+    ),
+    bind(
+        Action::EditList,
+        "edit_list",
+        "Edit the list in $EDITOR",
+        &["gE"],
+    ),
+    bind(Action::CyclePriority, "priority", "Cycle priority", &["!"]),
+    bind(Action::Yank, "yank", "Copy the todo", &["y"]),
+    bind(
+        Action::YankMarkdown,
+        "yank_markdown",
+        "Copy as a Markdown checklist",
+        &["Y"],
+    ),
+    bind(Action::PasteAfter, "paste", "Paste after the todo", &["p"]),
+    bind(
+        Action::PasteBefore,
+        "paste_before",
+        "Paste before the todo",
+        &["P"],
+    ),
+    bind(Action::DueDate, "due", "Set the due date", &["D"]),
+    bind(Action::Select, "select", "Selection mode", &["v"]),
+    bind(Action::Search, "search", "Full-text search", &["/"]),
+    bind(Action::Find, "find", "Fuzzy find", &["F"]),
+    bind(Action::Command, "command", "Command line", &[":"]),
+    bind(Action::Filter, "filter", "Filter the list", &["f"]),
+    bind(Action::Views, "views", "Saved views", &["V"]),
+    bind(Action::ClearView, "view_0", "Show all todos", &["g0"]),
+    bind(Action::ApplyView(0), "view_1", "Switch to view 1", &["g1"]),
+    bind(Action::ApplyView(1), "view_2", "Switch to view 2", &["g2"]),
+    bind(Action::ApplyView(2), "view_3", "Switch to view 3", &["g3"]),
+    bind(Action::ApplyView(3), "view_4", "Switch to view 4", &["g4"]),
+    bind(Action::ApplyView(4), "view_5", "Switch to view 5", &["g5"]),
+    bind(Action::ApplyView(5), "view_6", "Switch to view 6", &["g6"]),
+    bind(Action::ApplyView(6), "view_7", "Switch to view 7", &["g7"]),
+    bind(Action::ApplyView(7), "view_8", "Switch to view 8", &["g8"]),
+    bind(Action::ApplyView(8), "view_9", "Switch to view 9", &["g9"]),
+    bind(Action::CycleSort, "sort", "Cycle sort mode", &["s"]),
+    bind(
+        Action::ToggleSortDirection,
+        "sort_direction",
+        "Reverse sort",
+        &["S"],
+    ),
+    bind(
+        Action::ToggleHideCompleted,
+        "hide_done",
+        "Hide done todos",
+        &["c"],
+    ),
+    bind(
+        Action::ArchiveCompleted,
+        "archive_done",
+        "Archive done todos",
+        &["x"],
+    ),
+    bind(Action::Archive, "archive", "Open the archive", &["A"]),
+    bind(Action::Trash, "trash", "Open the trash", &["T"]),
+    bind(Action::Help, "help", "Show this help", &["?"]),
+    bind(Action::Quit, "quit", "Quit", &["q", "<Esc>"]),
+];
+
+const ARCHIVE_BINDINGS: &[Binding] = &[
+    bind(Action::Next, "next", "Next todo", &["j", "<Down>"]),
+    bind(Action::Prev, "prev", "Previous todo", &["k", "<Up>"]),
+    bind(Action::Restore, "restore", "Restore the todo", &["u"]),
+    bind(Action::Back, "back", "Back to the list", &["q", "<Esc>"]),
+];
+
+const TRASH_BINDINGS: &[Binding] = &[
+    bind(Action::Next, "next", "Next todo", &["j", "<Down>"]),
+    bind(Action::Prev, "prev", "Previous todo", &["k", "<Up>"]),
+    bind(Action::Restore, "restore", "Restore the todo", &["u"]),
+    bind(Action::Purge, "delete", "Delete the todo for good", &["dd"]),
+    bind(Action::Back, "back", "Back to the list", &["q", "<Esc>"]),
+];
+
+const VIEWS_BINDINGS: &[Binding] = &[
+    bind(Action::Next, "next", "Next view", &["j", "<Down>"]),
+    bind(Action::Prev, "prev", "Previous view", &["k", "<Up>"]),
+    bind(Action::Apply, "apply", "Apply the view", &["<Enter>"]),
+    bind(Action::NewView, "new", "Save the current filter", &["n"]),
+    bind(Action::EditView, "edit", "Edit the query", &["e"]),
+    bind(Action::RenameView, "rename", "Rename the view", &["r"]),
+    bind(Action::DeleteView, "delete", "Delete the view", &["d"]),
+    bind(Action::Back, "back", "Back to the list", &["q", "<Esc>"]),
+];
+
+const DELETE_BINDINGS: &[Binding] = &[
+    bind(Action::Confirm, "confirm", "Yes", &["y", "Y"]),
+    bind(Action::Back, "cancel", "No", &["n", "N", "<Esc>"]),
+];
+
+const DETAILS_BINDINGS: &[Binding] = &[
+    bind(Action::History, "history", "Show the history", &["h"]),
+    bind(Action::Back, "back", "Close", &["q", "<Esc>", "<Enter>"]),
+];
+
+const HISTORY_BINDINGS: &[Binding] = &[
+    bind(Action::Next, "next", "Scroll down", &["j", "<Down>"]),
+    bind(Action::Prev, "prev", "Scroll up", &["k", "<Up>"]),
+    bind(
+        Action::PageDown,
+        "page_down",
+        "Page down",
+        &["<PageDown>", "<Space>"],
+    ),
+    bind(Action::PageUp, "page_up", "Page up", &["<PageUp>"]),
+    bind(Action::GoToTop, "top", "Newest change", &["gg", "<Home>"]),
+    bind(
+        Action::GoToBottom,
+        "bottom",
+        "Oldest change",
+        &["G", "<End>"],
+    ),
+    bind(Action::Back, "back", "Close the history", &["q", "<Esc>"]),
+];
+
+const SEARCH_BINDINGS: &[Binding] = &[
+    bind(Action::Next, "next", "Next result", &["<Down>", "<C-n>"]),
+    bind(Action::Prev, "prev", "Previous result", &["<Up>", "<C-p>"]),
+    bind(Action::Jump, "jump", "Jump to the result", &["<Enter>"]),
+    bind(Action::Back, "back", "Back to the list", &["<Esc>"]),
+];
+
+const HELP_BINDINGS: &[Binding] = &[
+    bind(Action::Next, "next", "Scroll down", &["j", "<Down>"]),
+    bind(Action::Prev, "prev", "Scroll up", &["k", "<Up>"]),
+    bind(
+        Action::PageDown,
+        "page_down",
+        "Page down",
+        &["<PageDown>", "<Space>"],
+    ),
+    bind(Action::PageUp, "page_up", "Page up", &["<PageUp>"]),
+    bind(Action::GoToTop, "top", "Top", &["gg", "<Home>"]),
+    bind(Action::GoToBottom, "bottom", "Bottom", &["G", "<End>"]),
+    bind(Action::Back, "back", "Close the help", &["q", "<Esc>", "?"]),
+];
+
+pub enum Lookup {
+    Action(Action),
+    // The keys so far start a longer sequence
+    Pending,
+    None,
+}
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: HashMap<KeyContext, Vec<(Vec<KeyPress>, Action)>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new(&HashMap::new()).expect("default key bindings are valid")
+    }
+}
+
+impl Keymap {
+    // Builds the keymap from the defaults and the `[keys.<context>]` overrides,
+    // given as action name to key sequences. An override replaces all default
+    // keys of its action, and default keys that clash with an override are
+    // dropped. Clashes between overrides are errors.
+    pub fn new(
+        overrides: &HashMap<KeyContext, Vec<(String, Vec<String>)>>,
+    ) -> Result<Self, String> {
+        let mut bindings = HashMap::new();
+        for context in KeyContext::ALL {
+            let mut custom: Vec<(Vec<KeyPress>, Action)> = vec![];
+            for (name, sequences) in overrides.get(&context).into_iter().flatten() {
+                let binding = context
+                    .bindings()
+                    .iter()
+                    .find(|binding| binding.name == name)
+                    .ok_or_else(|| {
+                        let names: Vec<&str> = context
+                            .bindings()
+                            .iter()
+                            .map(|binding| binding.name)
+                            .collect();
+                        format!(
+                            "keys.{}: unknown action '{}', expected one of {}",
+                            context.name(),
+                            name,
+                            names.join(", ")
+                        )
+                    })?;
+                for sequence in sequences {
+                    let keys = parse_sequence(sequence)
+                        .map_err(|err| format!("keys.{}.{}: {}", context.name(), name, err))?;
+                    if let Some((_, other)) = custom
+                        .iter()
+                        .find(|(other_keys, _)| clashes(other_keys, &keys))
+                    {
+                        let other = context
+                            .bindings()
+                            .iter()
+                            .find(|binding| binding.action == *other)
+                            .map_or("?", |binding| binding.name);
+                        return Err(format!(
+                            "keys.{}.{}: '{}' clashes with the keys for '{}'",
+                            context.name(),
+                            name,
+                            sequence,
+                            other
+                        ));
+                    }
+                    custom.push((keys, binding.action));
+                }
+            }
+
+            let mut keys = custom.clone();
+            for binding in context.bindings() {
+                if custom.iter().any(|(_, action)| *action == binding.action) {
+                    continue;
+                }
+                for sequence in binding.keys {
+                    let default = parse_sequence(sequence)?;
+                    if !custom.iter().any(|(other, _)| clashes(other, &default)) {
+                        keys.push((default, binding.action));
+                    }
+                }
+            }
+            bindings.insert(context, keys);
+        }
+        Ok(Self { bindings })
+    }
+
+    pub fn lookup(&self, context: KeyContext, keys: &[KeyPress]) -> Lookup {
+        let mut pending = false;
+        for (sequence, action) in &self.bindings[&context] {
+            if sequence == keys {
+                return Lookup::Action(*action);
+            }
+            pending |= sequence.starts_with(keys);
+        }
+        if pending {
+            Lookup::Pending
+        } else {
+            Lookup::None
+        }
+    }
+
+    // The key sequences bound to `action`, in the order they were defined
+    pub fn keys_for(&self, context: KeyContext, action: Action) -> Vec<&[KeyPress]> {
+        self.bindings[&context]
+            .iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(keys, _)| keys.as_slice())
+            .collect()
+    }
+}
+
+// Two sequences clash when one is a prefix of the other, since the shorter
+// one would always fire first
+fn clashes(a: &[KeyPress], b: &[KeyPress]) -> bool {
+    a.starts_with(b) || b.starts_with(a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(c: char) -> KeyPress {
+        KeyPress::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    fn keys(input: &str) -> Vec<KeyPress> {
+        parse_sequence(input).unwrap()
+    }
+
+    fn with_overrides(
+        context: KeyContext,
+        overrides: &[(&str, &[&str])],
+    ) -> Result<Keymap, String> {
+        let overrides = overrides
+            .iter()
+            .map(|(name, keys)| {
+                (
+                    name.to_string(),
+                    keys.iter().map(|keys| keys.to_string()).collect(),
+                )
+            })
+            .collect();
+        Keymap::new(&HashMap::from([(context, overrides)]))
+    }
+
+    fn action(keymap: &Keymap, input: &str) -> Option<Action> {
+        match keymap.lookup(KeyContext::Main, &keys(input)) {
+            Lookup::Action(action) => Some(action),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parses_key_sequences() {
+        assert_eq!(keys("gg"), [key('g'), key('g')]);
+        assert_eq!(
+            keys("<C-d>"),
+            [KeyPress::new(KeyCode::Char('d'), KeyModifiers::CONTROL)]
+        );
+        assert_eq!(keys("<Space>x"), [key(' '), key('x')]);
+        assert_eq!(keys("<s-a>"), [key('A')]);
+        assert_eq!(keys("<lt><"), [key('<'), key('<')]);
+        assert_eq!(
+            keys("<A-Enter>"),
+            [KeyPress::new(KeyCode::Enter, KeyModifiers::ALT)]
+        );
+        assert_eq!(
+            keys("<f12>"),
+            [KeyPress::new(KeyCode::F(12), KeyModifiers::NONE)]
+        );
+        // Shift is part of the character, however the terminal reports it
+        assert_eq!(
+            KeyPress::new(KeyCode::Char('G'), KeyModifiers::SHIFT),
+            key('G')
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_sequences() {
+        assert_eq!(parse_sequence(""), Err(String::from("empty key sequence")));
+        assert_eq!(
+            parse_sequence("<X-a>"),
+            Err(String::from("unknown modifier 'X' in <X-a>"))
+        );
+        assert_eq!(
+            parse_sequence("<Hyper>"),
+            Err(String::from("unknown key <Hyper>"))
+        );
+        assert_eq!(
+            parse_sequence("<F13>"),
+            Err(String::from("unknown key <F13>"))
+        );
+        assert!(parse_sequence("g g").is_err());
+    }
+
+    #[test]
+    fn sequences_format_as_they_parse() {
+        for input in ["gg", "<C-d>", "<Space>", "<lt>", "<A-S-Up>", "<F5>", "G"] {
+            assert_eq!(format_sequence(&keys(input)), input);
+        }
+    }
+
+    #[test]
+    fn default_bindings_dont_clash() {
+        for context in KeyContext::ALL {
+            let keymap = Keymap::default();
+            let bound = &keymap.bindings[&context];
+            for (index, (keys, _)) in bound.iter().enumerate() {
+                for (other, _) in &bound[index + 1..] {
+                    assert!(
+                        !clashes(keys, other),
+                        "{}: {} and {}",
+                        context.name(),
+                        format_sequence(keys),
+                        format_sequence(other)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn looks_up_sequences() {
+        let keymap = Keymap::default();
+        assert_eq!(action(&keymap, "gg"), Some(Action::GoToTop));
+        assert_eq!(action(&keymap, "g3"), Some(Action::ApplyView(2)));
+        assert!(matches!(
+            keymap.lookup(KeyContext::Main, &keys("g")),
+            Lookup::Pending
+        ));
+        assert!(matches!(
+            keymap.lookup(KeyContext::Main, &keys("gz")),
+            Lookup::None
+        ));
+    }
+
+    #[test]
+    fn overrides_replace_the_default_keys() {
+        let keymap = with_overrides(KeyContext::Main, &[("top", &["<Home>", "t"])]).unwrap();
+        assert_eq!(action(&keymap, "t"), Some(Action::GoToTop));
+        assert_eq!(action(&keymap, "gg"), None);
+        assert_eq!(keymap.keys_for(KeyContext::Main, Action::GoToTop).len(), 2);
+
+        // A default that clashes with an override is dropped
+        let keymap = with_overrides(KeyContext::Main, &[("quit", &["d"])]).unwrap();
+        assert_eq!(action(&keymap, "d"), Some(Action::Quit));
+        assert!(keymap.keys_for(KeyContext::Main, Action::Delete).is_empty());
+        assert_eq!(action(&keymap, "q"), None);
+    }
+
+    #[test]
+    fn rejects_clashing_overrides() {
+        assert_eq!(
+            with_overrides(KeyContext::Main, &[("add", &["x"]), ("edit", &["xy"])]).unwrap_err(),
+            "keys.main.edit: 'xy' clashes with the keys for 'add'"
+        );
+        assert!(with_overrides(KeyContext::Main, &[("top", &["t", "t"])]).is_err());
+        assert!(with_overrides(KeyContext::Main, &[("fly", &["f"])])
+            .unwrap_err()
+            .starts_with("keys.main: unknown action 'fly'"));
+        assert_eq!(
+            with_overrides(KeyContext::Trash, &[("next", &["<Nope>"])]).unwrap_err(),
+            "keys.trash.next: unknown key <Nope>"
+        );
+    }
+}
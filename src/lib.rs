@@ -0,0 +1,27 @@
+pub mod app;
+pub mod backup;
+pub mod cli;
+pub mod clipboard;
+pub mod command;
+pub mod config;
+pub mod db;
+pub mod document;
+pub mod editor;
+pub mod event;
+pub mod export;
+pub mod fuzzy;
+pub mod history;
+pub mod json_store;
+pub mod keys;
+pub mod memory_store;
+pub mod query;
+pub mod search;
+pub mod sort;
+pub mod store;
+pub mod theme;
+pub mod time;
+pub mod todo;
+pub mod tui;
+pub mod ui;
+pub mod update;
+pub mod view;
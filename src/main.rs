@@ -1,36 +1,95 @@
-pub mod app;
-pub mod db;
-pub mod event;
-pub mod todo;
-pub mod tui;
-pub mod ui;
-pub mod update;
-
-use std::io::stderr;
-
-use app::App;
-use event::EventHandler;
+use std::{env, io::stderr, process};
+
 use ratatui::{prelude::CrosstermBackend, Terminal};
-use tui::Tui;
-use update::update;
+use todo_rs::{
+    app::{App, ExternalEdit},
+    cli,
+    config::Config,
+    editor,
+    event::{self, EventHandler},
+    tui::Tui,
+    update::{update, update_mouse, update_paste},
+};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut app = App::new();
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Error in config file {}", err);
+            process::exit(2);
+        }
+    };
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        return cli::run(&args, &config);
+    }
+
+    let tick_rate = config.tick_rate;
+    let mut app = App::new(config);
 
     let backend = CrosstermBackend::new(stderr());
     let terminal = Terminal::new(backend)?;
-    let events = EventHandler::new(250);
+    let events = EventHandler::new(tick_rate)?;
     let mut tui = Tui::new(terminal, events);
     tui.enter()?;
 
     while !app.should_quit {
-        tui.draw(&mut app)?;
+        if app.should_suspend {
+            app.should_suspend = false;
+            tui.suspend()?;
+            app.should_redraw = true;
+        }
+        if let Some(edit) = app.external_edit.take() {
+            if let Some(document) = app.external_document(edit) {
+                let extension = match edit {
+                    ExternalEdit::Todo(_) => "md",
+                    ExternalEdit::List => "txt",
+                };
+                let edited = tui.run_external(|| editor::edit(&document, extension))?;
+                app.apply_external_edit(edit, &document, edited);
+            }
+            app.should_redraw = true;
+        }
+        if app.should_redraw {
+            tui.draw(&mut app)?;
+            app.should_redraw = false;
+        }
 
         match tui.events.next()? {
-            event::Event::Tick => {}
-            event::Event::Key(key_event) => update(&mut app, key_event),
-            event::Event::Mouse(_) => {}
-            event::Event::Resize(_, _) => {}
+            event::Event::Tick => {
+                let reloaded = app.reload_external_changes();
+                if app.backup_if_due() || reloaded {
+                    app.should_redraw = true;
+                }
+            }
+            event::Event::Key(key_event) => {
+                update(&mut app, key_event);
+                app.should_redraw = true;
+            }
+            event::Event::Mouse(mouse_event) => {
+                if update_mouse(&mut app, mouse_event) {
+                    app.should_redraw = true;
+                }
+            }
+            event::Event::Resize(_, _) => app.should_redraw = true,
+            event::Event::Paste(text) => {
+                update_paste(&mut app, &text);
+                app.should_redraw = true;
+            }
+            event::Event::Suspend => app.should_suspend = true,
+            // Stopped from outside, so the screen may have been drawn over
+            event::Event::Resume => {
+                tui.clear()?;
+                app.should_redraw = true;
+            }
+            // Every change is already written, so quitting cleanly is enough
+            event::Event::Terminate => app.should_quit = true,
+            event::Event::Error(err) => {
+                tui.exit()?;
+                eprintln!("Unable to read from the terminal: {}", err);
+                process::exit(1);
+            }
         }
     }
 
@@ -0,0 +1,580 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    history::HistoryEntry,
+    query::Query,
+    search::{self, find_phrase, snippet, tokenize, SearchHit, Token},
+    sort::{Sort, SortMode},
+    store::{StoreError, StoreResult, TodoStore},
+    time::now,
+    todo::Todo,
+    view::SavedView,
+};
+
+// Everything a store holds, as kept in memory and written out by `JsonStore`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoreData {
+    next_todo_id: i64,
+    next_view_id: i64,
+    // Sorted by id
+    todos: Vec<Todo>,
+    views: Vec<SavedView>,
+    settings: BTreeMap<String, String>,
+    // Oldest first
+    history: Vec<HistoryEntry>,
+}
+
+impl Default for StoreData {
+    // The same views a new database starts out with
+    fn default() -> Self {
+        let view = |id, name: &str, query: &str, mode| SavedView {
+            id,
+            name: name.to_string(),
+            query: query.to_string(),
+            sort: Sort {
+                mode,
+                descending: false,
+            },
+        };
+        Self {
+            next_todo_id: 1,
+            next_view_id: 4,
+            todos: vec![],
+            views: vec![
+                view(1, "Today", "status:open due<=today", SortMode::Priority),
+                view(2, "Overdue", "status:open due:overdue", SortMode::Due),
+                view(
+                    3,
+                    "Waiting on others",
+                    "status:open tag:waiting",
+                    SortMode::Updated,
+                ),
+            ],
+            settings: BTreeMap::new(),
+            history: vec![],
+        }
+    }
+}
+
+// A store that keeps everything in memory and behaves like the database,
+// for tests and as the working copy of `JsonStore`
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    data: StoreData,
+    // The data as it was when the open transaction began
+    snapshot: Option<StoreData>,
+}
+
+// Values as the database columns hold them, for the history
+fn timestamp_value(timestamp: Option<i64>) -> Option<String> {
+    timestamp.map(|timestamp| timestamp.to_string())
+}
+
+fn bool_value(value: bool) -> Option<String> {
+    Some(String::from(if value { "1" } else { "0" }))
+}
+
+// FTS5's bm25 parameters
+const BM25_K1: f64 = 1.2;
+const BM25_B: f64 = 0.75;
+
+// The label and notes of a todo split into words for searching
+struct Indexed<'a> {
+    todo: &'a Todo,
+    label: Vec<Token>,
+    notes: Vec<Token>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_data(data: StoreData) -> Self {
+        Self {
+            data,
+            snapshot: None,
+        }
+    }
+
+    pub fn data(&self) -> &StoreData {
+        &self.data
+    }
+
+    fn index_of(&self, id: i64) -> Option<usize> {
+        self.data
+            .todos
+            .binary_search_by_key(&id, |todo| todo.id)
+            .ok()
+    }
+
+    fn record(&mut self, todo_id: i64, field: &str, old: Option<String>, new: Option<String>) {
+        self.data.history.push(HistoryEntry {
+            todo_id,
+            changed_at: now(),
+            field: field.to_string(),
+            old_value: old,
+            new_value: new,
+        });
+    }
+
+    // Records the fields that differ between `old` and `new`, like the
+    // history triggers of the database
+    fn record_changes(&mut self, old: &Todo, new: &Todo) {
+        let changes = [
+            ("label", Some(old.label.clone()), Some(new.label.clone())),
+            (
+                "completed",
+                bool_value(old.completed),
+                bool_value(new.completed),
+            ),
+            (
+                "priority",
+                Some(old.priority.as_i64().to_string()),
+                Some(new.priority.as_i64().to_string()),
+            ),
+            (
+                "due_at",
+                timestamp_value(old.due_at),
+                timestamp_value(new.due_at),
+            ),
+            ("notes", Some(old.notes.clone()), Some(new.notes.clone())),
+            (
+                "archived_at",
+                timestamp_value(old.archived_at),
+                timestamp_value(new.archived_at),
+            ),
+            (
+                "deleted_at",
+                timestamp_value(old.deleted_at),
+                timestamp_value(new.deleted_at),
+            ),
+        ];
+        for (field, old_value, new_value) in changes {
+            if old_value != new_value {
+                self.record(new.id, field, old_value, new_value);
+            }
+        }
+    }
+
+    // Applies `change` to the stored copy of `todo` when its version still
+    // matches, then bumps the version on both
+    fn write_todo(&mut self, todo: &mut Todo, change: impl FnOnce(&mut Todo)) -> StoreResult<()> {
+        let index = self
+            .index_of(todo.id)
+            .filter(|index| self.data.todos[*index].version == todo.version)
+            .ok_or(StoreError::Conflict(todo.id))?;
+        let old = self.data.todos[index].clone();
+        let stored = &mut self.data.todos[index];
+        change(stored);
+        stored.version += 1;
+        let new = stored.clone();
+        self.record_changes(&old, &new);
+        todo.version = new.version;
+        Ok(())
+    }
+
+    // Permanently deletes the todos matching `remove` and returns how many
+    fn remove_todos(&mut self, remove: impl Fn(&Todo) -> bool) -> usize {
+        let (removed, kept): (Vec<Todo>, Vec<Todo>) = std::mem::take(&mut self.data.todos)
+            .into_iter()
+            .partition(|todo| remove(todo));
+        self.data.todos = kept;
+        for todo in &removed {
+            self.record(todo.id, "deleted", Some(todo.label.clone()), None);
+        }
+        removed.len()
+    }
+
+    fn check_view_name(&self, name: &str, id: Option<i64>) -> StoreResult<()> {
+        if self
+            .data
+            .views
+            .iter()
+            .any(|view| view.name == name && Some(view.id) != id)
+        {
+            return Err(StoreError::Invalid(format!(
+                "a view named \"{}\" already exists",
+                name
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl TodoStore for MemoryStore {
+    // Nothing else can write to memory
+    fn data_version(&mut self) -> StoreResult<i64> {
+        Ok(0)
+    }
+
+    fn get_setting(&self, key: &str) -> Option<String> {
+        self.data.settings.get(key).cloned()
+    }
+
+    fn set_setting(&mut self, key: &str, value: &str) -> StoreResult<()> {
+        self.data
+            .settings
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn delete_setting(&mut self, key: &str) -> StoreResult<()> {
+        self.data.settings.remove(key);
+        Ok(())
+    }
+
+    fn get_views(&self) -> StoreResult<Vec<SavedView>> {
+        Ok(self.data.views.clone())
+    }
+
+    fn add_view(&mut self, name: &str, query: &str, sort: Sort) -> StoreResult<SavedView> {
+        self.check_view_name(name, None)?;
+        let view = SavedView {
+            id: self.data.next_view_id,
+            name: name.to_string(),
+            query: query.to_string(),
+            sort,
+        };
+        self.data.next_view_id += 1;
+        self.data.views.push(view.clone());
+        Ok(view)
+    }
+
+    fn update_view(&mut self, view: &SavedView) -> StoreResult<()> {
+        self.check_view_name(&view.name, Some(view.id))?;
+        if let Some(stored) = self
+            .data
+            .views
+            .iter_mut()
+            .find(|stored| stored.id == view.id)
+        {
+            *stored = view.clone();
+        }
+        Ok(())
+    }
+
+    fn delete_view(&mut self, id: i64) -> StoreResult<()> {
+        self.data.views.retain(|view| view.id != id);
+        Ok(())
+    }
+
+    fn get_history(&self, id: Option<i64>) -> StoreResult<Vec<HistoryEntry>> {
+        Ok(self
+            .data
+            .history
+            .iter()
+            .rev()
+            .filter(|entry| id.is_none() || Some(entry.todo_id) == id)
+            .cloned()
+            .collect())
+    }
+
+    fn get_all_todos(&self) -> StoreResult<HashMap<i64, Todo>> {
+        Ok(self
+            .data
+            .todos
+            .iter()
+            .filter(|todo| todo.deleted_at.is_none())
+            .map(|todo| (todo.id, todo.clone()))
+            .collect())
+    }
+
+    fn get_trash(&self) -> StoreResult<Vec<Todo>> {
+        let mut trash: Vec<Todo> = self
+            .data
+            .todos
+            .iter()
+            .filter(|todo| todo.deleted_at.is_some())
+            .cloned()
+            .collect();
+        trash.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at).then(b.id.cmp(&a.id)));
+        Ok(trash)
+    }
+
+    fn find_todos(&self, query: &Query, now: i64) -> StoreResult<Vec<Todo>> {
+        let include_archived = query.includes_archived();
+        Ok(self
+            .data
+            .todos
+            .iter()
+            .filter(|todo| todo.deleted_at.is_none())
+            .filter(|todo| include_archived || !todo.is_archived())
+            .filter(|todo| query.matches(todo, now))
+            .cloned()
+            .collect())
+    }
+
+    // Matches and ranks like the FTS5 index of `TodoDb`: every phrase has to
+    // appear in the label or notes, and hits are ordered by the same bm25
+    // score, computed over every todo including those in the trash
+    fn search(&self, input: &str, limit: usize) -> StoreResult<Vec<SearchHit>> {
+        let phrases: Vec<(Vec<String>, bool)> = search::phrases(input)
+            .into_iter()
+            .map(|phrase| {
+                let words = tokenize(&phrase.text).into_iter().map(|token| token.text);
+                (words.collect(), phrase.prefix)
+            })
+            .collect();
+        if phrases.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let indexed: Vec<Indexed> = self
+            .data
+            .todos
+            .iter()
+            .map(|todo| Indexed {
+                todo,
+                label: tokenize(&todo.label),
+                notes: tokenize(&todo.notes),
+            })
+            .collect();
+        let rows = indexed.len() as f64;
+        let average_len = indexed
+            .iter()
+            .map(|row| (row.label.len() + row.notes.len()) as f64)
+            .sum::<f64>()
+            / rows;
+        let found: Vec<Vec<(Vec<usize>, Vec<usize>)>> = indexed
+            .iter()
+            .map(|row| {
+                phrases
+                    .iter()
+                    .map(|(words, prefix)| {
+                        (
+                            find_phrase(&row.label, words, *prefix),
+                            find_phrase(&row.notes, words, *prefix),
+                        )
+                    })
+                    .collect()
+            })
+            .collect();
+        let idf: Vec<f64> = (0..phrases.len())
+            .map(|phrase| {
+                let with_phrase = found
+                    .iter()
+                    .filter(|row| !row[phrase].0.is_empty() || !row[phrase].1.is_empty())
+                    .count() as f64;
+                let idf = ((rows - with_phrase + 0.5) / (with_phrase + 0.5)).ln();
+                if idf <= 0.0 {
+                    1e-6
+                } else {
+                    idf
+                }
+            })
+            .collect();
+
+        let mut scored = vec![];
+        for (row, found) in indexed.iter().zip(&found) {
+            if row.todo.deleted_at.is_some()
+                || found
+                    .iter()
+                    .any(|(label, notes)| label.is_empty() && notes.is_empty())
+            {
+                continue;
+            }
+            let len = (row.label.len() + row.notes.len()) as f64;
+            let score: f64 = found
+                .iter()
+                .zip(&idf)
+                .map(|((label, notes), idf)| {
+                    let frequency = (label.len() + notes.len()) as f64;
+                    idf * frequency * (BM25_K1 + 1.0)
+                        / (frequency + BM25_K1 * (1.0 - BM25_B + BM25_B * len / average_len))
+                })
+                .sum();
+            scored.push((score, row, found));
+        }
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.todo.id.cmp(&b.1.todo.id)));
+
+        Ok(scored
+            .into_iter()
+            .take(limit)
+            .map(|(_, row, found)| {
+                let (label, notes): (Vec<_>, Vec<_>) = phrases
+                    .iter()
+                    .zip(found)
+                    .map(|((words, _), (label, notes))| {
+                        ((label.clone(), words.len()), (notes.clone(), words.len()))
+                    })
+                    .unzip();
+                SearchHit {
+                    id: row.todo.id,
+                    label: snippet(&row.todo.label, &row.label, &label),
+                    notes: snippet(&row.todo.notes, &row.notes, &notes),
+                }
+            })
+            .collect())
+    }
+
+    fn add_todo_with(&mut self, todo: &Todo, position: Option<f64>) -> StoreResult<Todo> {
+        let created_at = now();
+        let position = position.unwrap_or_else(|| {
+            self.data
+                .todos
+                .iter()
+                .map(|todo| todo.position)
+                .fold(0.0, f64::max)
+                + 1.0
+        });
+        let mut added = Todo::new(self.data.next_todo_id, todo.label.clone());
+        added.completed = todo.completed;
+        added.completed_at = todo.completed.then_some(created_at);
+        added.created_at = created_at;
+        added.updated_at = created_at;
+        added.due_at = todo.due_at;
+        added.priority = todo.priority;
+        added.notes = todo.notes.clone();
+        added.position = position;
+        self.data.next_todo_id += 1;
+        self.data.todos.push(added.clone());
+        self.record(added.id, "created", None, Some(added.label.clone()));
+        Ok(added)
+    }
+
+    fn set_position(&mut self, todo: &mut Todo, position: f64) -> StoreResult<()> {
+        self.write_todo(todo, |stored| stored.position = position)?;
+        todo.position = position;
+        Ok(())
+    }
+
+    fn toggle_todo(&mut self, todo: &mut Todo) -> StoreResult<()> {
+        let updated_at = now();
+        let completed = todo.completed;
+        let completed_at = if completed { Some(updated_at) } else { None };
+        self.write_todo(todo, |stored| {
+            stored.completed = completed;
+            stored.completed_at = completed_at;
+            stored.updated_at = updated_at;
+        })?;
+        todo.completed_at = completed_at;
+        todo.updated_at = updated_at;
+        Ok(())
+    }
+
+    fn set_archived(&mut self, todo: &mut Todo, archived: bool) -> StoreResult<()> {
+        let archived_at = if archived { Some(now()) } else { None };
+        self.write_todo(todo, |stored| stored.archived_at = archived_at)?;
+        todo.archived_at = archived_at;
+        Ok(())
+    }
+
+    fn archive_completed(&mut self, completed_before: i64) -> StoreResult<usize> {
+        let archived_at = now();
+        let mut archived = 0;
+        for index in 0..self.data.todos.len() {
+            let todo = &self.data.todos[index];
+            if todo.completed
+                && todo.archived_at.is_none()
+                && todo.deleted_at.is_none()
+                && todo
+                    .completed_at
+                    .is_some_and(|completed_at| completed_at <= completed_before)
+            {
+                let mut todo = todo.clone();
+                self.write_todo(&mut todo, |stored| stored.archived_at = Some(archived_at))?;
+                archived += 1;
+            }
+        }
+        Ok(archived)
+    }
+
+    fn purge_archived(&mut self, archived_before: i64) -> StoreResult<usize> {
+        Ok(self.remove_todos(|todo| {
+            todo.archived_at
+                .is_some_and(|archived_at| archived_at < archived_before)
+        }))
+    }
+
+    fn set_deleted(&mut self, todo: &mut Todo, deleted: bool) -> StoreResult<()> {
+        let deleted_at = if deleted { Some(now()) } else { None };
+        self.write_todo(todo, |stored| stored.deleted_at = deleted_at)?;
+        todo.deleted_at = deleted_at;
+        Ok(())
+    }
+
+    fn delete_todo(&mut self, todo: &Todo) -> StoreResult<()> {
+        let removed =
+            self.remove_todos(|stored| stored.id == todo.id && stored.version == todo.version);
+        if removed == 0 {
+            return Err(StoreError::Conflict(todo.id));
+        }
+        Ok(())
+    }
+
+    fn empty_trash(&mut self, deleted_before: i64) -> StoreResult<usize> {
+        Ok(self.remove_todos(|todo| {
+            todo.deleted_at
+                .is_some_and(|deleted_at| deleted_at < deleted_before)
+        }))
+    }
+
+    fn update_todo(&mut self, todo: &mut Todo) -> StoreResult<()> {
+        let updated_at = now();
+        let edited = todo.clone();
+        self.write_todo(todo, |stored| {
+            stored.label = edited.label;
+            stored.completed = edited.completed;
+            stored.completed_at = edited.completed_at;
+            stored.due_at = edited.due_at;
+            stored.priority = edited.priority;
+            stored.notes = edited.notes;
+            stored.updated_at = updated_at;
+        })?;
+        todo.updated_at = updated_at;
+        Ok(())
+    }
+
+    fn begin(&mut self) -> StoreResult<()> {
+        if self.snapshot.is_some() {
+            return Err(StoreError::Invalid(String::from(
+                "a transaction is already open",
+            )));
+        }
+        self.snapshot = Some(self.data.clone());
+        Ok(())
+    }
+
+    fn commit(&mut self) -> StoreResult<()> {
+        match self.snapshot.take() {
+            Some(_) => Ok(()),
+            None => Err(StoreError::Invalid(String::from("no transaction is open"))),
+        }
+    }
+
+    fn rollback(&mut self) {
+        if let Some(snapshot) = self.snapshot.take() {
+            self.data = snapshot;
+        }
+    }
+
+    fn backup_dir(&self) -> Option<&Path> {
+        None
+    }
+
+    fn backup(&self, _reason: Option<&str>) -> StoreResult<PathBuf> {
+        Err(StoreError::Invalid(String::from(
+            "todos kept in memory can't be backed up",
+        )))
+    }
+
+    fn verify_backup(&self, path: &Path) -> Result<(), String> {
+        Err(format!(
+            "todos kept in memory can't be restored from {}",
+            path.display()
+        ))
+    }
+
+    fn restore(&mut self, path: &Path) -> StoreResult<()> {
+        Err(StoreError::Invalid(format!(
+            "todos kept in memory can't be restored from {}",
+            path.display()
+        )))
+    }
+}
@@ -0,0 +1,469 @@
+use std::fmt;
+
+use crate::{
+    time,
+    todo::{Priority, Todo},
+};
+
+// A filter such as `status:open tag:work due<1w prio>=high "text"`.
+// Terms are separated by whitespace and must all match; prefix a term with
+// `-` to negate it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub terms: Vec<Term>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    Status(Status),
+    Tag(String),
+    // Compared against the due timestamp; todos without a due date never match
+    Due(Comparison, i64),
+    HasDue(bool),
+    Priority(Comparison, Priority),
+    // Compared against the age in seconds of the timestamp
+    Age(AgeField, Comparison, i64),
+    Text(String),
+    Not(Box<Term>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Open,
+    Done,
+    Archived,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgeField {
+    Created,
+    Updated,
+    Completed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    pub fn apply<T: PartialOrd>(self, left: T, right: T) -> bool {
+        match self {
+            Comparison::Eq => left == right,
+            Comparison::Lt => left < right,
+            Comparison::Le => left <= right,
+            Comparison::Gt => left > right,
+            Comparison::Ge => left >= right,
+        }
+    }
+
+    pub fn sql(self) -> &'static str {
+        match self {
+            Comparison::Eq => "=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError(pub String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// Splits the input on whitespace, keeping double quoted phrases together
+fn tokenize(input: &str) -> Result<Vec<String>, ParseError> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in input.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(c);
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(ParseError(String::from("unterminated quote")));
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+fn split_operator(token: &str) -> Option<(&str, Comparison, &str)> {
+    let index = token.find([':', '<', '>', '='])?;
+    let (field, rest) = token.split_at(index);
+    let (comparison, value) = if let Some(value) = rest.strip_prefix("<=") {
+        (Comparison::Le, value)
+    } else if let Some(value) = rest.strip_prefix(">=") {
+        (Comparison::Ge, value)
+    } else if let Some(value) = rest.strip_prefix('<') {
+        (Comparison::Lt, value)
+    } else if let Some(value) = rest.strip_prefix('>') {
+        (Comparison::Gt, value)
+    } else {
+        (Comparison::Eq, &rest[1..])
+    };
+    Some((field, comparison, value))
+}
+
+fn parse_priority(value: &str) -> Result<Priority, ParseError> {
+    match value {
+        "none" | "n" | "0" => Ok(Priority::None),
+        "low" | "l" | "1" => Ok(Priority::Low),
+        "medium" | "med" | "m" | "2" => Ok(Priority::Medium),
+        "high" | "h" | "3" => Ok(Priority::High),
+        _ => Err(ParseError(format!("unknown priority '{}'", value))),
+    }
+}
+
+// A due value is either a span from now ("1w") or anything `time::parse_due` accepts
+fn parse_due_value(value: &str, now: i64) -> Result<i64, ParseError> {
+    if let Some(seconds) = time::parse_duration(value) {
+        return now
+            .checked_add(seconds)
+            .ok_or_else(|| ParseError(format!("'{}' is too far in the future", value)));
+    }
+    match time::parse_due(value, now) {
+        Ok(Some(due_at)) => Ok(due_at),
+        _ => Err(ParseError(format!("invalid date '{}'", value))),
+    }
+}
+
+fn parse_term(token: &str, now: i64) -> Result<Term, ParseError> {
+    if let Some(negated) = token.strip_prefix('-').filter(|rest| !rest.is_empty()) {
+        return Ok(Term::Not(Box::new(parse_term(negated, now)?)));
+    }
+    if let Some(text) = token.strip_prefix('"') {
+        return Ok(Term::Text(text.trim_end_matches('"').to_lowercase()));
+    }
+
+    let (field, comparison, value) = match split_operator(token) {
+        Some(parts) => parts,
+        None => return Ok(Term::Text(token.to_lowercase())),
+    };
+    let value = value.to_lowercase();
+    if value.is_empty() {
+        return Err(ParseError(format!("missing value for '{}'", field)));
+    }
+    let only_eq = |term: Term| match comparison {
+        Comparison::Eq => Ok(term),
+        _ => Err(ParseError(format!("'{}' only supports ':'", field))),
+    };
+
+    let field = field.to_lowercase();
+    match field.as_str() {
+        "status" | "is" => only_eq(Term::Status(match value.as_str() {
+            "open" | "todo" => Status::Open,
+            "done" | "completed" => Status::Done,
+            "archived" => Status::Archived,
+            _ => return Err(ParseError(format!("unknown status '{}'", value))),
+        })),
+        "tag" => only_eq(Term::Tag(value.trim_start_matches('#').to_string())),
+        "due" => match (comparison, value.as_str()) {
+            (Comparison::Eq, "none") => Ok(Term::HasDue(false)),
+            (Comparison::Eq, "any") => Ok(Term::HasDue(true)),
+            (Comparison::Eq, "overdue") => Ok(Term::Due(Comparison::Lt, now)),
+            _ => Ok(Term::Due(comparison, parse_due_value(&value, now)?)),
+        },
+        "prio" | "priority" => Ok(Term::Priority(comparison, parse_priority(&value)?)),
+        "created" | "updated" | "completed" => {
+            let age_field = match field.as_str() {
+                "created" => AgeField::Created,
+                "updated" => AgeField::Updated,
+                _ => AgeField::Completed,
+            };
+            let seconds = time::parse_duration(&value)
+                .ok_or_else(|| ParseError(format!("invalid age '{}', try 3d or 2w", value)))?;
+            Ok(Term::Age(age_field, comparison, seconds))
+        }
+        _ => Err(ParseError(format!("unknown field '{}'", field))),
+    }
+}
+
+impl Query {
+    pub fn parse(input: &str, now: i64) -> Result<Self, ParseError> {
+        let terms = tokenize(input)?
+            .iter()
+            .map(|token| parse_term(token, now))
+            .collect::<Result<Vec<Term>, ParseError>>()?;
+        Ok(Self { terms })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn matches(&self, todo: &Todo, now: i64) -> bool {
+        self.terms.iter().all(|term| term.matches(todo, now))
+    }
+
+    // Whether any term asks for archived todos, which are otherwise left out
+    pub fn includes_archived(&self) -> bool {
+        self.terms.contains(&Term::Status(Status::Archived))
+    }
+}
+
+impl Term {
+    pub fn matches(&self, todo: &Todo, now: i64) -> bool {
+        match self {
+            Term::Status(Status::Open) => !todo.completed,
+            Term::Status(Status::Done) => todo.completed,
+            Term::Status(Status::Archived) => todo.is_archived(),
+            Term::Tag(tag) => todo
+                .tags()
+                .any(|todo_tag| todo_tag.eq_ignore_ascii_case(tag)),
+            Term::Due(comparison, timestamp) => todo
+                .due_at
+                .is_some_and(|due_at| comparison.apply(due_at, *timestamp)),
+            Term::HasDue(has_due) => todo.due_at.is_some() == *has_due,
+            Term::Priority(comparison, priority) => comparison.apply(todo.priority, *priority),
+            Term::Age(field, comparison, seconds) => {
+                let timestamp = match field {
+                    AgeField::Created => Some(todo.created_at),
+                    AgeField::Updated => Some(todo.updated_at),
+                    AgeField::Completed => todo.completed_at,
+                };
+                timestamp.is_some_and(|timestamp| comparison.apply(now - timestamp, *seconds))
+            }
+            Term::Text(text) => todo.label.to_lowercase().contains(text.as_str()),
+            Term::Not(term) => !term.matches(todo, now),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        db::TodoDb,
+        store::TodoStore,
+        time::{SECONDS_PER_DAY, SECONDS_PER_HOUR, SECONDS_PER_WEEK},
+    };
+
+    // A fixed time so relative dates give the same answers every run
+    const NOW: i64 = 1_700_000_000;
+
+    #[test]
+    fn due_spans_count_from_now() {
+        assert_eq!(parse_due_value("1w", NOW), Ok(NOW + SECONDS_PER_WEEK));
+        assert_eq!(parse_due_value("3d", NOW), Ok(NOW + 3 * SECONDS_PER_DAY));
+    }
+
+    #[test]
+    fn due_dates_fall_at_the_end_of_the_day() {
+        let today = parse_due_value("today", NOW).unwrap();
+        assert!((NOW..NOW + SECONDS_PER_DAY).contains(&today));
+        assert_eq!(
+            parse_due_value("tomorrow", NOW).ok(),
+            time::parse_due("1d", NOW).unwrap()
+        );
+        let date = parse_due_value("2024-01-31", NOW).unwrap();
+        assert_eq!(time::format_date(date), "2024-01-31");
+    }
+
+    #[test]
+    fn rejects_invalid_due_values() {
+        assert!(parse_due_value("soon", NOW).is_err());
+        assert!(parse_due_value("2024-13-01", NOW).is_err());
+    }
+
+    #[test]
+    fn rejects_due_values_that_overflow() {
+        // Fits in seconds, but not once added to now
+        assert!(parse_due_value("153722867280912930m", NOW).is_err());
+        assert!(parse_due_value("9999999999999999w", NOW).is_err());
+        assert!(Query::parse("due<9999999999999999w", NOW).is_err());
+    }
+
+    fn terms(input: &str) -> Vec<Term> {
+        Query::parse(input, NOW).unwrap().terms
+    }
+
+    fn error(input: &str) -> String {
+        Query::parse(input, NOW).unwrap_err().0
+    }
+
+    #[test]
+    fn parses_terms() {
+        assert_eq!(
+            terms("is:done tag:#Work prio>=h"),
+            [
+                Term::Status(Status::Done),
+                Term::Tag(String::from("work")),
+                Term::Priority(Comparison::Ge, Priority::High),
+            ]
+        );
+        assert_eq!(
+            terms("due<1d created>2w due:none"),
+            [
+                Term::Due(Comparison::Lt, NOW + SECONDS_PER_DAY),
+                Term::Age(AgeField::Created, Comparison::Gt, 2 * SECONDS_PER_WEEK),
+                Term::HasDue(false),
+            ]
+        );
+        assert_eq!(terms("due:overdue"), [Term::Due(Comparison::Lt, NOW)]);
+        assert!(Query::parse("  ", NOW).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parses_text_and_negation() {
+        assert_eq!(
+            terms("Milk \"Oat milk\" -tag:home -"),
+            [
+                Term::Text(String::from("milk")),
+                Term::Text(String::from("oat milk")),
+                Term::Not(Box::new(Term::Tag(String::from("home")))),
+                Term::Text(String::from("-")),
+            ]
+        );
+        // Quoted text can hold what would otherwise be a field
+        assert_eq!(
+            terms("\"due:soon\""),
+            [Term::Text(String::from("due:soon"))]
+        );
+    }
+
+    #[test]
+    fn archived_todos_are_only_included_when_asked_for() {
+        assert!(!Query::parse("status:done", NOW)
+            .unwrap()
+            .includes_archived());
+        assert!(Query::parse("tag:x status:archived", NOW)
+            .unwrap()
+            .includes_archived());
+        assert!(!Query::parse("-status:archived", NOW)
+            .unwrap()
+            .includes_archived());
+    }
+
+    #[test]
+    fn rejects_invalid_queries() {
+        assert_eq!(error("\"open"), "unterminated quote");
+        assert_eq!(error("colour:red"), "unknown field 'colour'");
+        assert_eq!(error("tag:"), "missing value for 'tag'");
+        assert_eq!(error("status<open"), "'status' only supports ':'");
+        assert_eq!(error("status:maybe"), "unknown status 'maybe'");
+        assert_eq!(error("prio:urgent"), "unknown priority 'urgent'");
+        assert_eq!(error("created<soon"), "invalid age 'soon', try 3d or 2w");
+        assert_eq!(error("due>later"), "invalid date 'later'");
+    }
+
+    // The SQL that `TodoDb` filters with has to agree with `Query::matches`,
+    // which the other stores use
+    #[test]
+    fn sql_and_memory_filters_agree() {
+        let mut db = TodoDb::new(":memory:").unwrap();
+        let now = time::now();
+        let todos = [
+            (
+                "Buy milk #home",
+                Priority::Low,
+                Some(now + SECONDS_PER_HOUR),
+                false,
+            ),
+            (
+                "File taxes #home #Money",
+                Priority::High,
+                Some(now - SECONDS_PER_DAY),
+                false,
+            ),
+            ("Report 100% done #work", Priority::Medium, None, true),
+            (
+                "snake_case names #work-notes",
+                Priority::None,
+                Some(now + SECONDS_PER_WEEK),
+                true,
+            ),
+            ("Café visit #Été", Priority::High, None, false),
+            ("ÉCOLE forms", Priority::None, Some(now), false),
+        ];
+        for (index, (label, priority, due_at, completed)) in todos.into_iter().enumerate() {
+            let mut todo = db.add_todo(label).unwrap();
+            todo.priority = priority;
+            todo.due_at = due_at;
+            db.update_todo(&mut todo).unwrap();
+            if completed {
+                todo.toggle();
+                db.toggle_todo(&mut todo).unwrap();
+            }
+            if index == 3 {
+                db.set_archived(&mut todo, true).unwrap();
+            }
+        }
+        let all = db.get_all_todos().unwrap();
+
+        for input in [
+            "",
+            "status:open",
+            "status:done",
+            "status:archived",
+            "-status:done",
+            "tag:home",
+            "tag:money",
+            "-tag:home",
+            "tag:work",
+            "tag:work-notes",
+            "tag:été",
+            "-tag:été",
+            "milk",
+            "MILK",
+            "100%",
+            "-100%",
+            "snake_case",
+            "e_o",
+            "café",
+            "-école",
+            "école",
+            "due<1d",
+            "due>=today",
+            "due:none",
+            "-due:any",
+            "due:overdue",
+            "prio>=medium",
+            "-prio:none",
+            "created<1h",
+            "-created<1h",
+            "completed<1d",
+            "-completed<1d",
+            "updated>1w",
+        ] {
+            let query = Query::parse(input, now).unwrap();
+            let found: Vec<i64> = db
+                .find_todos(&query, now)
+                .unwrap()
+                .iter()
+                .map(|todo| todo.id)
+                .collect();
+            let mut expected: Vec<i64> = all
+                .values()
+                .filter(|todo| query.includes_archived() || !todo.is_archived())
+                .filter(|todo| query.matches(todo, now))
+                .map(|todo| todo.id)
+                .collect();
+            expected.sort_unstable();
+            assert_eq!(found, expected, "{}", input);
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use std::iter;
+
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+// Markers wrapped around matched text in search snippets
+pub const MATCH_START: char = '\u{1}';
+pub const MATCH_END: char = '\u{2}';
+
+// Words shown in a snippet, as asked of FTS5's `snippet()`
+pub const SNIPPET_TOKENS: usize = 12;
+
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub id: i64,
+    // Snippets with matches wrapped in `MATCH_START` and `MATCH_END`
+    pub label: String,
+    pub notes: String,
+}
+
+// A phrase of a search, matching consecutive words. The last word is matched
+// as a prefix when `prefix` is set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phrase {
+    pub text: String,
+    pub prefix: bool,
+}
+
+// Splits user input into phrases. Double quoted text is kept as a phrase,
+// other words become a phrase each with punctuation dropped, and words ending
+// in '*' as well as the last word typed are matched as prefixes.
+pub fn phrases(input: &str) -> Vec<Phrase> {
+    let mut phrases = vec![];
+    let mut rest = input;
+    while let Some(start) = rest.find('"') {
+        phrases.extend(words(&rest[..start], false));
+        rest = &rest[start + 1..];
+        let end = rest.find('"').unwrap_or(rest.len());
+        let text = rest[..end].split_whitespace().collect::<Vec<_>>().join(" ");
+        if !text.is_empty() {
+            phrases.push(Phrase {
+                text,
+                prefix: false,
+            });
+        }
+        rest = rest.get(end + 1..).unwrap_or("");
+    }
+    phrases.extend(words(rest, !rest.ends_with(char::is_whitespace)));
+    phrases
+}
+
+// Turns user input into an FTS5 query, quoting every phrase so punctuation
+// can't break the query
+pub fn fts_query(input: &str) -> String {
+    phrases(input)
+        .iter()
+        .map(|phrase| {
+            format!(
+                "\"{}\"{}",
+                phrase.text,
+                if phrase.prefix { "*" } else { "" }
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn words(input: &str, prefix_last: bool) -> Vec<Phrase> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    words
+        .iter()
+        .enumerate()
+        .filter_map(|(index, word)| {
+            let prefix = word.ends_with('*') || (prefix_last && index == words.len() - 1);
+            let text: String = word
+                .chars()
+                .filter(|c| c.is_alphanumeric() || *c == '_')
+                .collect();
+            if text.is_empty() {
+                return None;
+            }
+            Some(Phrase { text, prefix })
+        })
+        .collect()
+}
+
+// A word of text as FTS5's `unicode61 remove_diacritics 2` tokenizer sees it,
+// along with the bytes of the text it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+// Splits `text` into runs of letters and digits, lowercased and with accents
+// removed so "École" and "ecole" are the same word
+pub fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens: Vec<Token> = vec![];
+    let mut in_token = false;
+    for (start, c) in text.char_indices() {
+        if !(c.is_alphanumeric() || is_combining_mark(c)) {
+            in_token = false;
+            continue;
+        }
+        if !in_token {
+            tokens.push(Token {
+                text: String::new(),
+                start,
+                end: start,
+            });
+            in_token = true;
+        }
+        let token = tokens.last_mut().expect("a token was just started");
+        token.end = start + c.len_utf8();
+        token.text.extend(
+            iter::once(c)
+                .nfd()
+                .filter(|c| !is_combining_mark(*c))
+                .flat_map(char::to_lowercase),
+        );
+    }
+    tokens.retain(|token| !token.text.is_empty());
+    tokens
+}
+
+// Where the words of `phrase` appear one after another in `tokens`, the last
+// one as a prefix when `prefix` is set
+pub fn find_phrase(tokens: &[Token], phrase: &[String], prefix: bool) -> Vec<usize> {
+    if phrase.is_empty() || phrase.len() > tokens.len() {
+        return vec![];
+    }
+    (0..=tokens.len() - phrase.len())
+        .filter(|&start| {
+            phrase.iter().enumerate().all(|(index, word)| {
+                let token = &tokens[start + index].text;
+                if prefix && index == phrase.len() - 1 {
+                    token.starts_with(word.as_str())
+                } else {
+                    token == word
+                }
+            })
+        })
+        .collect()
+}
+
+// A snippet of `text` picked and highlighted like FTS5's `snippet()` does.
+// `found` holds where each phrase was found in `tokens`, with its length.
+pub fn snippet(text: &str, tokens: &[Token], found: &[(Vec<usize>, usize)]) -> String {
+    // Phrase, first token and length of each match, in the order of the text
+    let mut matches: Vec<(usize, usize, usize)> = found
+        .iter()
+        .enumerate()
+        .flat_map(|(phrase, (starts, len))| starts.iter().map(move |start| (phrase, *start, *len)))
+        .collect();
+    matches.sort_by_key(|(phrase, start, _)| (*start, *phrase));
+
+    // Windows with more phrases in them are better, and so are windows
+    // starting a sentence
+    let window_score = |first: usize| {
+        let mut seen = vec![false; found.len()];
+        let mut score = 0;
+        let mut span: Option<(usize, usize)> = None;
+        for &(phrase, start, len) in &matches {
+            if (first..first + SNIPPET_TOKENS).contains(&start) {
+                score += if seen[phrase] { 1 } else { 1000 };
+                seen[phrase] = true;
+                span = Some((span.map_or(start, |span| span.0), start + len));
+            }
+        }
+        (score, span)
+    };
+    let sentences = sentence_starts(text, tokens);
+    let mut best = (0, 0);
+    for &(_, start, _) in &matches {
+        let (score, span) = window_score(start);
+        if score > best.0 {
+            let (span_start, span_end) = span.unwrap_or((start, start));
+            let centred = span_start as i64
+                - (SNIPPET_TOKENS as i64 - (span_end as i64 - span_start as i64)) / 2;
+            let centred = centred.min(tokens.len() as i64 - SNIPPET_TOKENS as i64);
+            best = (score, centred.max(0) as usize);
+        }
+        if tokens.len() > SNIPPET_TOKENS {
+            let sentence = sentences
+                .iter()
+                .rev()
+                .find(|sentence| **sentence <= start)
+                .copied()
+                .unwrap_or(0);
+            if sentence < start {
+                let bonus = if sentence == 0 { 120 } else { 100 };
+                let score = window_score(sentence).0 + bonus;
+                if score > best.0 {
+                    best = (score, sentence);
+                }
+            }
+        }
+    }
+    let first = best.1;
+    let last = (first + SNIPPET_TOKENS).min(tokens.len());
+
+    let mut snippet = String::new();
+    let mut position = 0;
+    if first > 0 {
+        snippet.push_str("...");
+        position = tokens[first].start;
+    }
+    let end = if last < tokens.len() {
+        tokens[last - 1].end
+    } else {
+        text.len()
+    };
+
+    // Overlapping matches are highlighted as one, and matches running past
+    // the end of the snippet are cut there
+    let mut highlighted: Vec<(usize, usize)> = vec![];
+    for &(_, start, len) in &matches {
+        if start < first || start >= last {
+            continue;
+        }
+        let end = (start + len).min(last);
+        match highlighted.last_mut() {
+            Some(previous) if start < previous.1 => previous.1 = previous.1.max(end),
+            _ => highlighted.push((start, end)),
+        }
+    }
+    for (start, end) in highlighted {
+        snippet.push_str(&text[position..tokens[start].start]);
+        snippet.push(MATCH_START);
+        snippet.push_str(&text[tokens[start].start..tokens[end - 1].end]);
+        snippet.push(MATCH_END);
+        position = tokens[end - 1].end;
+    }
+    snippet.push_str(&text[position..end]);
+    if last < tokens.len() {
+        snippet.push_str("...");
+    }
+    snippet
+}
+
+// The tokens starting a sentence: the first one, and those following a '.'
+// or ':' and some whitespace
+fn sentence_starts(text: &str, tokens: &[Token]) -> Vec<usize> {
+    tokens
+        .iter()
+        .enumerate()
+        .filter(|(index, token)| {
+            if *index == 0 {
+                return true;
+            }
+            let before = &text[..token.start];
+            let trimmed = before.trim_end_matches([' ', '\t', '\n', '\r']);
+            trimmed.len() < before.len() && trimmed.ends_with(['.', ':'])
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<String> {
+        tokenize(text).into_iter().map(|token| token.text).collect()
+    }
+
+    #[test]
+    fn quotes_every_word() {
+        assert_eq!(fts_query("buy milk "), "\"buy\" \"milk\"");
+        // The last word is still being typed
+        assert_eq!(fts_query("buy mi"), "\"buy\" \"mi\"*");
+        assert_eq!(fts_query("mi* buy "), "\"mi\"* \"buy\"");
+        assert_eq!(fts_query("   "), "");
+    }
+
+    #[test]
+    fn punctuation_cant_break_the_query() {
+        assert_eq!(fts_query("AND OR NOT "), "\"AND\" \"OR\" \"NOT\"");
+        assert_eq!(
+            fts_query("(a OR b) ^c: -d "),
+            "\"a\" \"OR\" \"b\" \"c\" \"d\""
+        );
+        assert_eq!(fts_query("snake_case "), "\"snake_case\"");
+        assert_eq!(fts_query("!!! ? "), "");
+    }
+
+    #[test]
+    fn keeps_quoted_phrases() {
+        assert_eq!(fts_query("\"oat  milk\" buy"), "\"oat milk\" \"buy\"*");
+        assert_eq!(fts_query("say \"hi"), "\"say\" \"hi\"");
+        assert_eq!(fts_query("\"\" x "), "\"x\"");
+    }
+
+    #[test]
+    fn tokenizes_like_unicode61() {
+        assert_eq!(words("École, naïve CAFÉ"), ["ecole", "naive", "cafe"]);
+        assert_eq!(
+            words("snake_case e-mail 42x"),
+            ["snake", "case", "e", "mail", "42x"]
+        );
+        // Letters that aren't an accent on a base letter are kept
+        assert_eq!(words("straße ø"), ["straße", "ø"]);
+        let tokens = tokenize(" é-b");
+        assert_eq!((tokens[0].start, tokens[0].end), (1, 3));
+    }
+
+    #[test]
+    fn finds_phrases() {
+        let tokens = tokenize("the work report and work");
+        let phrase = |text: &str| words(text);
+        assert_eq!(find_phrase(&tokens, &phrase("work"), false), [1, 4]);
+        assert_eq!(find_phrase(&tokens, &phrase("work rep"), true), [1]);
+        assert_eq!(
+            find_phrase(&tokens, &phrase("work rep"), false),
+            Vec::<usize>::new()
+        );
+    }
+
+    #[test]
+    fn snippets_highlight_matches() {
+        let text = "Call mom: about the weekend work trip";
+        let tokens = tokenize(text);
+        assert_eq!(
+            snippet(text, &tokens, &[(vec![0], 2)]),
+            "\u{1}Call mom\u{2}: about the weekend work trip"
+        );
+        let text =
+            "one two three four five six seven eight nine ten eleven twelve thirteen fourteen";
+        let tokens = tokenize(text);
+        assert_eq!(
+            snippet(text, &tokens, &[(vec![13], 1)]),
+            "...three four five six seven eight nine ten eleven twelve thirteen \u{1}fourteen\u{2}"
+        );
+        assert_eq!(
+            snippet(text, &tokens, &[]),
+            "one two three four five six seven eight nine ten eleven twelve..."
+        );
+    }
+}
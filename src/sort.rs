@@ -0,0 +1,102 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use crate::todo::Todo;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortMode {
+    #[default]
+    Manual,
+    Created,
+    Updated,
+    Due,
+    Priority,
+    #[serde(rename = "alpha")]
+    Alphabetical,
+    Status,
+}
+
+impl SortMode {
+    pub const ALL: [SortMode; 7] = [
+        SortMode::Manual,
+        SortMode::Created,
+        SortMode::Updated,
+        SortMode::Due,
+        SortMode::Priority,
+        SortMode::Alphabetical,
+        SortMode::Status,
+    ];
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|mode| *mode == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortMode::Manual => "manual",
+            SortMode::Created => "created",
+            SortMode::Updated => "updated",
+            SortMode::Due => "due",
+            SortMode::Priority => "priority",
+            SortMode::Alphabetical => "alpha",
+            SortMode::Status => "status",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+
+    // Orders two todos ascending by this mode. Ties fall back to the manual order
+    // so the list never jumps around between refreshes.
+    pub fn compare(self, a: &Todo, b: &Todo) -> Ordering {
+        let ordering = match self {
+            SortMode::Manual => a.position.total_cmp(&b.position),
+            SortMode::Created => a.created_at.cmp(&b.created_at),
+            SortMode::Updated => a.updated_at.cmp(&b.updated_at),
+            // Todos without a due date go last
+            SortMode::Due => match (a.due_at, b.due_at) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+            // Highest priority first
+            SortMode::Priority => b.priority.cmp(&a.priority),
+            SortMode::Alphabetical => a.label.to_lowercase().cmp(&b.label.to_lowercase()),
+            // Open todos first
+            SortMode::Status => a.completed.cmp(&b.completed),
+        };
+        ordering
+            .then(a.position.total_cmp(&b.position))
+            .then(a.id.cmp(&b.id))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Sort {
+    pub mode: SortMode,
+    pub descending: bool,
+}
+
+impl Sort {
+    pub fn compare(self, a: &Todo, b: &Todo) -> Ordering {
+        let ordering = self.mode.compare(a, b);
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+
+    pub fn indicator(self) -> String {
+        format!(
+            "{} {}",
+            self.mode.name(),
+            if self.descending { "desc" } else { "asc" }
+        )
+    }
+}
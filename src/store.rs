@@ -0,0 +1,504 @@
+use std::{
+    collections::HashMap,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    db::TodoDb, history::HistoryEntry, json_store::JsonStore, query::Query, search::SearchHit,
+    sort::Sort, todo::Todo, view::SavedView,
+};
+
+// Days todos stay in the trash unless the `trash_retention_days` setting
+// says otherwise
+const DEFAULT_TRASH_RETENTION_DAYS: i64 = 30;
+
+// Why a read or write didn't happen
+#[derive(Debug)]
+pub enum StoreError {
+    // The todo was changed or deleted elsewhere since it was read
+    Conflict(i64),
+    Sqlite(rusqlite::Error),
+    Io(io::Error),
+    // The data can't be used, like a file that doesn't parse or a name
+    // that is taken
+    Invalid(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Conflict(id) => write!(f, "todo {} was changed elsewhere", id),
+            StoreError::Sqlite(err) => err.fmt(f),
+            StoreError::Io(err) => err.fmt(f),
+            StoreError::Invalid(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(err: rusqlite::Error) -> Self {
+        StoreError::Sqlite(err)
+    }
+}
+
+impl From<io::Error> for StoreError {
+    fn from(err: io::Error) -> Self {
+        StoreError::Io(err)
+    }
+}
+
+pub type StoreResult<T> = Result<T, StoreError>;
+
+// Where todos, views, settings and history are kept. Writes to a todo check
+// its `version` against the stored one and fail with `StoreError::Conflict`
+// when it moved on, then bump it.
+pub trait TodoStore {
+    // Changes whenever another process writes to the store, so polling it
+    // tells whether the data in memory is stale
+    fn data_version(&mut self) -> StoreResult<i64>;
+
+    fn get_setting(&self, key: &str) -> Option<String>;
+    fn set_setting(&mut self, key: &str, value: &str) -> StoreResult<()>;
+    fn delete_setting(&mut self, key: &str) -> StoreResult<()>;
+
+    // Days after which the trash is emptied, or None when it is kept forever
+    fn trash_retention_days(&self) -> Option<i64> {
+        match self.get_setting("trash_retention_days").as_deref() {
+            None => Some(DEFAULT_TRASH_RETENTION_DAYS),
+            Some("off") => None,
+            Some(days) => days.parse().ok(),
+        }
+    }
+
+    fn get_views(&self) -> StoreResult<Vec<SavedView>>;
+    fn add_view(&mut self, name: &str, query: &str, sort: Sort) -> StoreResult<SavedView>;
+    fn update_view(&mut self, view: &SavedView) -> StoreResult<()>;
+    fn delete_view(&mut self, id: i64) -> StoreResult<()>;
+
+    // Changes to todo `id`, or to all todos, newest first
+    fn get_history(&self, id: Option<i64>) -> StoreResult<Vec<HistoryEntry>>;
+    // Every todo that isn't in the trash
+    fn get_all_todos(&self) -> StoreResult<HashMap<i64, Todo>>;
+    // Todos in the trash, most recently deleted first
+    fn get_trash(&self) -> StoreResult<Vec<Todo>>;
+    // The todos matching `query` by id. Archived todos are left out unless
+    // the query asks for them, and todos in the trash always are.
+    fn find_todos(&self, query: &Query, now: i64) -> StoreResult<Vec<Todo>>;
+    // Full-text search over labels and notes, best matches first
+    fn search(&self, input: &str, limit: usize) -> StoreResult<Vec<SearchHit>>;
+
+    // Adds a todo at the end of the manual order
+    fn add_todo(&mut self, label: &str) -> StoreResult<Todo> {
+        self.add_todo_with(&Todo::new(0, label.to_string()), None)
+    }
+    // Adds a todo with the label, completion, due date, priority and notes of
+    // `todo` in a single write, at `position` in the manual order or at the end
+    fn add_todo_with(&mut self, todo: &Todo, position: Option<f64>) -> StoreResult<Todo>;
+    // Moves `todo` in the manual order
+    fn set_position(&mut self, todo: &mut Todo, position: f64) -> StoreResult<()>;
+    // Persists `todo.completed` and stamps the completion and update times on `todo`
+    fn toggle_todo(&mut self, todo: &mut Todo) -> StoreResult<()>;
+    fn set_archived(&mut self, todo: &mut Todo, archived: bool) -> StoreResult<()>;
+    // Archives every completed todo that was completed at or before `completed_before`
+    fn archive_completed(&mut self, completed_before: i64) -> StoreResult<usize>;
+    // Permanently deletes archived todos that were archived before `archived_before`
+    fn purge_archived(&mut self, archived_before: i64) -> StoreResult<usize>;
+    // Moves `todo` to the trash, or back out of it
+    fn set_deleted(&mut self, todo: &mut Todo, deleted: bool) -> StoreResult<()>;
+    // Permanently deletes `todo`, which is normally in the trash
+    fn delete_todo(&mut self, todo: &Todo) -> StoreResult<()>;
+    // Permanently deletes the todos that went to the trash before `deleted_before`
+    fn empty_trash(&mut self, deleted_before: i64) -> StoreResult<usize>;
+    // Persists the editable fields of `todo` and stamps the update time on it
+    fn update_todo(&mut self, todo: &mut Todo) -> StoreResult<()>;
+
+    // Starts a transaction for several writes that belong together, kept or
+    // dropped as a whole by `commit` or `rollback`
+    fn begin(&mut self) -> StoreResult<()>;
+    fn commit(&mut self) -> StoreResult<()>;
+    fn rollback(&mut self);
+
+    // Where backups go, or None when the store can't be backed up
+    fn backup_dir(&self) -> Option<&Path>;
+    // Copies the store into the backup directory and returns the file
+    fn backup(&self, reason: Option<&str>) -> StoreResult<PathBuf>;
+    // Checks that `path` is an intact backup before it is restored
+    fn verify_backup(&self, path: &Path) -> Result<(), String>;
+    // Replaces everything in the store with the backup at `path`, which
+    // should have passed `verify_backup`
+    fn restore(&mut self, path: &Path) -> StoreResult<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    // A SQLite database, the default
+    #[default]
+    Sqlite,
+    // A single JSON file that reads well in diffs, for keeping todos in git
+    Json,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 2] = [Backend::Sqlite, Backend::Json];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Sqlite => "sqlite",
+            Backend::Json => "json",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|backend| backend.name() == name)
+    }
+
+    // Where the store is kept unless configured otherwise
+    pub fn default_path(self) -> PathBuf {
+        PathBuf::from(match self {
+            Backend::Sqlite => "todos.db",
+            Backend::Json => "todos.json",
+        })
+    }
+}
+
+// Opens the `backend` store at `path`, creating it when it doesn't exist
+pub fn open(backend: Backend, path: &Path) -> StoreResult<Box<dyn TodoStore>> {
+    Ok(match backend {
+        Backend::Sqlite => {
+            let url = path
+                .to_str()
+                .ok_or_else(|| StoreError::Invalid(format!("invalid path {}", path.display())))?;
+            Box::new(TodoDb::new(url)?)
+        }
+        Backend::Json => Box::new(JsonStore::open(path)?),
+    })
+}
+
+// A scratch directory for tests, emptied when created and removed when
+// dropped, so a failed test doesn't leave files behind for the next run
+#[cfg(test)]
+pub(crate) struct TempDir(PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("todo-rs-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+
+    use super::*;
+    use crate::{memory_store::MemoryStore, time::now, todo::Priority};
+
+    // Runs `scenario` against every backend and checks they all end up with
+    // what SQLite gives
+    fn assert_same<T: PartialEq + Debug>(name: &str, scenario: impl Fn(&mut dyn TodoStore) -> T) {
+        let dir = TempDir::new(name);
+        let expected = scenario(&mut TodoDb::new(":memory:").unwrap());
+        assert_eq!(scenario(&mut MemoryStore::new()), expected, "memory");
+        let mut json = JsonStore::open(&dir.join("todos.json")).unwrap();
+        assert_eq!(scenario(&mut json), expected, "json");
+        // What was saved reads back the same
+        let reopened = JsonStore::open(&dir.join("todos.json")).unwrap();
+        assert_eq!(
+            labels(&reopened.get_all_todos().unwrap()),
+            labels(&json.get_all_todos().unwrap()),
+        );
+    }
+
+    fn labels(todos: &HashMap<i64, Todo>) -> Vec<(i64, String, i64)> {
+        let mut labels: Vec<_> = todos
+            .values()
+            .map(|todo| (todo.id, todo.label.clone(), todo.version))
+            .collect();
+        labels.sort_unstable();
+        labels
+    }
+
+    fn add(store: &mut dyn TodoStore, label: &str, notes: &str) -> Todo {
+        let mut todo = store.add_todo(label).unwrap();
+        if !notes.is_empty() {
+            todo.notes = notes.to_string();
+            store.update_todo(&mut todo).unwrap();
+        }
+        todo
+    }
+
+    #[test]
+    fn writes_check_versions() {
+        assert_same("versions", |store| {
+            let mut todo = add(store, "Buy milk", "");
+            let stale = todo.clone();
+            todo.label = String::from("Buy oat milk");
+            store.update_todo(&mut todo).unwrap();
+            assert_eq!(todo.version, stale.version + 1);
+
+            let mut other = stale.clone();
+            other.label = String::from("Buy cheese");
+            let conflict = store.update_todo(&mut other);
+            assert!(matches!(conflict, Err(StoreError::Conflict(id)) if id == todo.id));
+            let mut other = stale.clone();
+            assert!(store.toggle_todo(&mut other).is_err());
+            let mut other = stale;
+            assert!(store.set_position(&mut other, 10.0).is_err());
+
+            todo.toggle();
+            store.toggle_todo(&mut todo).unwrap();
+            store.set_position(&mut todo, 0.5).unwrap();
+            labels(&store.get_all_todos().unwrap())
+        });
+    }
+
+    #[test]
+    fn rolled_back_transactions_leave_nothing_behind() {
+        assert_same("rollback", |store| {
+            let mut kept = add(store, "Kept", "");
+            store.begin().unwrap();
+            add(store, "Dropped", "");
+            kept.label = String::from("Renamed");
+            store.update_todo(&mut kept).unwrap();
+            store.rollback();
+            let after_rollback = labels(&store.get_all_todos().unwrap());
+
+            store.begin().unwrap();
+            add(store, "Committed", "");
+            store.commit().unwrap();
+            (after_rollback, labels(&store.get_all_todos().unwrap()))
+        });
+    }
+
+    #[test]
+    fn trashed_todos_can_be_restored() {
+        assert_same("trash", |store| {
+            let mut trashed = add(store, "Old plan", "work");
+            let mut purged = add(store, "Older plan", "work");
+            add(store, "New plan", "work");
+            store.set_deleted(&mut trashed, true).unwrap();
+            store.set_deleted(&mut purged, true).unwrap();
+            let trash: Vec<i64> = store
+                .get_trash()
+                .unwrap()
+                .iter()
+                .map(|todo| todo.id)
+                .collect();
+            let search: Vec<i64> = store
+                .search("work", 10)
+                .unwrap()
+                .iter()
+                .map(|hit| hit.id)
+                .collect();
+            let all = Query::parse("", now()).unwrap();
+            let found: Vec<i64> = store
+                .find_todos(&all, now())
+                .unwrap()
+                .iter()
+                .map(|todo| todo.id)
+                .collect();
+
+            store.set_deleted(&mut trashed, false).unwrap();
+            assert_eq!(store.empty_trash(now() + 1).unwrap(), 1);
+            let mut trash = trash;
+            trash.sort_unstable();
+            (
+                trash,
+                search,
+                found,
+                labels(&store.get_all_todos().unwrap()),
+                store.get_trash().unwrap().len(),
+            )
+        });
+    }
+
+    #[test]
+    fn history_records_every_change() {
+        assert_same("history", |store| {
+            let mut todo = add(store, "Draft", "");
+            todo.label = String::from("Final");
+            todo.notes = String::from("for review");
+            todo.priority = Priority::High;
+            todo.due_at = Some(1_700_000_000);
+            store.update_todo(&mut todo).unwrap();
+            todo.toggle();
+            store.toggle_todo(&mut todo).unwrap();
+            store.set_archived(&mut todo, true).unwrap();
+            let other = add(store, "Other", "");
+            store.delete_todo(&other).unwrap();
+            let entries = |id| {
+                store
+                    .get_history(id)
+                    .unwrap()
+                    .into_iter()
+                    .map(|entry| {
+                        let stamped = entry.field == "completed_at" || entry.field == "archived_at";
+                        let keep = |value: Option<String>| value.filter(|_| !stamped);
+                        (
+                            entry.todo_id,
+                            entry.field,
+                            keep(entry.old_value),
+                            keep(entry.new_value),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            };
+            (entries(Some(todo.id)), entries(None))
+        });
+    }
+
+    #[test]
+    fn todos_are_added_with_their_fields_in_one_write() {
+        assert_same("add-with", |store| {
+            add(store, "First", "");
+            let mut pasted = Todo::new(0, String::from("Pasted"));
+            pasted.completed = true;
+            pasted.due_at = Some(1_700_000_000);
+            pasted.priority = Priority::Medium;
+            pasted.notes = String::from("from the clipboard");
+            let todo = store.add_todo_with(&pasted, Some(0.5)).unwrap();
+            let stored = store.get_all_todos().unwrap().remove(&todo.id).unwrap();
+            assert_eq!(
+                (stored.completed_at, stored.position, stored.version),
+                (todo.completed_at, todo.position, todo.version)
+            );
+            let history: Vec<String> = store
+                .get_history(Some(todo.id))
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.field)
+                .collect();
+            (
+                todo.label,
+                todo.completed,
+                todo.completed_at.is_some(),
+                todo.due_at,
+                todo.priority,
+                todo.notes,
+                todo.position,
+                todo.version,
+                history,
+            )
+        });
+    }
+
+    #[test]
+    fn queries_find_the_same_todos() {
+        assert_same("find", |store| {
+            let now = now();
+            let mut due_soon = add(store, "Pay rent #home", "");
+            due_soon.due_at = Some(now + 3600);
+            due_soon.priority = Priority::High;
+            store.update_todo(&mut due_soon).unwrap();
+            let mut overdue = add(store, "File taxes #home #money", "");
+            overdue.due_at = Some(now - 3600);
+            store.update_todo(&mut overdue).unwrap();
+            let mut done = add(store, "Write report #work", "");
+            done.toggle();
+            store.toggle_todo(&mut done).unwrap();
+            let mut archived = add(store, "Old report #work", "");
+            archived.toggle();
+            store.toggle_todo(&mut archived).unwrap();
+            store.set_archived(&mut archived, true).unwrap();
+            let mut trashed = add(store, "Trashed report #work", "");
+            store.set_deleted(&mut trashed, true).unwrap();
+
+            [
+                "",
+                "status:open",
+                "status:done",
+                "status:archived",
+                "tag:work",
+                "tag:home -tag:money",
+                "due:overdue",
+                "due<1d",
+                "due:any",
+                "due:none",
+                "prio>=high",
+                "report",
+                "REPORT status:archived",
+                "created<1d",
+                "completed<1h",
+            ]
+            .iter()
+            .map(|input| {
+                let query = Query::parse(input, now).unwrap();
+                let mut ids: Vec<i64> = store
+                    .find_todos(&query, now)
+                    .unwrap()
+                    .iter()
+                    .map(|todo| todo.id)
+                    .collect();
+                ids.sort_unstable();
+                (input.to_string(), ids)
+            })
+            .collect::<Vec<_>>()
+        });
+    }
+
+    #[test]
+    fn search_matches_and_ranks_like_sqlite() {
+        assert_same("search", |store| {
+            add(store, "Write work report", "the work summary");
+            add(store, "Buy milk", "");
+            add(store, "Visit the école", "bring forms");
+            add(store, "Call mom", "about the weekend work trip");
+            add(store, "work", "");
+            add(store, "Work work work", "");
+            add(store, "Plan", "alpha beta gamma delta. Epsilon zeta eta theta iota kappa lambda mu nu xi work pi rho");
+            let mut trashed = add(store, "Trashed work", "work work work");
+            store.set_deleted(&mut trashed, true).unwrap();
+
+            let ecole: Vec<i64> = store
+                .search("ecole", 10)
+                .unwrap()
+                .iter()
+                .map(|hit| hit.id)
+                .collect();
+            assert_eq!(ecole, [3]);
+            [
+                "work",
+                "wor",
+                "ecole",
+                "\"work report\"",
+                "mom work",
+                "the",
+                "xi",
+                "nothing",
+                "",
+            ]
+            .iter()
+            .map(|input| {
+                let hits = store.search(input, 10).unwrap();
+                let hits: Vec<_> = hits
+                    .into_iter()
+                    .map(|hit| (hit.id, hit.label, hit.notes))
+                    .collect();
+                (input.to_string(), hits)
+            })
+            .collect::<Vec<_>>()
+        });
+    }
+}
@@ -0,0 +1,506 @@
+use std::{env, str::FromStr};
+
+use ratatui::style::{Color, Modifier, Style, Stylize};
+
+// Styles for every coloured element of the UI. Built-in themes are listed in
+// `BUILTIN_THEMES`; user themes in the config start from one of those and
+// override single slots with style strings such as "bold yellow on #202020".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    // Secondary text: due dates, archived and done markers, notes
+    pub muted: Style,
+    pub done_marker: Style,
+    pub done_label: Style,
+    pub priority_low: Style,
+    pub priority_medium: Style,
+    pub priority_high: Style,
+    pub overdue: Style,
+    // Matched text in search and finder results
+    pub matched: Style,
+    pub selected: Style,
+    // Block borders, drawn over the popup style inside popups
+    pub border: Style,
+    pub notice: Style,
+    pub active_view: Style,
+    pub mode_normal: Style,
+    pub mode_add: Style,
+    pub mode_edit: Style,
+    pub mode_delete: Style,
+    pub mode_select: Style,
+    pub mode_search: Style,
+    pub mode_archive: Style,
+    pub mode_details: Style,
+    pub mode_filter: Style,
+    pub mode_views: Style,
+    pub popup: Style,
+    pub popup_edit: Style,
+    pub popup_danger: Style,
+    pub popup_filter: Style,
+    pub popup_views: Style,
+}
+
+pub const BUILTIN_THEMES: [&str; 4] = ["dark", "light", "high-contrast", "monochrome"];
+
+pub const SLOTS: [&str; 27] = [
+    "muted",
+    "done_marker",
+    "done_label",
+    "priority_low",
+    "priority_medium",
+    "priority_high",
+    "overdue",
+    "matched",
+    "selected",
+    "border",
+    "notice",
+    "active_view",
+    "mode_normal",
+    "mode_add",
+    "mode_edit",
+    "mode_delete",
+    "mode_select",
+    "mode_search",
+    "mode_archive",
+    "mode_details",
+    "mode_filter",
+    "mode_views",
+    "popup",
+    "popup_edit",
+    "popup_danger",
+    "popup_filter",
+    "popup_views",
+];
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        let style = Style::default();
+        Self {
+            muted: style.dark_gray(),
+            done_marker: style.green().bold(),
+            done_label: style.bold().crossed_out(),
+            priority_low: style.blue(),
+            priority_medium: style.yellow(),
+            priority_high: style.red().bold(),
+            overdue: style.red(),
+            matched: style.yellow().bold(),
+            selected: style.bold(),
+            border: style.dark_gray(),
+            notice: style.yellow(),
+            active_view: style.black().on_light_magenta().bold(),
+            mode_normal: style.white().bold(),
+            mode_add: style.light_cyan().bold(),
+            mode_edit: style.blue().bold(),
+            mode_delete: style.red().bold(),
+            mode_select: style.green().bold(),
+            mode_search: style.yellow().bold(),
+            mode_archive: style.magenta().bold(),
+            mode_details: style.cyan().bold(),
+            mode_filter: style.yellow().bold(),
+            mode_views: style.light_magenta().bold(),
+            popup: style.black().on_light_cyan(),
+            popup_edit: style.black().on_light_blue(),
+            popup_danger: style.black().on_light_red(),
+            popup_filter: style.black().on_light_yellow(),
+            popup_views: style.black().on_light_magenta(),
+        }
+    }
+
+    // For terminals with a light background, where yellow and light text wash out
+    pub fn light() -> Self {
+        let style = Style::default();
+        Self {
+            muted: style.gray(),
+            done_marker: style.green().bold(),
+            done_label: style.crossed_out().dim(),
+            priority_low: style.blue(),
+            priority_medium: style.magenta(),
+            priority_high: style.red().bold(),
+            overdue: style.red().bold(),
+            matched: style.blue().bold().underlined(),
+            selected: style.bold(),
+            border: style.dark_gray(),
+            notice: style.magenta().bold(),
+            active_view: style.white().on_blue().bold(),
+            mode_normal: style.black().bold(),
+            mode_add: style.cyan().bold(),
+            mode_edit: style.blue().bold(),
+            mode_delete: style.red().bold(),
+            mode_select: style.green().bold(),
+            mode_search: style.magenta().bold(),
+            mode_archive: style.magenta().bold(),
+            mode_details: style.cyan().bold(),
+            mode_filter: style.magenta().bold(),
+            mode_views: style.blue().bold(),
+            popup: style.black().on_white(),
+            popup_edit: style.black().on_white(),
+            popup_danger: style.white().on_red(),
+            popup_filter: style.black().on_white(),
+            popup_views: style.black().on_white(),
+        }
+    }
+
+    // Bright colours only, with bold and reversed text doing most of the work
+    pub fn high_contrast() -> Self {
+        let style = Style::default();
+        let popup = style.black().on_white().bold();
+        Self {
+            muted: style.white(),
+            done_marker: style.light_green().bold(),
+            done_label: style.white().crossed_out(),
+            priority_low: style.light_cyan().bold(),
+            priority_medium: style.light_yellow().bold(),
+            priority_high: style.light_red().bold().reversed(),
+            overdue: style.light_red().bold().underlined(),
+            matched: style.black().on_light_yellow().bold(),
+            selected: style.reversed().bold(),
+            border: style.bold(),
+            notice: style.black().on_light_yellow().bold(),
+            active_view: style.black().on_white().bold(),
+            mode_normal: style.white().bold().reversed(),
+            mode_add: style.light_cyan().bold().reversed(),
+            mode_edit: style.light_cyan().bold().reversed(),
+            mode_delete: style.light_red().bold().reversed(),
+            mode_select: style.light_green().bold().reversed(),
+            mode_search: style.light_yellow().bold().reversed(),
+            mode_archive: style.light_magenta().bold().reversed(),
+            mode_details: style.light_cyan().bold().reversed(),
+            mode_filter: style.light_yellow().bold().reversed(),
+            mode_views: style.light_magenta().bold().reversed(),
+            popup,
+            popup_edit: popup,
+            popup_danger: style.white().on_red().bold(),
+            popup_filter: popup,
+            popup_views: popup,
+        }
+    }
+
+    // No colours at all, only text attributes. Used for NO_COLOR.
+    pub fn monochrome() -> Self {
+        let style = Style::default();
+        Self {
+            muted: style.dim(),
+            done_marker: style.bold(),
+            done_label: style.crossed_out().dim(),
+            priority_low: style,
+            priority_medium: style.bold(),
+            priority_high: style.bold().underlined(),
+            overdue: style.bold().underlined(),
+            matched: style.bold().underlined(),
+            selected: style.reversed(),
+            border: style,
+            notice: style.bold(),
+            active_view: style.reversed().bold(),
+            mode_normal: style.bold(),
+            mode_add: style.bold(),
+            mode_edit: style.bold(),
+            mode_delete: style.bold().reversed(),
+            mode_select: style.bold(),
+            mode_search: style.bold(),
+            mode_archive: style.bold(),
+            mode_details: style.bold(),
+            mode_filter: style.bold(),
+            mode_views: style.bold(),
+            popup: style,
+            popup_edit: style,
+            popup_danger: style.bold(),
+            popup_filter: style,
+            popup_views: style,
+        }
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "high-contrast" => Some(Self::high_contrast()),
+            "monochrome" => Some(Self::monochrome()),
+            _ => None,
+        }
+    }
+
+    pub fn slot_mut(&mut self, name: &str) -> Option<&mut Style> {
+        Some(match name {
+            "muted" => &mut self.muted,
+            "done_marker" => &mut self.done_marker,
+            "done_label" => &mut self.done_label,
+            "priority_low" => &mut self.priority_low,
+            "priority_medium" => &mut self.priority_medium,
+            "priority_high" => &mut self.priority_high,
+            "overdue" => &mut self.overdue,
+            "matched" => &mut self.matched,
+            "selected" => &mut self.selected,
+            "border" => &mut self.border,
+            "notice" => &mut self.notice,
+            "active_view" => &mut self.active_view,
+            "mode_normal" => &mut self.mode_normal,
+            "mode_add" => &mut self.mode_add,
+            "mode_edit" => &mut self.mode_edit,
+            "mode_delete" => &mut self.mode_delete,
+            "mode_select" => &mut self.mode_select,
+            "mode_search" => &mut self.mode_search,
+            "mode_archive" => &mut self.mode_archive,
+            "mode_details" => &mut self.mode_details,
+            "mode_filter" => &mut self.mode_filter,
+            "mode_views" => &mut self.mode_views,
+            "popup" => &mut self.popup,
+            "popup_edit" => &mut self.popup_edit,
+            "popup_danger" => &mut self.popup_danger,
+            "popup_filter" => &mut self.popup_filter,
+            "popup_views" => &mut self.popup_views,
+            _ => return None,
+        })
+    }
+
+    // Rewrites every slot to colours the terminal can show. Without colour
+    // support the monochrome theme is used, since the others rely on colour
+    // to tell things apart.
+    pub fn degrade(mut self, mode: ColorMode) -> Self {
+        if mode == ColorMode::Mono {
+            return Self::monochrome();
+        }
+        for name in SLOTS {
+            if let Some(style) = self.slot_mut(name) {
+                style.fg = style.fg.map(|color| mode.convert(color));
+                style.bg = style.bg.map(|color| mode.convert(color));
+            }
+        }
+        self
+    }
+}
+
+// Parses a style string: any of the modifiers below, a foreground colour and
+// "on <colour>" for the background. Colours are names such as "light_red",
+// "#rrggbb" or a 0-255 palette index.
+pub fn parse_style(input: &str) -> Result<Style, String> {
+    let mut style = Style::default();
+    let mut words = input.split_whitespace();
+    while let Some(word) = words.next() {
+        let modifier = match word {
+            "bold" => Some(Modifier::BOLD),
+            "dim" => Some(Modifier::DIM),
+            "italic" => Some(Modifier::ITALIC),
+            "underlined" => Some(Modifier::UNDERLINED),
+            "reversed" => Some(Modifier::REVERSED),
+            "crossed_out" => Some(Modifier::CROSSED_OUT),
+            _ => None,
+        };
+        if let Some(modifier) = modifier {
+            style = style.add_modifier(modifier);
+        } else if word == "on" {
+            let color = words
+                .next()
+                .ok_or_else(|| format!("missing colour after 'on' in '{}'", input))?;
+            style = style.bg(parse_color(color)?);
+        } else if style.fg.is_some() {
+            return Err(format!("more than one foreground colour in '{}'", input));
+        } else {
+            style = style.fg(parse_color(word)?);
+        }
+    }
+    Ok(style)
+}
+
+fn parse_color(input: &str) -> Result<Color, String> {
+    Color::from_str(input).map_err(|_| format!("unknown colour '{}'", input))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+    Mono,
+}
+
+// The 16 ANSI colours in the order of their palette indices, with the usual
+// xterm values for finding the nearest one
+const ANSI_COLORS: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+// RGB value of a 256-colour palette entry
+fn indexed_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI_COLORS[index as usize].1,
+        16..=231 => {
+            let index = index - 16;
+            (
+                CUBE_LEVELS[(index / 36) as usize],
+                CUBE_LEVELS[(index / 6 % 6) as usize],
+                CUBE_LEVELS[(index % 6) as usize],
+            )
+        }
+        _ => {
+            let level = 8 + (index - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+fn nearest_indexed(rgb: (u8, u8, u8)) -> u8 {
+    (16..=255)
+        .min_by_key(|index| distance(rgb, indexed_rgb(*index)))
+        .unwrap_or(16)
+}
+
+fn nearest_ansi(rgb: (u8, u8, u8)) -> Color {
+    ANSI_COLORS
+        .iter()
+        .min_by_key(|(_, ansi)| distance(rgb, *ansi))
+        .map_or(Color::Reset, |(color, _)| *color)
+}
+
+impl ColorMode {
+    // NO_COLOR turns colours off; otherwise the depth is guessed from
+    // COLORTERM and TERM
+    pub fn detect() -> Self {
+        if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            return ColorMode::Mono;
+        }
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorMode::TrueColor
+        } else if term == "dumb" {
+            ColorMode::Mono
+        } else if term.contains("256color") {
+            ColorMode::Ansi256
+        } else {
+            ColorMode::Ansi16
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "truecolor" => Some(ColorMode::TrueColor),
+            "256" => Some(ColorMode::Ansi256),
+            "16" => Some(ColorMode::Ansi16),
+            "mono" => Some(ColorMode::Mono),
+            _ => None,
+        }
+    }
+
+    fn convert(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorMode::Ansi256, Color::Rgb(r, g, b)) => Color::Indexed(nearest_indexed((r, g, b))),
+            (ColorMode::Ansi16, Color::Rgb(r, g, b)) => nearest_ansi((r, g, b)),
+            (ColorMode::Ansi16, Color::Indexed(index)) => nearest_ansi(indexed_rgb(index)),
+            _ => color,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_styles() {
+        let style = Style::default();
+        assert_eq!(
+            parse_style("bold yellow on #202020"),
+            Ok(style.yellow().bold().bg(Color::Rgb(32, 32, 32)))
+        );
+        assert_eq!(
+            parse_style("reversed  underlined"),
+            Ok(style.reversed().underlined())
+        );
+        assert_eq!(parse_style("208"), Ok(style.fg(Color::Indexed(208))));
+        assert_eq!(parse_style("on light_red"), Ok(style.on_light_red()));
+        assert_eq!(parse_style(""), Ok(style));
+    }
+
+    #[test]
+    fn rejects_invalid_styles() {
+        assert_eq!(
+            parse_style("bold on"),
+            Err(String::from("missing colour after 'on' in 'bold on'"))
+        );
+        assert_eq!(
+            parse_style("red blue"),
+            Err(String::from(
+                "more than one foreground colour in 'red blue'"
+            ))
+        );
+        assert_eq!(
+            parse_style("blinking red"),
+            Err(String::from("unknown colour 'blinking'"))
+        );
+        assert_eq!(
+            parse_style("on #12345"),
+            Err(String::from("unknown colour '#12345'"))
+        );
+    }
+
+    #[test]
+    fn no_color_gives_monochrome() {
+        let previous = env::var_os("NO_COLOR");
+        env::set_var("NO_COLOR", "1");
+        let mode = ColorMode::detect();
+        match previous {
+            Some(value) => env::set_var("NO_COLOR", value),
+            None => env::remove_var("NO_COLOR"),
+        }
+        assert_eq!(mode, ColorMode::Mono);
+        assert_eq!(Theme::dark().degrade(mode), Theme::monochrome());
+    }
+
+    #[test]
+    fn maps_truecolor_to_the_nearest_palette_colour() {
+        let ansi16 = |r, g, b| ColorMode::Ansi16.convert(Color::Rgb(r, g, b));
+        assert_eq!(ansi16(0, 0, 0), Color::Black);
+        assert_eq!(ansi16(200, 10, 10), Color::Red);
+        assert_eq!(ansi16(250, 20, 20), Color::LightRed);
+        assert_eq!(ansi16(120, 130, 125), Color::DarkGray);
+        assert_eq!(ansi16(250, 250, 250), Color::White);
+        assert_eq!(ansi16(80, 80, 250), Color::LightBlue);
+        // Palette entries above 15 have no name, so they are mapped by value
+        assert_eq!(
+            ColorMode::Ansi16.convert(Color::Indexed(196)),
+            Color::LightRed
+        );
+        assert_eq!(
+            ColorMode::Ansi256.convert(Color::Rgb(255, 0, 0)),
+            Color::Indexed(196)
+        );
+        assert_eq!(
+            ColorMode::TrueColor.convert(Color::Rgb(1, 2, 3)),
+            Color::Rgb(1, 2, 3)
+        );
+
+        let mut theme = Theme::dark();
+        theme.popup = Style::default()
+            .fg(Color::Rgb(0, 0, 0))
+            .bg(Color::Rgb(0, 250, 250));
+        let theme = theme.degrade(ColorMode::Ansi16);
+        assert_eq!(theme.popup, Style::default().black().on_light_cyan());
+    }
+}
@@ -0,0 +1,129 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Days, Local, NaiveDate};
+
+pub const SECONDS_PER_MINUTE: i64 = 60;
+pub const SECONDS_PER_HOUR: i64 = 60 * SECONDS_PER_MINUTE;
+pub const SECONDS_PER_DAY: i64 = 24 * SECONDS_PER_HOUR;
+pub const SECONDS_PER_WEEK: i64 = 7 * SECONDS_PER_DAY;
+
+// Current time as seconds since the unix epoch
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+// Formats `timestamp` relative to `now`, e.g. "2h ago" or "in 3d"
+pub fn relative(timestamp: i64, now: i64) -> String {
+    let delta = now - timestamp;
+    let seconds = delta.abs();
+    let amount = if seconds < SECONDS_PER_MINUTE {
+        return String::from("just now");
+    } else if seconds < SECONDS_PER_HOUR {
+        format!("{}m", seconds / SECONDS_PER_MINUTE)
+    } else if seconds < SECONDS_PER_DAY {
+        format!("{}h", seconds / SECONDS_PER_HOUR)
+    } else if seconds < SECONDS_PER_WEEK {
+        format!("{}d", seconds / SECONDS_PER_DAY)
+    } else if seconds < 30 * SECONDS_PER_DAY {
+        format!("{}w", seconds / SECONDS_PER_WEEK)
+    } else if seconds < 365 * SECONDS_PER_DAY {
+        format!("{}mo", seconds / (30 * SECONDS_PER_DAY))
+    } else {
+        format!("{}y", seconds / (365 * SECONDS_PER_DAY))
+    };
+
+    if delta >= 0 {
+        format!("{} ago", amount)
+    } else {
+        format!("in {}", amount)
+    }
+}
+
+// Last second of `date` in the local timezone
+fn end_of_day(date: NaiveDate) -> Option<i64> {
+    date.and_hms_opt(23, 59, 59)?
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|datetime| datetime.timestamp())
+}
+
+fn today(now: i64) -> NaiveDate {
+    DateTime::from_timestamp(now, 0)
+        .map(|datetime| datetime.with_timezone(&Local).date_naive())
+        .unwrap_or_default()
+}
+
+// Parses a due date such as "today", "tomorrow", "3d", "2w" or "2024-01-31".
+// Due dates fall at the end of the given day. An empty input clears the date.
+pub fn parse_due(input: &str, now: i64) -> Result<Option<i64>, String> {
+    let input = input.trim().to_lowercase();
+    if input.is_empty() {
+        return Ok(None);
+    }
+
+    let today = today(now);
+    let offset_days = match input.as_str() {
+        "today" => Some(0),
+        "tomorrow" => Some(1),
+        _ => parse_duration(&input).map(|seconds| (seconds / SECONDS_PER_DAY) as u64),
+    };
+    let date = match offset_days {
+        Some(days) => today.checked_add_days(Days::new(days)),
+        None => NaiveDate::parse_from_str(&input, "%Y-%m-%d").ok(),
+    };
+
+    date.and_then(end_of_day)
+        .map(Some)
+        .ok_or_else(|| format!("Invalid due date: {}", input))
+}
+
+// Parses a span such as "3d" or "2w" into seconds
+pub fn parse_duration(input: &str) -> Option<i64> {
+    let unit_start = input.find(|c: char| !c.is_ascii_digit())?;
+    let amount: i64 = input[..unit_start].parse().ok()?;
+    let unit = match &input[unit_start..] {
+        "m" => SECONDS_PER_MINUTE,
+        "h" => SECONDS_PER_HOUR,
+        "d" => SECONDS_PER_DAY,
+        "w" => SECONDS_PER_WEEK,
+        _ => return None,
+    };
+    // Spans too long to count in seconds are as invalid as a bad unit
+    amount.checked_mul(unit)
+}
+
+// Formats a date the way `parse_due` reads it back
+pub fn format_date(timestamp: i64) -> String {
+    format(timestamp, "%Y-%m-%d")
+}
+
+// Formats a timestamp in local time with a strftime format
+pub fn format(timestamp: i64, format: &str) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|datetime| datetime.with_timezone(&Local).format(format).to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90m"), Some(90 * SECONDS_PER_MINUTE));
+        assert_eq!(parse_duration("3d"), Some(3 * SECONDS_PER_DAY));
+        assert_eq!(parse_duration("2w"), Some(2 * SECONDS_PER_WEEK));
+        assert_eq!(parse_duration("3y"), None);
+        assert_eq!(parse_duration("d"), None);
+    }
+
+    #[test]
+    fn rejects_durations_that_overflow() {
+        assert_eq!(parse_duration("9999999999999999w"), None);
+        assert_eq!(parse_duration("99999999999999999999d"), None);
+        assert!(parse_due("9999999999999999w", now()).is_err());
+    }
+}
@@ -1,8 +1,77 @@
-#[derive(Debug)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    #[default]
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+impl Priority {
+    pub fn from_i64(value: i64) -> Self {
+        match value {
+            1 => Priority::Low,
+            2 => Priority::Medium,
+            3 => Priority::High,
+            _ => Priority::None,
+        }
+    }
+
+    pub fn as_i64(self) -> i64 {
+        self as i64
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Priority::None => Priority::Low,
+            Priority::Low => Priority::Medium,
+            Priority::Medium => Priority::High,
+            Priority::High => Priority::None,
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        [
+            Priority::None,
+            Priority::Low,
+            Priority::Medium,
+            Priority::High,
+        ]
+        .into_iter()
+        .find(|priority| priority.label() == label)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Priority::None => "none",
+            Priority::Low => "low",
+            Priority::Medium => "medium",
+            Priority::High => "high",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Todo {
     pub id: i64,
     pub label: String,
     pub completed: bool,
+    pub completed_at: Option<i64>,
+    pub archived_at: Option<i64>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub due_at: Option<i64>,
+    pub priority: Priority,
+    pub notes: String,
+    // Place in the manual order, between the todos around it
+    pub position: f64,
+    // Bumped on every write, to notice edits made elsewhere in the meantime
+    pub version: i64,
+    // Set while the todo is in the trash
+    pub deleted_at: Option<i64>,
 }
 
 impl Todo {
@@ -11,10 +80,37 @@ impl Todo {
             id,
             label,
             completed: false,
+            completed_at: None,
+            archived_at: None,
+            created_at: 0,
+            updated_at: 0,
+            due_at: None,
+            priority: Priority::None,
+            notes: String::new(),
+            position: id as f64,
+            version: 0,
+            deleted_at: None,
         }
     }
 
     pub fn toggle(&mut self) {
         self.completed = !self.completed;
     }
+
+    pub fn is_archived(&self) -> bool {
+        self.archived_at.is_some()
+    }
+
+    // Words in the label starting with '#', without the '#'
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.label
+            .split_whitespace()
+            .filter_map(|word| word.strip_prefix('#'))
+            .map(|tag| tag.trim_end_matches(|c: char| !c.is_alphanumeric()))
+            .filter(|tag| !tag.is_empty())
+    }
+
+    pub fn is_overdue(&self, now: i64) -> bool {
+        !self.completed && self.due_at.is_some_and(|due_at| due_at < now)
+    }
 }
@@ -1,10 +1,13 @@
 use std::io;
 use std::panic;
 
+#[cfg(unix)]
+use signal_hook::consts::SIGTSTP;
+
 use crate::ui;
 
 use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture},
+    event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture},
     terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
 };
 
@@ -23,24 +26,60 @@ impl Tui {
     }
 
     pub fn enter(&mut self) -> io::Result<()> {
-        terminal::enable_raw_mode()?;
-        crossterm::execute!(io::stderr(), EnterAlternateScreen, EnableMouseCapture)?;
-
         let panic_hook = panic::take_hook();
         panic::set_hook(Box::new(move |panic| {
             Self::reset().expect("Failed to reset the terminal");
             panic_hook(panic);
         }));
 
+        self.resume()
+    }
+
+    // Takes over the terminal again, after `enter` or a suspend. The whole
+    // screen is redrawn on the next draw.
+    pub fn resume(&mut self) -> io::Result<()> {
+        terminal::enable_raw_mode()?;
+        crossterm::execute!(
+            io::stderr(),
+            EnterAlternateScreen,
+            EnableMouseCapture,
+            EnableBracketedPaste
+        )?;
+
         self.terminal.hide_cursor()?;
         self.terminal.clear()?;
 
         Ok(())
     }
 
+    // Gives the terminal back to the shell and stops the process until it is
+    // continued with `fg`, then takes the terminal back. Without job control
+    // there is nothing to do.
+    #[cfg(unix)]
+    pub fn suspend(&mut self) -> io::Result<()> {
+        self.exit()?;
+        signal_hook::low_level::emulate_default_handler(SIGTSTP)?;
+        self.resume()
+    }
+
+    #[cfg(not(unix))]
+    pub fn suspend(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    // Forgets what is on the screen, so the next draw paints all of it
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.terminal.clear()
+    }
+
     pub fn reset() -> io::Result<()> {
         terminal::disable_raw_mode()?;
-        crossterm::execute!(io::stderr(), LeaveAlternateScreen, DisableMouseCapture)?;
+        crossterm::execute!(
+            io::stderr(),
+            LeaveAlternateScreen,
+            DisableMouseCapture,
+            DisableBracketedPaste
+        )?;
         Ok(())
     }
 
@@ -50,6 +89,17 @@ impl Tui {
         Ok(())
     }
 
+    // Runs `run` while another program such as $EDITOR has the terminal,
+    // without reading input meant for it
+    pub fn run_external<T>(&mut self, run: impl FnOnce() -> T) -> io::Result<T> {
+        self.events.pause();
+        self.exit()?;
+        let result = run();
+        self.resume()?;
+        self.events.resume();
+        Ok(result)
+    }
+
     pub fn draw(&mut self, app: &mut App) -> io::Result<()> {
         self.terminal.draw(|frame| ui::render(app, frame))?;
         Ok(())
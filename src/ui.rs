@@ -1,9 +1,18 @@
-use crate::app::{App, CurrentScreen};
+use std::ops::Range;
+
+use crate::{
+    app::{App, CurrentScreen, HitAreas, ViewInputMode},
+    keys::{self, Action, KeyContext},
+    search::{MATCH_END, MATCH_START},
+    time,
+    todo::Priority,
+};
 use ratatui::{
     prelude::*,
     widgets::{
-        Block, BorderType, Borders, Clear, HighlightSpacing, List, ListItem, Paragraph, Scrollbar,
-        ScrollbarOrientation,
+        block::{Position, Title},
+        Block, BorderType, Borders, Clear, HighlightSpacing, List, ListItem, ListState, Paragraph,
+        Scrollbar, ScrollbarOrientation, Wrap,
     },
     Frame,
 };
@@ -30,29 +39,112 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         .split(popup_layout[1])[1]
 }
 
+// Scrolls `state` so its selection is inside a viewport of `height` rows and
+// returns the range of item indices that fall inside the viewport.
+fn viewport(state: &mut ListState, len: usize, height: usize) -> Range<usize> {
+    let mut offset = state.offset().min(len.saturating_sub(1));
+    if let Some(selected) = state.selected() {
+        if selected < offset {
+            offset = selected;
+        } else if height > 0 && selected >= offset + height {
+            offset = selected + 1 - height;
+        }
+    }
+    *state.offset_mut() = offset;
+    offset..(offset + height).min(len)
+}
+
+// Records where each item of a list was drawn, starting from the item at
+// `offset` at the top of `inner`
+fn record_rows(app: &mut App, inner: Rect, offset: usize, heights: impl Iterator<Item = u16>) {
+    let mut y = inner.y;
+    for (index, height) in heights.enumerate() {
+        if y >= inner.bottom() {
+            break;
+        }
+        let height = height.min(inner.bottom() - y);
+        app.hit_areas
+            .rows
+            .push((Rect::new(inner.x, y, inner.width, height), offset + index));
+        y += height;
+    }
+}
+
+// Width of the highlight symbol in front of each todo
+const HIGHLIGHT_WIDTH: u16 = 2;
+
 fn render_body(app: &mut App, chunk: Rect, frame: &mut Frame) {
+    let title = if app.filter.is_some() {
+        format!(" Todo [{}] ", app.filter_input)
+    } else {
+        String::from(" Todo ")
+    };
     let header_block = Block::default()
-        .title(" Todo ")
+        .title(title)
         .borders(Borders::ALL)
+        .border_style(app.config.theme.border)
         .border_type(BorderType::Double);
 
-    if app.todos.len() == 0 {
+    if app.visible_todos.is_empty() {
         let empty_todo = Paragraph::new(" Nothing left to do! 😎").block(header_block);
         frame.render_widget(empty_todo, chunk);
         return;
     }
 
-    let mut list_items: Vec<ListItem> = vec![];
-    for (_id, todo) in app.todos.iter() {
+    // Only the rows that fit in the viewport are turned into list items
+    let window = viewport(
+        &mut app.selected_todo,
+        app.visible_todos.len(),
+        chunk.height.saturating_sub(2) as usize,
+    );
+    let mut list_state = ListState::default().with_selected(
+        app.selected_todo
+            .selected()
+            .map(|selected| selected - window.start),
+    );
+    app.scroll_state = app
+        .scroll_state
+        .content_length(app.visible_todos.len())
+        .position(app.selected_todo.selected().unwrap_or(0));
+
+    let inner = chunk.inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+    app.hit_areas.checkbox = Some((inner.x + HIGHLIGHT_WIDTH, 3));
+    record_rows(app, inner, window.start, window.clone().map(|_| 1));
+
+    let now = time::now();
+    let mut list_items: Vec<ListItem> = Vec::with_capacity(window.len());
+    for id in &app.visible_todos[window] {
+        let todo = &app.todos[id];
         let mut completed = Span::styled("[ ] ", Style::default());
-        let mut label = Span::styled(todo.label.clone(), Style::default());
+        let mut label = Span::styled(todo.label.as_str(), Style::default());
         if todo.completed {
             completed = completed
                 .content("[] ")
-                .style(Style::default().green().bold());
-            label = label.set_style(Style::default().bold().crossed_out());
+                .style(app.config.theme.done_marker);
+            label = label.set_style(app.config.theme.done_label);
+        }
+        let priority = match todo.priority {
+            Priority::None => Span::raw(""),
+            Priority::Low => Span::styled("! ", app.config.theme.priority_low),
+            Priority::Medium => Span::styled("!! ", app.config.theme.priority_medium),
+            Priority::High => Span::styled("!!! ", app.config.theme.priority_high),
+        };
+        let mut spans = vec![completed, priority, label];
+        if let Some(due_at) = todo.due_at {
+            let style = if todo.is_overdue(now) {
+                app.config.theme.overdue
+            } else {
+                app.config.theme.muted
+            };
+            spans.push(Span::styled(
+                format!("  due {}", time::relative(due_at, now)),
+                style,
+            ));
         }
-        let list_item = ListItem::new(Line::from(vec![completed, label]));
+        let list_item = ListItem::new(Line::from(spans));
         list_items.push(list_item);
     }
 
@@ -63,11 +155,12 @@ fn render_body(app: &mut App, chunk: Rect, frame: &mut Frame) {
 
     let list = List::new(list_items)
         .block(header_block)
+        .highlight_style(app.config.theme.selected)
         .highlight_spacing(HighlightSpacing::Always)
         .highlight_symbol(" ")
         .repeat_highlight_symbol(true);
 
-    frame.render_stateful_widget(list, chunk, &mut app.selected_todo);
+    frame.render_stateful_widget(list, chunk, &mut list_state);
     frame.render_stateful_widget(
         scrollball,
         chunk.inner(&Margin {
@@ -78,99 +171,963 @@ fn render_body(app: &mut App, chunk: Rect, frame: &mut Frame) {
     );
 }
 
+fn render_archive_body(app: &mut App, chunk: Rect, frame: &mut Frame) {
+    let header_block = Block::default()
+        .title(" Archive ")
+        .borders(Borders::ALL)
+        .border_style(app.config.theme.border)
+        .border_type(BorderType::Double);
+
+    if app.archived_todos.is_empty() {
+        let empty_archive = Paragraph::new(" Nothing archived yet").block(header_block);
+        frame.render_widget(empty_archive, chunk);
+        return;
+    }
+
+    let window = viewport(
+        &mut app.selected_archived_todo,
+        app.archived_todos.len(),
+        chunk.height.saturating_sub(2) as usize,
+    );
+    let mut list_state = ListState::default().with_selected(
+        app.selected_archived_todo
+            .selected()
+            .map(|selected| selected - window.start),
+    );
+
+    let inner = chunk.inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+    record_rows(app, inner, window.start, window.clone().map(|_| 1));
+
+    let list_items: Vec<ListItem> = app.archived_todos[window]
+        .iter()
+        .map(|id| ListItem::new(Span::raw(app.todos[id].label.as_str())))
+        .collect();
+
+    let list = List::new(list_items)
+        .block(header_block)
+        .highlight_style(app.config.theme.selected)
+        .highlight_spacing(HighlightSpacing::Always)
+        .highlight_symbol("> ")
+        .style(app.config.theme.muted);
+
+    frame.render_stateful_widget(list, chunk, &mut list_state);
+}
+
+// Splits a search snippet into spans, highlighting the matched parts
+fn highlight_snippet(snippet: &str, matched_style: Style) -> Vec<Span<'_>> {
+    let mut spans = vec![];
+    for (index, part) in snippet.split(MATCH_START).enumerate() {
+        if index == 0 {
+            spans.push(Span::raw(part));
+            continue;
+        }
+        let (matched, rest) = part.split_once(MATCH_END).unwrap_or((part, ""));
+        spans.push(Span::styled(matched, matched_style));
+        spans.push(Span::raw(rest));
+    }
+    spans
+}
+
+fn render_trash_body(app: &mut App, chunk: Rect, frame: &mut Frame) {
+    let header_block = Block::default()
+        .title(" Trash ")
+        .borders(Borders::ALL)
+        .border_style(app.config.theme.border)
+        .border_type(BorderType::Double);
+
+    if app.trash.is_empty() {
+        let empty_trash = Paragraph::new(" The trash is empty").block(header_block);
+        frame.render_widget(empty_trash, chunk);
+        return;
+    }
+
+    let window = viewport(
+        &mut app.selected_trashed_todo,
+        app.trash.len(),
+        chunk.height.saturating_sub(2) as usize,
+    );
+    let mut list_state = ListState::default().with_selected(
+        app.selected_trashed_todo
+            .selected()
+            .map(|selected| selected - window.start),
+    );
+
+    let inner = chunk.inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+    record_rows(app, inner, window.start, window.clone().map(|_| 1));
+
+    let now = time::now();
+    let list_items: Vec<ListItem> = app.trash[window]
+        .iter()
+        .map(|todo| {
+            let mut spans = vec![Span::raw(todo.label.as_str())];
+            if let Some(deleted_at) = todo.deleted_at {
+                spans.push(Span::styled(
+                    format!("  deleted {}", time::relative(deleted_at, now)),
+                    app.config.theme.muted,
+                ));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+
+    let list = List::new(list_items)
+        .block(header_block)
+        .highlight_style(app.config.theme.selected)
+        .highlight_spacing(HighlightSpacing::Always)
+        .highlight_symbol("> ");
+
+    frame.render_stateful_widget(list, chunk, &mut list_state);
+}
+
+fn render_search_body(app: &mut App, chunk: Rect, frame: &mut Frame) {
+    let chunks = Layout::default()
+        .constraints([Constraint::Length(3), Constraint::Min(1)])
+        .split(chunk);
+
+    let input = Paragraph::new(app.search_query.as_str()).block(
+        Block::default()
+            .title(r#" Search - prefix*, "exact phrase" "#)
+            .borders(Borders::ALL)
+            .border_style(app.config.theme.border)
+            .border_type(BorderType::Double),
+    );
+    frame.render_widget(input, chunks[0]);
+
+    let results_block = Block::default()
+        .title(format!(" {} result(s) ", app.search_results.len()))
+        .borders(Borders::ALL)
+        .border_style(app.config.theme.border)
+        .border_type(BorderType::Double);
+    let list_items: Vec<ListItem> = app
+        .search_results
+        .iter()
+        .map(|hit| {
+            let mut lines = vec![Line::from(highlight_snippet(
+                &hit.label,
+                app.config.theme.matched,
+            ))];
+            if hit.notes.contains(MATCH_START) {
+                let mut notes = vec![Span::styled("    notes: ", app.config.theme.muted)];
+                notes.extend(highlight_snippet(&hit.notes, app.config.theme.matched));
+                lines.push(Line::from(notes));
+            }
+            if app
+                .todos
+                .get(&hit.id)
+                .is_some_and(|todo| todo.is_archived())
+            {
+                lines[0]
+                    .spans
+                    .push(Span::styled("  (archived)", app.config.theme.muted));
+            }
+            ListItem::new(lines)
+        })
+        .collect();
+
+    let list = List::new(list_items)
+        .block(results_block)
+        .highlight_style(app.config.theme.selected)
+        .highlight_spacing(HighlightSpacing::Always)
+        .highlight_symbol("> ");
+    frame.render_stateful_widget(list, chunks[1], &mut app.selected_search_result);
+
+    let offset = app.selected_search_result.offset();
+    let heights: Vec<u16> = app.search_results[offset..]
+        .iter()
+        .map(|hit| {
+            if hit.notes.contains(MATCH_START) {
+                2
+            } else {
+                1
+            }
+        })
+        .collect();
+    let inner = chunks[1].inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+    record_rows(app, inner, offset, heights.into_iter());
+}
+
+// Footer hints such as " a - Add | q - Quit", using the first key bound to
+// each action so remapped keys show up
+fn key_hints(app: &App, context: KeyContext, hints: &[(Action, &str)]) -> String {
+    let hints: Vec<String> = hints
+        .iter()
+        .filter_map(|(action, label)| {
+            let keys = app.config.keymap.keys_for(context, *action);
+            let first = keys.first()?;
+            Some(format!("{} - {}", keys::format_sequence(first), label))
+        })
+        .collect();
+    format!(" {}", hints.join(" | "))
+}
+
 fn render_footer(app: &App, chunk: Rect, frame: &mut Frame) {
+    let theme = &app.config.theme;
     let horizontal_chunk = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(10), Constraint::Percentage(90)])
+        .constraints([
+            Constraint::Percentage(10),
+            Constraint::Min(1),
+            Constraint::Length(20),
+        ])
         .split(chunk);
 
     let current_mode = vec![match &app.current_screen {
-        CurrentScreen::Main => Span::styled("NORMAL", Style::default().white().bold()),
-        CurrentScreen::AddTodo => Span::styled("ADD", Style::default().light_cyan().bold()),
-        CurrentScreen::DeleteTodo => Span::styled("DELETE", Style::default().red().bold()),
-        CurrentScreen::EditTodo => Span::styled("EDIT", Style::default().blue().bold()),
-        CurrentScreen::Selection => Span::styled("SELECT", Style::default().green().bold()),
-        CurrentScreen::Search => Span::styled("SEARCH", Style::default().yellow().bold()),
+        CurrentScreen::Main => Span::styled("NORMAL", theme.mode_normal),
+        CurrentScreen::AddTodo => Span::styled("ADD", theme.mode_add),
+        CurrentScreen::DeleteTodo => Span::styled("DELETE", theme.mode_delete),
+        CurrentScreen::EditTodo => Span::styled("EDIT", theme.mode_edit),
+        CurrentScreen::Selection => Span::styled("SELECT", theme.mode_select),
+        CurrentScreen::Search => Span::styled("SEARCH", theme.mode_search),
+        CurrentScreen::Finder => Span::styled("FIND", theme.mode_search),
+        CurrentScreen::Help => Span::styled("HELP", theme.mode_details),
+        CurrentScreen::Archive => Span::styled("ARCHIVE", theme.mode_archive),
+        CurrentScreen::Trash => Span::styled("TRASH", theme.mode_archive),
+        CurrentScreen::ConfirmPurge => Span::styled("DELETE", theme.mode_delete),
+        CurrentScreen::Details => Span::styled("DETAILS", theme.mode_details),
+        CurrentScreen::History => Span::styled("HISTORY", theme.mode_details),
+        CurrentScreen::DueDate => Span::styled("DUE", theme.mode_add),
+        CurrentScreen::EditNotes => Span::styled("NOTES", theme.mode_edit),
+        CurrentScreen::Filter => Span::styled("FILTER", theme.mode_filter),
+        CurrentScreen::Command => Span::styled("COMMAND", theme.mode_filter),
+        CurrentScreen::ConfirmPaste => Span::styled("PASTE", theme.mode_add),
+        CurrentScreen::Views | CurrentScreen::ViewInput => Span::styled("VIEWS", theme.mode_views),
     }];
     let mode_footer = Paragraph::new(Line::from(current_mode))
         .block(
             Block::default()
                 .borders(Borders::ALL)
+                .border_style(app.config.theme.border)
                 .border_type(BorderType::Double),
         )
         .alignment(Alignment::Center);
 
     let current_key_hint = vec![match &app.current_screen {
-        CurrentScreen::Main => {
-            Span::raw(" <Space> - Toggle | a - Add | e - Edit | d - Delete | q - Quit")
-        }
+        CurrentScreen::Main => Span::raw(key_hints(
+            app,
+            KeyContext::Main,
+            &[
+                (Action::Help, "Help"),
+                (Action::Toggle, "Toggle"),
+                (Action::Details, "Details"),
+                (Action::History, "History"),
+                (Action::Add, "Add"),
+                (Action::Edit, "Edit"),
+                (Action::Delete, "Delete"),
+                (Action::Yank, "Yank"),
+                (Action::PasteAfter, "Paste"),
+                (Action::Notes, "Notes"),
+                (Action::CyclePriority, "Priority"),
+                (Action::DueDate, "Due"),
+                (Action::Search, "Search"),
+                (Action::Find, "Find"),
+                (Action::Command, "Command"),
+                (Action::Filter, "Filter"),
+                (Action::Views, "Views"),
+                (Action::CycleSort, "Sort"),
+                (Action::ToggleSortDirection, "Reverse sort"),
+                (Action::ToggleHideCompleted, "Hide done"),
+                (Action::ArchiveCompleted, "Archive done"),
+                (Action::Archive, "Archive"),
+                (Action::Trash, "Trash"),
+                (Action::Quit, "Quit"),
+            ],
+        )),
         CurrentScreen::AddTodo => Span::raw(" <Enter> - Add | <Esc> - Cancel"),
-        CurrentScreen::DeleteTodo => Span::raw(" y - Yes | n - No | <Esc> - Cancel"),
+        CurrentScreen::DeleteTodo | CurrentScreen::ConfirmPaste | CurrentScreen::ConfirmPurge => {
+            Span::raw(key_hints(
+                app,
+                KeyContext::Delete,
+                &[(Action::Confirm, "Yes"), (Action::Back, "No")],
+            ))
+        }
         CurrentScreen::EditTodo => Span::raw(" <Enter> - Yes | <Esc> - Cancel"),
         CurrentScreen::Selection => Span::raw(" <Esc> | q - Normal "),
-        CurrentScreen::Search => Span::raw(" <Esc> - Normal "),
+        CurrentScreen::Search | CurrentScreen::Finder => Span::raw(key_hints(
+            app,
+            KeyContext::Search,
+            &[
+                (Action::Jump, "Jump"),
+                (Action::Next, "Next"),
+                (Action::Prev, "Previous"),
+                (Action::Back, "Normal"),
+            ],
+        )),
+        CurrentScreen::Help => Span::raw(key_hints(
+            app,
+            KeyContext::Help,
+            &[
+                (Action::Next, "Down"),
+                (Action::Prev, "Up"),
+                (Action::PageDown, "Page down"),
+                (Action::PageUp, "Page up"),
+                (Action::Back, "Close"),
+            ],
+        )),
+        CurrentScreen::Archive => Span::raw(key_hints(
+            app,
+            KeyContext::Archive,
+            &[(Action::Restore, "Restore"), (Action::Back, "Normal")],
+        )),
+        CurrentScreen::Trash => Span::raw(key_hints(
+            app,
+            KeyContext::Trash,
+            &[
+                (Action::Restore, "Restore"),
+                (Action::Purge, "Delete for good"),
+                (Action::Back, "Normal"),
+            ],
+        )),
+        CurrentScreen::Details => Span::raw(key_hints(
+            app,
+            KeyContext::Details,
+            &[(Action::History, "History"), (Action::Back, "Normal")],
+        )),
+        CurrentScreen::History => Span::raw(key_hints(
+            app,
+            KeyContext::History,
+            &[
+                (Action::Next, "Down"),
+                (Action::Prev, "Up"),
+                (Action::Back, "Close"),
+            ],
+        )),
+        CurrentScreen::DueDate => Span::raw(" <Enter> - Set | <Esc> - Cancel"),
+        CurrentScreen::EditNotes => Span::raw(" <Enter> - Save | <Esc> - Cancel"),
+        CurrentScreen::Filter => Span::raw(" <Enter> - Apply | <Esc> - Cancel"),
+        CurrentScreen::Views => Span::raw(key_hints(
+            app,
+            KeyContext::Views,
+            &[
+                (Action::Apply, "Apply"),
+                (Action::NewView, "Save current"),
+                (Action::EditView, "Edit query"),
+                (Action::RenameView, "Rename"),
+                (Action::DeleteView, "Delete"),
+                (Action::Back, "Normal"),
+            ],
+        )),
+        CurrentScreen::ViewInput => Span::raw(" <Enter> - Save | <Esc> - Cancel"),
+        CurrentScreen::Command => Span::raw(format!(" :{}", app.command_input)),
     }];
-    let key_hints = Paragraph::new(Line::from(current_key_hint)).block(
-        Block::default()
-            .borders(Borders::ALL)
-            .border_type(BorderType::Double),
+    let current_key_hint = match &app.notice {
+        // Completion candidates are listed after the command being typed
+        Some(notice) if matches!(app.current_screen, CurrentScreen::Command) => {
+            let mut spans = current_key_hint;
+            spans.push(Span::styled(format!("   {}", notice), theme.notice));
+            spans
+        }
+        Some(notice) => vec![Span::styled(format!(" {}", notice), theme.notice)],
+        None => current_key_hint,
+    };
+    if matches!(app.current_screen, CurrentScreen::Command) {
+        frame.set_cursor(
+            horizontal_chunk[1].x + 3 + app.command_input.chars().count() as u16,
+            horizontal_chunk[1].y + 1,
+        );
+    }
+    // Keys typed so far of a count or sequence, shown like vim's showcmd
+    let pending = format!(
+        "{}{}",
+        app.count.map(|count| count.to_string()).unwrap_or_default(),
+        keys::format_sequence(&app.pending_keys)
     );
+    let mut key_hints_block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(app.config.theme.border)
+        .border_type(BorderType::Double);
+    if !pending.is_empty() {
+        key_hints_block = key_hints_block.title(
+            Title::from(Span::styled(format!(" {} ", pending), theme.notice))
+                .alignment(Alignment::Right)
+                .position(Position::Bottom),
+        );
+    }
+    let key_hints = Paragraph::new(Line::from(current_key_hint)).block(key_hints_block);
+
+    let sort_footer = Paragraph::new(format!("Sort: {}", app.sort.indicator()))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(app.config.theme.border)
+                .border_type(BorderType::Double),
+        )
+        .alignment(Alignment::Center);
 
     frame.render_widget(mode_footer, horizontal_chunk[0]);
     frame.render_widget(key_hints, horizontal_chunk[1]);
+    frame.render_widget(sort_footer, horizontal_chunk[2]);
+}
+
+fn render_view_bar(app: &mut App, chunk: Rect, frame: &mut Frame) {
+    let active_style = app.config.theme.active_view;
+    // Tabs start with the keys that switch to them, as remapped
+    let tab_key = |action| {
+        app.config
+            .keymap
+            .keys_for(KeyContext::Main, action)
+            .first()
+            .map(|keys| format!("{} ", keys::format_sequence(keys)))
+            .unwrap_or_default()
+    };
+    let mut spans = vec![Span::styled(
+        format!(" {}All ({}) ", tab_key(Action::ClearView), app.all_count),
+        if app.active_view.is_none() {
+            active_style
+        } else {
+            Style::default()
+        },
+    )];
+    for (index, view) in app.views.iter().enumerate() {
+        spans.push(Span::raw(" "));
+        spans.push(Span::styled(
+            format!(
+                " {}{} ({}) ",
+                tab_key(Action::ApplyView(index)),
+                view.name,
+                app.view_counts.get(index).copied().unwrap_or_default()
+            ),
+            if app.active_view == Some(view.id) {
+                active_style
+            } else {
+                Style::default()
+            },
+        ));
+    }
+    // The tabs are every other span, with single spaces in between
+    let mut x = chunk.x;
+    for (index, span) in spans.iter().enumerate() {
+        let width = span.width() as u16;
+        if index % 2 == 0 {
+            let tab = Rect::new(x, chunk.y, width, 1).intersection(chunk);
+            app.hit_areas
+                .view_tabs
+                .push((tab, (index / 2).checked_sub(1)));
+        }
+        x = x.saturating_add(width);
+    }
+    frame.render_widget(Paragraph::new(Line::from(spans)), chunk);
 }
 
 fn render_main(app: &mut App, frame: &mut Frame) {
     let chunks = Layout::default()
-        .constraints([Constraint::Min(1), Constraint::Length(3)])
+        .constraints([
+            Constraint::Min(1),
+            Constraint::Length(1),
+            Constraint::Length(3),
+        ])
         .split(frame.size());
 
-    render_body(app, chunks[0], frame);
-    render_footer(app, chunks[1], frame);
+    match app.current_screen {
+        CurrentScreen::Archive => render_archive_body(app, chunks[0], frame),
+        CurrentScreen::Trash | CurrentScreen::ConfirmPurge => {
+            render_trash_body(app, chunks[0], frame)
+        }
+        CurrentScreen::Search => render_search_body(app, chunks[0], frame),
+        _ => render_body(app, chunks[0], frame),
+    }
+    render_view_bar(app, chunks[1], frame);
+    render_footer(app, chunks[2], frame);
 }
 
-fn render_add_todo_popup(app: &App, frame: &mut Frame) {
+fn render_add_todo_popup(app: &mut App, frame: &mut Frame) {
     let popup_block = Block::default()
         .title("  Add new todo ")
-        .style(Style::default().black().on_light_cyan())
+        .style(app.config.theme.popup)
         .borders(Borders::ALL)
+        .border_style(app.config.theme.border)
         .border_type(BorderType::Double);
 
     let todo_label = Paragraph::new(app.todo_input.clone()).block(popup_block);
     let area = centered_rect(60, TODO_INPUT_HEIGHT, frame.size());
     frame.render_widget(Clear, area);
+    app.hit_areas.popup = Some(area);
     frame.render_widget(todo_label, area);
 }
 
 fn render_delete_todo_popup(app: &mut App, frame: &mut Frame) {
-    if let Some(selected_todo) = app.get_selected_todo() {
+    if let Some(selected) = app.selected_todo.selected() {
+        let labels: Vec<Line> = app
+            .visible_todos
+            .iter()
+            .skip(selected)
+            .take(app.delete_count)
+            .map(|id| Line::raw(app.todos[id].label.clone()))
+            .collect();
+        let title = if labels.len() > 1 {
+            format!(" Delete {} Todos - y/n ", labels.len())
+        } else {
+            String::from(" Delete Todo - y/n ")
+        };
+        let popup_block = Block::default()
+            .title(title)
+            .style(app.config.theme.popup_danger)
+            .borders(Borders::ALL)
+            .border_style(app.config.theme.border)
+            .border_type(BorderType::Double);
+
+        let todo_label = Paragraph::new(labels).block(popup_block);
+        let area = centered_rect(60, 20, frame.size());
+        frame.render_widget(Clear, area);
+        app.hit_areas.popup = Some(area);
+        frame.render_widget(todo_label, area);
+    }
+}
+
+fn render_confirm_purge_popup(app: &mut App, frame: &mut Frame) {
+    if let Some(todo) = app.get_selected_trashed_todo() {
         let popup_block = Block::default()
-            .title(" Delete Todo - y/n ")
-            .style(Style::default().black().on_light_red())
+            .title(" Delete for Good - y/n ")
+            .style(app.config.theme.popup_danger)
             .borders(Borders::ALL)
+            .border_style(app.config.theme.border)
             .border_type(BorderType::Double);
 
-        let todo_label = Paragraph::new(selected_todo.label.clone()).block(popup_block);
+        let todo_label = Paragraph::new(todo.label.clone()).block(popup_block);
         let area = centered_rect(60, 20, frame.size());
         frame.render_widget(Clear, area);
+        app.hit_areas.popup = Some(area);
         frame.render_widget(todo_label, area);
     }
 }
 
+fn render_confirm_paste_popup(app: &mut App, frame: &mut Frame) {
+    let popup_block = Block::default()
+        .title(format!(" Add {} Todos - y/n ", app.pending_paste.len()))
+        .style(app.config.theme.popup)
+        .borders(Borders::ALL)
+        .border_style(app.config.theme.border)
+        .border_type(BorderType::Double);
+
+    let labels: Vec<Line> = app
+        .pending_paste
+        .iter()
+        .map(|todo| Line::raw(todo.label.clone()))
+        .collect();
+    let labels = Paragraph::new(labels).block(popup_block);
+    let area = centered_rect(60, 40, frame.size());
+    frame.render_widget(Clear, area);
+    app.hit_areas.popup = Some(area);
+    frame.render_widget(labels, area);
+}
+
 fn render_edit_todo_popup(app: &mut App, frame: &mut Frame) {
     let popup_block = Block::default()
         .title(" Edit - <Enter> to Edit ")
-        .style(Style::default().black().on_light_blue())
+        .style(app.config.theme.popup_edit)
         .borders(Borders::ALL)
+        .border_style(app.config.theme.border)
         .border_type(BorderType::Double);
 
     let todo_label = Paragraph::new(app.todo_input.clone()).block(popup_block);
     let area = centered_rect(60, 20, frame.size());
     frame.render_widget(Clear, area);
+    app.hit_areas.popup = Some(area);
     frame.render_widget(todo_label, area);
 }
 
+fn render_details_popup(app: &mut App, frame: &mut Frame) {
+    if let Some(todo) = app.get_selected_todo() {
+        let popup_block = Block::default()
+            .title(" Details ")
+            .style(app.config.theme.popup)
+            .borders(Borders::ALL)
+            .border_style(app.config.theme.border)
+            .border_type(BorderType::Double);
+
+        let now = time::now();
+        let field = |name: &'static str, value: String| {
+            Line::from(vec![Span::raw(name).bold(), Span::raw(value)])
+        };
+        let (date_format, datetime_format) = (&app.config.date_format, &app.config.datetime_format);
+        let timestamp = |timestamp: i64| {
+            format!(
+                "{} ({})",
+                time::format(timestamp, datetime_format),
+                time::relative(timestamp, now)
+            )
+        };
+        let mut lines = vec![
+            field("Id:        ", format!("#{}", todo.id)),
+            field("Label:     ", todo.label.clone()),
+            field(
+                "Status:    ",
+                String::from(if todo.completed { "Done" } else { "Open" }),
+            ),
+            field("Created:   ", timestamp(todo.created_at)),
+            field("Updated:   ", timestamp(todo.updated_at)),
+            field("Priority:  ", String::from(todo.priority.label())),
+        ];
+        if let Some(due_at) = todo.due_at {
+            lines.push(field(
+                "Due:       ",
+                format!(
+                    "{} ({})",
+                    time::format(due_at, date_format),
+                    time::relative(due_at, now)
+                ),
+            ));
+        }
+        if let Some(completed_at) = todo.completed_at {
+            lines.push(field("Completed: ", timestamp(completed_at)));
+        }
+        if !todo.notes.is_empty() {
+            lines.push(Line::raw(""));
+            lines.extend(todo.notes.lines().map(|line| Line::raw(line.to_string())));
+        }
+
+        let details = Paragraph::new(lines).block(popup_block);
+        let area = centered_rect(60, 30, frame.size());
+        frame.render_widget(Clear, area);
+        app.hit_areas.popup = Some(area);
+        frame.render_widget(details, area);
+    }
+}
+
+fn render_history_popup(app: &mut App, frame: &mut Frame) {
+    let now = time::now();
+    let lines: Vec<Line> = if app.history.is_empty() {
+        vec![Line::raw("No changes recorded")]
+    } else {
+        app.history
+            .iter()
+            .map(|entry| {
+                Line::from(vec![
+                    Span::raw(format!(
+                        "{} ({})  ",
+                        time::format(entry.changed_at, &app.config.datetime_format),
+                        time::relative(entry.changed_at, now)
+                    ))
+                    .bold(),
+                    Span::raw(entry.describe(&app.config.date_format)),
+                ])
+            })
+            .collect()
+    };
+    let area = centered_rect(70, 60, frame.size());
+    // Long changes wrap, so count the rows they take up
+    let width = area.width.saturating_sub(2).max(1) as usize;
+    let rows: usize = lines
+        .iter()
+        .map(|line| line.width().div_ceil(width).max(1))
+        .sum();
+    let max_scroll = rows.saturating_sub(area.height.saturating_sub(2) as usize);
+    app.history_scroll = app.history_scroll.min(max_scroll);
+
+    let title = match app.history.first() {
+        Some(entry) => format!(" History of #{} ", entry.todo_id),
+        None => String::from(" History "),
+    };
+    let popup_block = Block::default()
+        .title(title)
+        .style(app.config.theme.popup)
+        .borders(Borders::ALL)
+        .border_style(app.config.theme.border)
+        .border_type(BorderType::Double);
+    let history = Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .block(popup_block)
+        .scroll((app.history_scroll as u16, 0));
+    frame.render_widget(Clear, area);
+    frame.render_widget(history, area);
+    app.hit_areas.popup = Some(area);
+}
+
+fn render_due_date_popup(app: &mut App, frame: &mut Frame) {
+    let popup_block = Block::default()
+        .title(" Due date - today, tomorrow, 3d, 2w or YYYY-MM-DD ")
+        .style(app.config.theme.popup)
+        .borders(Borders::ALL)
+        .border_style(app.config.theme.border)
+        .border_type(BorderType::Double);
+
+    let due_date = Paragraph::new(app.todo_input.as_str()).block(popup_block);
+    let area = centered_rect(60, 20, frame.size());
+    frame.render_widget(Clear, area);
+    app.hit_areas.popup = Some(area);
+    frame.render_widget(due_date, area);
+}
+
+fn render_filter_popup(app: &mut App, frame: &mut Frame) {
+    let popup_block = Block::default()
+        .title(r#" Filter - e.g. status:open tag:work due<1w prio>=high "text" "#)
+        .style(app.config.theme.popup_filter)
+        .borders(Borders::ALL)
+        .border_style(app.config.theme.border)
+        .border_type(BorderType::Double);
+
+    let filter = Paragraph::new(app.todo_input.as_str()).block(popup_block);
+    let area = centered_rect(60, 20, frame.size());
+    frame.render_widget(Clear, area);
+    app.hit_areas.popup = Some(area);
+    frame.render_widget(filter, area);
+}
+
+fn render_views_popup(app: &mut App, frame: &mut Frame) {
+    let popup_block = Block::default()
+        .title(" Views ")
+        .style(app.config.theme.popup_views)
+        .borders(Borders::ALL)
+        .border_style(app.config.theme.border)
+        .border_type(BorderType::Double);
+
+    let list_items: Vec<ListItem> = app
+        .views
+        .iter()
+        .enumerate()
+        .map(|(index, view)| {
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{} ", view.name)).bold(),
+                Span::raw(format!(
+                    "({})  {}  [{}]",
+                    app.view_counts.get(index).copied().unwrap_or_default(),
+                    view.query,
+                    view.sort.indicator()
+                )),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(list_items)
+        .block(popup_block)
+        .highlight_style(app.config.theme.selected)
+        .highlight_spacing(HighlightSpacing::Always)
+        .highlight_symbol("> ");
+    let area = centered_rect(60, 40, frame.size());
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, &mut app.selected_view);
+
+    app.hit_areas.popup = Some(area);
+    app.hit_areas.rows.clear();
+    let offset = app.selected_view.offset();
+    let inner = area.inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+    record_rows(app, inner, offset, (offset..app.views.len()).map(|_| 1));
+}
+
+fn render_view_input_popup(app: &mut App, frame: &mut Frame) {
+    let title = match app.view_input_mode {
+        ViewInputMode::New => " Save current filter and sort as ",
+        ViewInputMode::EditQuery => " Edit view query - uses the current sort ",
+        ViewInputMode::Rename => " Rename view ",
+    };
+    let popup_block = Block::default()
+        .title(title)
+        .style(app.config.theme.popup_views)
+        .borders(Borders::ALL)
+        .border_style(app.config.theme.border)
+        .border_type(BorderType::Double);
+
+    let input = Paragraph::new(app.todo_input.as_str()).block(popup_block);
+    let area = centered_rect(60, 20, frame.size());
+    frame.render_widget(Clear, area);
+    app.hit_areas.popup = Some(area);
+    frame.render_widget(input, area);
+}
+
+// Splits `label` into spans with the chars at `positions` highlighted
+fn highlight_positions<'a>(
+    label: &'a str,
+    positions: &[usize],
+    matched_style: Style,
+) -> Vec<Span<'a>> {
+    let mut spans = vec![];
+    let mut positions = positions.iter().peekable();
+    let mut start = 0;
+    let mut matched = false;
+    for (index, (byte, _)) in label.char_indices().enumerate() {
+        let is_match = positions.next_if_eq(&&index).is_some();
+        if is_match != matched {
+            if byte > start {
+                spans.push(Span::styled(
+                    &label[start..byte],
+                    if matched {
+                        matched_style
+                    } else {
+                        Style::default()
+                    },
+                ));
+            }
+            start = byte;
+            matched = is_match;
+        }
+    }
+    if start < label.len() {
+        spans.push(Span::styled(
+            &label[start..],
+            if matched {
+                matched_style
+            } else {
+                Style::default()
+            },
+        ));
+    }
+    spans
+}
+
+fn render_finder_popup(app: &mut App, frame: &mut Frame) {
+    let area = centered_rect(70, 60, frame.size());
+    frame.render_widget(Clear, area);
+    let chunks = Layout::default()
+        .constraints([Constraint::Length(3), Constraint::Min(1)])
+        .split(area);
+
+    let input = Paragraph::new(format!("> {}", app.finder_query)).block(
+        Block::default()
+            .title(" Find todo ")
+            .borders(Borders::ALL)
+            .border_style(app.config.theme.border)
+            .border_type(BorderType::Double),
+    );
+    frame.render_widget(input, chunks[0]);
+
+    let results_block = Block::default()
+        .title(format!(" {} match(es) ", app.finder_results.len()))
+        .borders(Borders::ALL)
+        .border_style(app.config.theme.border)
+        .border_type(BorderType::Double);
+    let list_items: Vec<ListItem> = app
+        .finder_results
+        .iter()
+        .filter_map(|result| {
+            let todo = app.todos.get(&result.id)?;
+            let mut spans =
+                highlight_positions(&todo.label, &result.positions, app.config.theme.matched);
+            if todo.is_archived() {
+                spans.push(Span::styled("  (archived)", app.config.theme.muted));
+            } else if todo.completed {
+                spans.push(Span::styled("  (done)", app.config.theme.muted));
+            }
+            Some(ListItem::new(Line::from(spans)))
+        })
+        .collect();
+
+    let list = List::new(list_items)
+        .block(results_block)
+        .highlight_style(app.config.theme.selected)
+        .highlight_spacing(HighlightSpacing::Always)
+        .highlight_symbol("> ");
+    frame.render_stateful_widget(list, chunks[1], &mut app.selected_finder_result);
+
+    app.hit_areas.popup = Some(area);
+    app.hit_areas.rows.clear();
+    let offset = app.selected_finder_result.offset();
+    let inner = chunks[1].inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+    let count = app.finder_results.len().saturating_sub(offset);
+    record_rows(app, inner, offset, (0..count).map(|_| 1));
+}
+
+// Width of the key column in the help overlay
+const HELP_KEYS_WIDTH: usize = 22;
+
+// Every key binding, grouped by screen, as the keymap currently has them
+fn help_lines(app: &App) -> Vec<Line<'static>> {
+    let keymap = &app.config.keymap;
+    let mut lines = vec![];
+    for context in KeyContext::ALL {
+        if !lines.is_empty() {
+            lines.push(Line::raw(""));
+        }
+        lines.push(Line::from(Span::raw(context.title()).bold().underlined()));
+        for binding in context.bindings() {
+            let keys: Vec<String> = keymap
+                .keys_for(context, binding.action)
+                .into_iter()
+                .map(keys::format_sequence)
+                .collect();
+            let keys = if keys.is_empty() {
+                Span::styled(
+                    format!("  {:<HELP_KEYS_WIDTH$}", "(unbound)"),
+                    app.config.theme.muted,
+                )
+            } else {
+                Span::raw(format!("  {:<HELP_KEYS_WIDTH$}", keys.join(", "))).bold()
+            };
+            lines.push(Line::from(vec![keys, Span::raw(binding.description)]));
+        }
+    }
+
+    lines.push(Line::raw(""));
+    lines.push(Line::raw(
+        "Type a count before a key to repeat it, like 5j, 3dd or 10G.",
+    ));
+    lines.push(Line::raw(
+        "Ctrl-Z suspends to the shell on any screen, fg comes back.",
+    ));
+
+    lines.push(Line::raw(""));
+    lines.push(Line::from(Span::raw("Text prompts").bold().underlined()));
+    for (keys, description) in [
+        ("<Enter>", "Confirm"),
+        ("<Esc>", "Cancel"),
+        ("<Backspace>", "Delete the last character"),
+    ] {
+        lines.push(Line::from(vec![
+            Span::raw(format!("  {:<HELP_KEYS_WIDTH$}", keys)).bold(),
+            Span::raw(description),
+        ]));
+    }
+    lines
+}
+
+fn render_help_popup(app: &mut App, frame: &mut Frame) {
+    let lines = help_lines(app);
+    let area = centered_rect(70, 80, frame.size());
+    let max_scroll = lines
+        .len()
+        .saturating_sub(area.height.saturating_sub(2) as usize);
+    app.help_scroll = app.help_scroll.min(max_scroll);
+
+    let popup_block = Block::default()
+        .title(format!(
+            " Help {}/{} ",
+            app.help_scroll.min(lines.len()) + 1,
+            lines.len()
+        ))
+        .style(app.config.theme.popup)
+        .borders(Borders::ALL)
+        .border_style(app.config.theme.border)
+        .border_type(BorderType::Double);
+    let help = Paragraph::new(lines)
+        .block(popup_block)
+        .scroll((app.help_scroll as u16, 0));
+    frame.render_widget(Clear, area);
+    frame.render_widget(help, area);
+    app.hit_areas.popup = Some(area);
+}
+
+fn render_notes_popup(app: &mut App, frame: &mut Frame) {
+    let popup_block = Block::default()
+        .title(" Notes - <Enter> to Save ")
+        .style(app.config.theme.popup_edit)
+        .borders(Borders::ALL)
+        .border_style(app.config.theme.border)
+        .border_type(BorderType::Double);
+
+    let notes = Paragraph::new(app.todo_input.as_str())
+        .wrap(Wrap { trim: false })
+        .block(popup_block);
+    let area = centered_rect(60, 20, frame.size());
+    frame.render_widget(Clear, area);
+    app.hit_areas.popup = Some(area);
+    frame.render_widget(notes, area);
+}
+
 pub fn render(app: &mut App, frame: &mut Frame) {
+    app.hit_areas = HitAreas::default();
     render_main(app, frame);
 
     match &app.current_screen {
@@ -181,5 +1138,19 @@ pub fn render(app: &mut App, frame: &mut Frame) {
         // TODO: Implement selection mode
         CurrentScreen::Selection => {}
         CurrentScreen::Search => {}
+        CurrentScreen::Finder => render_finder_popup(app, frame),
+        CurrentScreen::Help => render_help_popup(app, frame),
+        CurrentScreen::Archive => {}
+        CurrentScreen::Details => render_details_popup(app, frame),
+        CurrentScreen::History => render_history_popup(app, frame),
+        CurrentScreen::DueDate => render_due_date_popup(app, frame),
+        CurrentScreen::EditNotes => render_notes_popup(app, frame),
+        CurrentScreen::Filter => render_filter_popup(app, frame),
+        CurrentScreen::Views => render_views_popup(app, frame),
+        CurrentScreen::ViewInput => render_view_input_popup(app, frame),
+        CurrentScreen::Command => {}
+        CurrentScreen::ConfirmPaste => render_confirm_paste_popup(app, frame),
+        CurrentScreen::Trash => {}
+        CurrentScreen::ConfirmPurge => render_confirm_purge_popup(app, frame),
     }
 }
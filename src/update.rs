@@ -1,20 +1,35 @@
-use crate::app::{App, CurrentScreen};
-use crossterm::event::{KeyCode, KeyEvent};
+use crate::{
+    app::{App, Change, CurrentScreen, ExternalEdit, ViewInputMode, MAX_COUNT},
+    keys::{Action, KeyContext, KeyPress, Lookup},
+    time,
+};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::{layout::Rect, widgets::ListState};
+use std::time::{Duration, Instant};
 
-fn handle_main_screen_events(app: &mut App, key: &KeyEvent) {
-    match key.code {
-        KeyCode::Char('q') | KeyCode::Esc => {
+// Opens the delete confirmation for `count` todos, or deletes them right away
+fn delete_todos(app: &mut App, count: usize) {
+    if app.config.confirm_delete {
+        app.delete_count = count;
+        app.current_screen = CurrentScreen::DeleteTodo;
+    } else {
+        app.delete_selected_todos(count);
+        app.last_change = Some((Change::Delete, count));
+    }
+}
+
+// `count` is the number typed before the key, if any
+fn handle_main_action(app: &mut App, action: Action, count: Option<usize>) {
+    let times = count.unwrap_or(1);
+    match action {
+        Action::Quit => {
             app.should_quit = true;
         }
-        KeyCode::Char('a') => {
+        Action::Add => {
             app.current_screen = CurrentScreen::AddTodo;
         }
-        KeyCode::Char('d') => {
-            if app.selected_todo.selected().is_some() {
-                app.current_screen = CurrentScreen::DeleteTodo;
-            }
-        }
-        KeyCode::Char('e') => {
+        Action::Delete if app.selected_todo.selected().is_some() => delete_todos(app, times),
+        Action::Edit => {
             let todo = app.get_selected_todo();
             if todo.is_none() {
                 return;
@@ -23,31 +38,179 @@ fn handle_main_screen_events(app: &mut App, key: &KeyEvent) {
             app.todo_input = String::from(&todo.label);
             app.current_screen = CurrentScreen::EditTodo;
         }
-        KeyCode::Char('g') => {
+        // With a count, gg and G go to that line like in vim
+        Action::GoToTop | Action::GoToBottom if count.is_some() => {
+            app.select_todo_at(times.min(app.visible_todos.len()).saturating_sub(1));
+        }
+        Action::GoToTop => {
             app.go_to_top();
         }
-        KeyCode::Char('G') => {
+        Action::GoToBottom => {
             app.go_to_bottom();
         }
-        KeyCode::Char('v') => {
+        Action::HalfPageDown | Action::HalfPageUp => {
+            let half = app
+                .rows_on_screen()
+                .map_or(1, |(first, last)| (last - first).div_ceil(2).max(1));
+            let offset = (half * times) as isize;
+            app.move_selection(if action == Action::HalfPageDown {
+                offset
+            } else {
+                -offset
+            });
+        }
+        Action::ScreenTop | Action::ScreenMiddle | Action::ScreenBottom => {
+            if let Some((first, last)) = app.rows_on_screen() {
+                app.select_todo_at(match action {
+                    Action::ScreenTop => first,
+                    Action::ScreenMiddle => first + (last - first) / 2,
+                    _ => last,
+                });
+            }
+        }
+        Action::Select => {
             if app.selected_todo.selected().is_none() {
                 app.selected_todo.select(Some(0));
             }
             app.current_screen = CurrentScreen::Selection;
         }
-        KeyCode::Char('j') => {
+        // A single step wraps around, a counted one stops at the end
+        Action::Next if count.is_some() => app.move_selection(times as isize),
+        Action::Prev if count.is_some() => app.move_selection(-(times as isize)),
+        Action::Next => {
             app.select_next_todo();
         }
-        KeyCode::Char('k') => {
+        Action::Prev => {
             app.select_prev_todo();
         }
-        KeyCode::Char('/') => {
+        Action::Search => {
             app.current_screen = CurrentScreen::Search;
             app.search_query.clear();
+            app.update_search_results();
+        }
+        Action::Find => {
+            app.current_screen = CurrentScreen::Finder;
+            app.finder_query.clear();
+            app.update_finder_results();
+        }
+        Action::Command => app.open_command_line(),
+        Action::Toggle => {
+            app.toggle_selected_todos(times);
+            app.last_change = Some((Change::Toggle, times));
+        }
+        Action::Repeat => {
+            if let Some((change, last_count)) = app.last_change.clone() {
+                let times = count.unwrap_or(last_count);
+                if change == Change::Delete {
+                    delete_todos(app, times);
+                } else {
+                    app.repeat_change(&change, times);
+                    app.last_change = Some((change, times));
+                }
+            }
+        }
+        Action::Help => {
+            app.help_scroll = 0;
+            app.current_screen = CurrentScreen::Help;
+        }
+        Action::Details if app.selected_todo.selected().is_some() => {
+            app.current_screen = CurrentScreen::Details;
+        }
+        Action::History => app.open_history(),
+        Action::EditExternal => {
+            if let Some(id) = app.get_selected_todo_id() {
+                app.external_edit = Some(ExternalEdit::Todo(id));
+            }
+        }
+        Action::EditList => app.external_edit = Some(ExternalEdit::List),
+        Action::Notes => {
+            if let Some(todo) = app.get_selected_todo() {
+                app.todo_input = todo.notes.clone();
+                app.current_screen = CurrentScreen::EditNotes;
+            }
+        }
+        Action::Yank | Action::YankMarkdown => {
+            app.yank_selected_todos(times, action == Action::YankMarkdown);
+        }
+        Action::PasteAfter | Action::PasteBefore => {
+            let before = action == Action::PasteBefore;
+            app.paste(times, before);
+            app.last_change = Some((Change::Paste(before), times));
+        }
+        Action::CyclePriority => {
+            app.cycle_selected_priority(times);
+            app.last_change = Some((Change::CyclePriority, times));
+        }
+        Action::DueDate => {
+            if let Some(todo) = app.get_selected_todo() {
+                app.todo_input = todo.due_at.map(time::format_date).unwrap_or_default();
+                app.current_screen = CurrentScreen::DueDate;
+            }
+        }
+        Action::Filter => {
+            app.todo_input = app.filter_input.clone();
+            app.current_screen = CurrentScreen::Filter;
+        }
+        Action::Views => {
+            if app.selected_view.selected().is_none() && !app.views.is_empty() {
+                app.selected_view.select(Some(0));
+            }
+            app.current_screen = CurrentScreen::Views;
+        }
+        Action::ClearView => {
+            app.clear_view();
+        }
+        Action::ApplyView(index) => {
+            app.apply_view(index);
+        }
+        Action::CycleSort => {
+            app.cycle_sort_mode();
+        }
+        Action::ToggleSortDirection => {
+            app.toggle_sort_direction();
         }
-        KeyCode::Char(' ') => {
-            app.toggle_selected_todo();
+        Action::ToggleHideCompleted => {
+            app.toggle_hide_completed();
         }
+        Action::ArchiveCompleted => {
+            app.archive_completed_todos();
+        }
+        Action::Archive => {
+            if app.selected_archived_todo.selected().is_none() && !app.archived_todos.is_empty() {
+                app.selected_archived_todo.select(Some(0));
+            }
+            app.current_screen = CurrentScreen::Archive;
+        }
+        Action::Trash => app.open_trash(),
+        _ => {}
+    }
+}
+
+// Lines scrolled by a page in the help overlay
+const HELP_PAGE: usize = 10;
+
+fn handle_history_action(app: &mut App, action: Action) {
+    match action {
+        Action::Back => app.current_screen = CurrentScreen::Main,
+        Action::Next => app.history_scroll = app.history_scroll.saturating_add(1),
+        Action::Prev => app.history_scroll = app.history_scroll.saturating_sub(1),
+        Action::PageDown => app.history_scroll = app.history_scroll.saturating_add(HELP_PAGE),
+        Action::PageUp => app.history_scroll = app.history_scroll.saturating_sub(HELP_PAGE),
+        Action::GoToTop => app.history_scroll = 0,
+        Action::GoToBottom => app.history_scroll = usize::MAX,
+        _ => {}
+    }
+}
+
+fn handle_help_action(app: &mut App, action: Action) {
+    match action {
+        Action::Back => app.current_screen = CurrentScreen::Main,
+        Action::Next => app.help_scroll = app.help_scroll.saturating_add(1),
+        Action::Prev => app.help_scroll = app.help_scroll.saturating_sub(1),
+        Action::PageDown => app.help_scroll = app.help_scroll.saturating_add(HELP_PAGE),
+        Action::PageUp => app.help_scroll = app.help_scroll.saturating_sub(HELP_PAGE),
+        Action::GoToTop => app.help_scroll = 0,
+        Action::GoToBottom => app.help_scroll = usize::MAX,
         _ => {}
     }
 }
@@ -62,9 +225,10 @@ fn handle_add_screen_events(app: &mut App, key: &KeyEvent) {
             let _ = app.todo_input.pop();
         }
         KeyCode::Enter => {
-            if app.todo_input.len() == 0 {
+            if app.todo_input.is_empty() {
                 return;
             }
+            app.last_change = Some((Change::Add(app.todo_input.clone()), 1));
             app.add_todo();
             app.current_screen = CurrentScreen::Main;
         }
@@ -75,13 +239,26 @@ fn handle_add_screen_events(app: &mut App, key: &KeyEvent) {
     }
 }
 
-fn handle_delete_screen_events(app: &mut App, key: &KeyEvent) {
-    match key.code {
-        KeyCode::Esc | KeyCode::Char('n') | KeyCode::Char('N') => {
+fn handle_delete_action(app: &mut App, action: Action) {
+    match action {
+        Action::Back => {
             app.current_screen = CurrentScreen::Main;
         }
-        KeyCode::Char('y') | KeyCode::Char('Y') => {
-            app.delete_selected_todo();
+        Action::Confirm => {
+            app.delete_selected_todos(app.delete_count);
+            app.last_change = Some((Change::Delete, app.delete_count));
+            app.current_screen = CurrentScreen::Main;
+        }
+        _ => {}
+    }
+}
+
+fn handle_confirm_paste_action(app: &mut App, action: Action) {
+    match action {
+        Action::Back => cancel_popup(app),
+        Action::Confirm => {
+            app.add_pending_paste();
+            app.todo_input.clear();
             app.current_screen = CurrentScreen::Main;
         }
         _ => {}
@@ -95,15 +272,17 @@ fn handle_edit_screen_events(app: &mut App, key: &KeyEvent) {
             app.todo_input.clear();
         }
         KeyCode::Char(c) => {
-            let _ = app.todo_input.push(c);
+            app.todo_input.push(c);
         }
         KeyCode::Backspace => {
             let _ = app.todo_input.pop();
         }
         KeyCode::Enter => {
-            if app.todo_input.len() == 0 {
-                app.delete_selected_todo();
+            if app.todo_input.is_empty() {
+                app.delete_selected_todos(1);
+                app.last_change = Some((Change::Delete, 1));
             } else {
+                app.last_change = Some((Change::Edit(app.todo_input.clone()), 1));
                 app.update_selected_todo();
             }
             app.current_screen = CurrentScreen::Main;
@@ -120,21 +299,589 @@ fn handle_selection_screen_events(app: &mut App, key: &KeyEvent) {
     }
 }
 
+fn handle_search_action(app: &mut App, action: Action) {
+    match action {
+        Action::Back => app.current_screen = CurrentScreen::Main,
+        Action::Jump => app.jump_to_search_result(),
+        Action::Next => app.select_next_search_result(),
+        Action::Prev => app.select_prev_search_result(),
+        _ => {}
+    }
+}
+
+// Typing on the search screen, for keys that aren't bound to an action
 fn handle_search_screen_events(app: &mut App, key: &KeyEvent) {
     match key.code {
-        KeyCode::Esc => app.current_screen = CurrentScreen::Main,
-        KeyCode::Char(c) => app.search_query.push(c),
+        KeyCode::Char(c) => {
+            app.search_query.push(c);
+            app.update_search_results();
+        }
+        KeyCode::Backspace => {
+            let _ = app.search_query.pop();
+            app.update_search_results();
+        }
+        _ => {}
+    }
+}
+
+fn handle_finder_action(app: &mut App, action: Action) {
+    match action {
+        Action::Back => app.current_screen = CurrentScreen::Main,
+        Action::Jump => app.jump_to_finder_result(),
+        Action::Next => app.select_next_finder_result(),
+        Action::Prev => app.select_prev_finder_result(),
+        _ => {}
+    }
+}
+
+fn handle_finder_screen_events(app: &mut App, key: &KeyEvent) {
+    match key.code {
+        KeyCode::Char(c) => {
+            app.finder_query.push(c);
+            app.update_finder_results();
+        }
+        KeyCode::Backspace => {
+            let _ = app.finder_query.pop();
+            app.update_finder_results();
+        }
+        _ => {}
+    }
+}
+
+fn handle_notes_screen_events(app: &mut App, key: &KeyEvent) {
+    match key.code {
+        KeyCode::Esc => {
+            app.current_screen = CurrentScreen::Main;
+            app.todo_input.clear();
+        }
+        KeyCode::Char(c) => app.todo_input.push(c),
+        KeyCode::Backspace => {
+            let _ = app.todo_input.pop();
+        }
+        KeyCode::Enter => {
+            app.update_selected_notes();
+            app.current_screen = CurrentScreen::Main;
+        }
+        _ => {}
+    }
+}
+
+fn handle_archive_action(app: &mut App, action: Action) {
+    match action {
+        Action::Back => app.current_screen = CurrentScreen::Main,
+        Action::Next => app.select_next_archived_todo(),
+        Action::Prev => app.select_prev_archived_todo(),
+        Action::Restore => app.restore_selected_archived_todo(),
+        _ => {}
+    }
+}
+
+fn handle_trash_action(app: &mut App, action: Action) {
+    match action {
+        Action::Back => app.current_screen = CurrentScreen::Main,
+        Action::Next => app.select_next_trashed_todo(),
+        Action::Prev => app.select_prev_trashed_todo(),
+        Action::Restore => app.restore_selected_trashed_todo(),
+        Action::Purge if app.get_selected_trashed_todo().is_some() => {
+            app.current_screen = CurrentScreen::ConfirmPurge;
+        }
+        _ => {}
+    }
+}
+
+fn handle_confirm_purge_action(app: &mut App, action: Action) {
+    match action {
+        Action::Back => app.current_screen = CurrentScreen::Trash,
+        Action::Confirm => {
+            app.delete_selected_trashed_todo();
+            app.current_screen = CurrentScreen::Trash;
+        }
+        _ => {}
+    }
+}
+
+fn handle_details_action(app: &mut App, action: Action) {
+    match action {
+        Action::History => app.open_history(),
+        Action::Back => app.current_screen = CurrentScreen::Main,
+        _ => {}
+    }
+}
+
+fn handle_due_date_screen_events(app: &mut App, key: &KeyEvent) {
+    match key.code {
+        KeyCode::Esc => {
+            app.current_screen = CurrentScreen::Main;
+            app.todo_input.clear();
+        }
+        KeyCode::Char(c) => app.todo_input.push(c),
+        KeyCode::Backspace => {
+            let _ = app.todo_input.pop();
+        }
+        KeyCode::Enter => {
+            let input = app.todo_input.clone();
+            if app.set_selected_due_date() {
+                app.last_change = Some((Change::DueDate(input), 1));
+                app.current_screen = CurrentScreen::Main;
+            }
+        }
+        _ => {}
+    }
+}
+
+fn handle_filter_screen_events(app: &mut App, key: &KeyEvent) {
+    match key.code {
+        KeyCode::Esc => {
+            app.current_screen = CurrentScreen::Main;
+            app.todo_input.clear();
+        }
+        KeyCode::Char(c) => app.todo_input.push(c),
+        KeyCode::Backspace => {
+            let _ = app.todo_input.pop();
+        }
+        KeyCode::Enter if app.apply_filter() => {
+            app.current_screen = CurrentScreen::Main;
+        }
+        _ => {}
+    }
+}
+
+fn handle_command_screen_events(app: &mut App, key: &KeyEvent) {
+    match key.code {
+        KeyCode::Esc => {
+            app.current_screen = CurrentScreen::Main;
+            app.command_input.clear();
+        }
+        KeyCode::Enter => app.run_command(),
+        KeyCode::Tab => {
+            app.complete_command();
+            return;
+        }
+        KeyCode::Up => app.browse_command_history(true),
+        KeyCode::Down => app.browse_command_history(false),
+        KeyCode::Char(c) => app.command_input.push(c),
+        // Backspace on an empty line leaves it, like in vim
+        KeyCode::Backspace if app.command_input.is_empty() => {
+            app.current_screen = CurrentScreen::Main;
+        }
+        KeyCode::Backspace => {
+            let _ = app.command_input.pop();
+        }
+        _ => {}
+    }
+    app.completion = None;
+}
+
+fn handle_views_action(app: &mut App, action: Action) {
+    match action {
+        Action::Back => app.current_screen = CurrentScreen::Main,
+        Action::Next => app.select_next_view(),
+        Action::Prev => app.select_prev_view(),
+        Action::Apply => {
+            if let Some(index) = app.selected_view.selected() {
+                app.apply_view(index);
+            }
+            app.current_screen = CurrentScreen::Main;
+        }
+        Action::NewView => {
+            app.view_input_mode = ViewInputMode::New;
+            app.todo_input.clear();
+            app.current_screen = CurrentScreen::ViewInput;
+        }
+        Action::EditView => {
+            if let Some(view) = app.selected_view.selected().and_then(|i| app.views.get(i)) {
+                app.todo_input = view.query.clone();
+                app.view_input_mode = ViewInputMode::EditQuery;
+                app.current_screen = CurrentScreen::ViewInput;
+            }
+        }
+        Action::RenameView => {
+            if let Some(view) = app.selected_view.selected().and_then(|i| app.views.get(i)) {
+                app.todo_input = view.name.clone();
+                app.view_input_mode = ViewInputMode::Rename;
+                app.current_screen = CurrentScreen::ViewInput;
+            }
+        }
+        Action::DeleteView => app.delete_selected_view(),
+        _ => {}
+    }
+}
+
+fn handle_view_input_screen_events(app: &mut App, key: &KeyEvent) {
+    match key.code {
+        KeyCode::Esc => {
+            app.current_screen = CurrentScreen::Views;
+            app.todo_input.clear();
+        }
+        KeyCode::Char(c) => app.todo_input.push(c),
+        KeyCode::Backspace => {
+            let _ = app.todo_input.pop();
+        }
+        KeyCode::Enter if app.submit_view_input() => {
+            app.current_screen = CurrentScreen::Views;
+        }
         _ => {}
     }
 }
 
+fn key_context(screen: &CurrentScreen) -> Option<KeyContext> {
+    match screen {
+        CurrentScreen::Main => Some(KeyContext::Main),
+        CurrentScreen::Archive => Some(KeyContext::Archive),
+        CurrentScreen::Views => Some(KeyContext::Views),
+        CurrentScreen::Trash => Some(KeyContext::Trash),
+        CurrentScreen::DeleteTodo | CurrentScreen::ConfirmPaste | CurrentScreen::ConfirmPurge => {
+            Some(KeyContext::Delete)
+        }
+        CurrentScreen::Details => Some(KeyContext::Details),
+        CurrentScreen::History => Some(KeyContext::History),
+        CurrentScreen::Search | CurrentScreen::Finder => Some(KeyContext::Search),
+        CurrentScreen::Help => Some(KeyContext::Help),
+        _ => None,
+    }
+}
+
+// Looks `key` up in the keymap for the current screen, keeping track of
+// multi-key sequences. Returns None when the key isn't bound.
+fn resolve_action(app: &mut App, context: KeyContext, key: &KeyEvent) -> Option<Action> {
+    app.pending_keys.push(KeyPress::from(key));
+    match app.config.keymap.lookup(context, &app.pending_keys) {
+        Lookup::Action(action) => {
+            app.pending_keys.clear();
+            Some(action)
+        }
+        Lookup::Pending => None,
+        Lookup::None => {
+            // A sequence that went nowhere is dropped, but the last key may
+            // still be a binding of its own
+            let retry = app.pending_keys.len() > 1;
+            app.pending_keys.clear();
+            if retry {
+                resolve_action(app, context, key)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+// Adds `key` to the count being typed if it is an unbound digit. A count
+// can't start with 0.
+fn push_count(app: &mut App, context: KeyContext, key: &KeyEvent) -> bool {
+    let digit = match key.code {
+        KeyCode::Char(c) if key.modifiers.is_empty() => c.to_digit(10),
+        _ => None,
+    };
+    let digit = match digit {
+        Some(0) if app.count.is_none() => return false,
+        Some(digit) => digit as usize,
+        None => return false,
+    };
+    if !app.pending_keys.is_empty()
+        || !matches!(
+            app.config.keymap.lookup(context, &[KeyPress::from(key)]),
+            Lookup::None
+        )
+    {
+        return false;
+    }
+    app.count = Some((app.count.unwrap_or(0) * 10 + digit).min(MAX_COUNT));
+    true
+}
+
 pub fn update(app: &mut App, key: KeyEvent) {
+    app.notice = None;
+    // Raw mode turns off the terminal's own Ctrl-Z handling, so it works the
+    // same on every screen
+    if key.code == KeyCode::Char('z') && key.modifiers == KeyModifiers::CONTROL {
+        app.should_suspend = true;
+        return;
+    }
+    if let Some(context) = key_context(&app.current_screen) {
+        if context == KeyContext::Main {
+            if push_count(app, context, &key) {
+                return;
+            }
+            // Esc drops a half typed count or sequence instead of quitting
+            if key.code == KeyCode::Esc && (app.count.is_some() || !app.pending_keys.is_empty()) {
+                app.count = None;
+                app.pending_keys.clear();
+                return;
+            }
+        }
+        let pending = !app.pending_keys.is_empty();
+        if let Some(action) = resolve_action(app, context, &key) {
+            match app.current_screen {
+                CurrentScreen::Main => {
+                    let count = app.count.take();
+                    handle_main_action(app, action, count)
+                }
+                CurrentScreen::Archive => handle_archive_action(app, action),
+                CurrentScreen::Views => handle_views_action(app, action),
+                CurrentScreen::DeleteTodo => handle_delete_action(app, action),
+                CurrentScreen::ConfirmPaste => handle_confirm_paste_action(app, action),
+                CurrentScreen::Trash => handle_trash_action(app, action),
+                CurrentScreen::ConfirmPurge => handle_confirm_purge_action(app, action),
+                CurrentScreen::Details => handle_details_action(app, action),
+                CurrentScreen::History => handle_history_action(app, action),
+                CurrentScreen::Search => handle_search_action(app, action),
+                CurrentScreen::Finder => handle_finder_action(app, action),
+                CurrentScreen::Help => handle_help_action(app, action),
+                _ => {}
+            }
+            return;
+        }
+        if pending || !app.pending_keys.is_empty() {
+            return;
+        }
+        app.count = None;
+    } else {
+        app.pending_keys.clear();
+    }
+
     match app.current_screen {
-        CurrentScreen::Main => handle_main_screen_events(app, &key),
         CurrentScreen::AddTodo => handle_add_screen_events(app, &key),
-        CurrentScreen::DeleteTodo => handle_delete_screen_events(app, &key),
         CurrentScreen::EditTodo => handle_edit_screen_events(app, &key),
         CurrentScreen::Selection => handle_selection_screen_events(app, &key),
         CurrentScreen::Search => handle_search_screen_events(app, &key),
+        CurrentScreen::Finder => handle_finder_screen_events(app, &key),
+        CurrentScreen::DueDate => handle_due_date_screen_events(app, &key),
+        CurrentScreen::EditNotes => handle_notes_screen_events(app, &key),
+        CurrentScreen::Filter => handle_filter_screen_events(app, &key),
+        CurrentScreen::ViewInput => handle_view_input_screen_events(app, &key),
+        CurrentScreen::Command => handle_command_screen_events(app, &key),
+        _ => {}
+    }
+}
+
+// Handles text pasted into the terminal. Prompts get it inserted at the
+// cursor, while several lines pasted into the list or the add popup become
+// todos once confirmed.
+pub fn update_paste(app: &mut App, text: &str) {
+    app.notice = None;
+    app.count = None;
+    app.pending_keys.clear();
+    // Terminals tend to send line breaks as carriage returns
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let multi_line = text.trim().contains('\n');
+    // Labels and queries are a single line
+    let line = text.trim_matches('\n').replace('\n', " ");
+    match app.current_screen {
+        CurrentScreen::Main if multi_line => app.confirm_paste(&text, true),
+        // A single todo doesn't need confirming
+        CurrentScreen::Main => {
+            app.confirm_paste(&text, true);
+            if !app.pending_paste.is_empty() {
+                app.add_pending_paste();
+                app.current_screen = CurrentScreen::Main;
+            }
+        }
+        CurrentScreen::AddTodo if multi_line => app.confirm_paste(&text, false),
+        CurrentScreen::AddTodo
+        | CurrentScreen::EditTodo
+        | CurrentScreen::DueDate
+        | CurrentScreen::Filter
+        | CurrentScreen::ViewInput => app.todo_input.push_str(&line),
+        CurrentScreen::EditNotes => app.todo_input.push_str(&text),
+        CurrentScreen::Search => {
+            app.search_query.push_str(&line);
+            app.update_search_results();
+        }
+        CurrentScreen::Finder => {
+            app.finder_query.push_str(&line);
+            app.update_finder_results();
+        }
+        CurrentScreen::Command => {
+            app.command_input.push_str(&line);
+            app.completion = None;
+        }
+        _ => {}
+    }
+}
+
+// Two clicks on the same item within this time count as a double click
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+fn contains(area: Rect, column: u16, row: u16) -> bool {
+    column >= area.x && column < area.right() && row >= area.y && row < area.bottom()
+}
+
+// Leaves a popup the way <Esc> does
+fn cancel_popup(app: &mut App) {
+    match app.current_screen {
+        CurrentScreen::ViewInput => {
+            app.todo_input.clear();
+            app.current_screen = CurrentScreen::Views;
+        }
+        CurrentScreen::AddTodo
+        | CurrentScreen::EditTodo
+        | CurrentScreen::DueDate
+        | CurrentScreen::EditNotes
+        | CurrentScreen::Filter => {
+            app.todo_input.clear();
+            app.current_screen = CurrentScreen::Main;
+        }
+        CurrentScreen::Command => {
+            app.command_input.clear();
+            app.current_screen = CurrentScreen::Main;
+        }
+        CurrentScreen::ConfirmPaste => {
+            app.pending_paste.clear();
+            app.todo_input.clear();
+            app.current_screen = CurrentScreen::Main;
+        }
+        CurrentScreen::ConfirmPurge => app.current_screen = CurrentScreen::Trash,
+        _ => app.current_screen = CurrentScreen::Main,
+    }
+}
+
+// Handles a left click on item `index` of the clickable list. Returns whether
+// it was the second click of a double click.
+fn click_item(app: &mut App, index: usize) -> bool {
+    let now = Instant::now();
+    let double = app
+        .last_click
+        .is_some_and(|(at, last)| last == index && now.duration_since(at) < DOUBLE_CLICK);
+    app.last_click = if double { None } else { Some((now, index)) };
+    double
+}
+
+fn handle_left_click(app: &mut App, column: u16, row: u16) {
+    if let Some(popup) = app.hit_areas.popup {
+        if !contains(popup, column, row) {
+            cancel_popup(app);
+            return;
+        }
+    }
+    let clicked = app
+        .hit_areas
+        .rows
+        .iter()
+        .find(|(area, _)| contains(*area, column, row))
+        .map(|(_, index)| *index);
+
+    match app.current_screen {
+        CurrentScreen::Main => {
+            if let Some((_, view)) = app
+                .hit_areas
+                .view_tabs
+                .iter()
+                .find(|(area, _)| contains(*area, column, row))
+            {
+                match *view {
+                    Some(index) => app.apply_view(index),
+                    None => app.clear_view(),
+                }
+                return;
+            }
+            let index = match clicked {
+                Some(index) => index,
+                None => return,
+            };
+            let on_checkbox = app
+                .hit_areas
+                .checkbox
+                .is_some_and(|(x, width)| column >= x && column < x + width);
+            app.select_todo_at(index);
+            if on_checkbox {
+                app.last_click = None;
+                app.toggle_selected_todos(1);
+                app.last_change = Some((Change::Toggle, 1));
+            } else if click_item(app, index) {
+                handle_main_action(app, Action::Edit, None);
+            }
+        }
+        CurrentScreen::Archive => {
+            if let Some(index) = clicked {
+                app.selected_archived_todo.select(Some(index));
+            }
+        }
+        CurrentScreen::Trash => {
+            if let Some(index) = clicked {
+                app.selected_trashed_todo.select(Some(index));
+            }
+        }
+        CurrentScreen::Search => {
+            if let Some(index) = clicked {
+                app.selected_search_result.select(Some(index));
+                if click_item(app, index) {
+                    app.jump_to_search_result();
+                }
+            }
+        }
+        CurrentScreen::Finder => {
+            if let Some(index) = clicked {
+                app.selected_finder_result.select(Some(index));
+                if click_item(app, index) {
+                    app.jump_to_finder_result();
+                }
+            }
+        }
+        CurrentScreen::Views => {
+            if let Some(index) = clicked {
+                app.selected_view.select(Some(index));
+                if click_item(app, index) {
+                    handle_views_action(app, Action::Apply);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// Moves one row through a list of `len` items, stopping at either end
+fn scroll_list(state: &mut ListState, len: usize, down: bool) {
+    if len == 0 {
+        return;
+    }
+    state.select(Some(match (state.selected(), down) {
+        (None, _) => 0,
+        (Some(i), true) => (i + 1).min(len - 1),
+        (Some(i), false) => i.saturating_sub(1),
+    }));
+}
+
+// The wheel stops at the ends of a list, where a flick would otherwise wrap
+// around to the other end like the keys do
+fn handle_scroll(app: &mut App, down: bool) {
+    let action = if down { Action::Next } else { Action::Prev };
+    match app.current_screen {
+        CurrentScreen::Main => app.move_selection(if down { 1 } else { -1 }),
+        CurrentScreen::Archive => scroll_list(
+            &mut app.selected_archived_todo,
+            app.archived_todos.len(),
+            down,
+        ),
+        CurrentScreen::Trash => scroll_list(&mut app.selected_trashed_todo, app.trash.len(), down),
+        CurrentScreen::Views => scroll_list(&mut app.selected_view, app.views.len(), down),
+        CurrentScreen::Search => scroll_list(
+            &mut app.selected_search_result,
+            app.search_results.len(),
+            down,
+        ),
+        CurrentScreen::Finder => scroll_list(
+            &mut app.selected_finder_result,
+            app.finder_results.len(),
+            down,
+        ),
+        CurrentScreen::Help => handle_help_action(app, action),
+        CurrentScreen::History => handle_history_action(app, action),
+        _ => {}
+    }
+}
+
+// Returns whether the event did anything, so plain mouse movement doesn't
+// cause a redraw
+pub fn update_mouse(app: &mut App, mouse: MouseEvent) -> bool {
+    match mouse.kind {
+        MouseEventKind::Down(MouseButton::Left) => {
+            app.notice = None;
+            app.pending_keys.clear();
+            handle_left_click(app, mouse.column, mouse.row);
+        }
+        MouseEventKind::ScrollDown => handle_scroll(app, true),
+        MouseEventKind::ScrollUp => handle_scroll(app, false),
+        _ => return false,
     }
+    true
 }
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    query::{ParseError, Query},
+    sort::Sort,
+};
+
+// A named filter and sort the main list can be switched to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedView {
+    pub id: i64,
+    pub name: String,
+    pub query: String,
+    pub sort: Sort,
+}
+
+impl SavedView {
+    // Relative dates such as `due<1w` are resolved against `now`, so the query
+    // is parsed whenever the view is used rather than once when loaded
+    pub fn parse_query(&self, now: i64) -> Result<Query, ParseError> {
+        Query::parse(&self.query, now)
+    }
+}